@@ -0,0 +1,457 @@
+// Backends the prover can hand finalized transactions to, so the same code runs in tests
+// (`DryRunBroadcaster`), on regtest (`BitcoindBroadcaster::regtest`, which also mines) and on
+// testnet/mainnet (`BitcoindBroadcaster::new`, `EsploraBroadcaster`, `ElectrumBroadcaster`).
+// bitcoind can also take chains of transactions as packages (`BitcoindBroadcaster::packages`).
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+    ops::Range,
+};
+
+use bitcoin::{transaction, Address, Amount, Txid, Wtxid};
+use bitcoincore_rpc::{Client, RawTx, RpcApi};
+use serde::Deserialize;
+
+use crate::require_regtest;
+
+// Outcome of submitting a single transaction
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubmissionReport {
+    pub txid: Txid,
+    pub allowed: bool,
+    // Why the backend rejected the transaction (or the package it was part of)
+    pub reject_reason: Option<String>,
+    pub vsize: Option<u64>,
+    pub fees: Option<Amount>,
+    // Whether the transaction was actually broadcast, not just tested or recorded
+    pub submitted: bool,
+}
+
+impl SubmissionReport {
+    fn rejected(tx: &transaction::Transaction, reason: String) -> SubmissionReport {
+        SubmissionReport {
+            txid: tx.compute_txid(),
+            allowed: false,
+            reject_reason: Some(reason),
+            vsize: None,
+            fees: None,
+            submitted: false,
+        }
+    }
+
+    fn submitted(tx: &transaction::Transaction) -> SubmissionReport {
+        SubmissionReport {
+            txid: tx.compute_txid(),
+            allowed: true,
+            reject_reason: None,
+            vsize: Some(tx.vsize() as u64),
+            fees: None,
+            submitted: true,
+        }
+    }
+}
+
+pub fn print_reports(reports: &[SubmissionReport], txs: &[transaction::Transaction]) {
+    for (i, (report, tx)) in reports.iter().zip(txs).enumerate() {
+        match &report.reject_reason {
+            None => println!(
+                "Transaction #{} ({}): {}, {} vbytes, {} fees",
+                i + 1,
+                report.txid,
+                if report.submitted {
+                    "submitted"
+                } else {
+                    "accepted"
+                },
+                report.vsize.map_or("?".to_string(), |x| x.to_string()),
+                report.fees.map_or("?".to_string(), |x| x.to_string()),
+            ),
+            Some(reason) => println!(
+                "Transaction #{} ({}): rejected: {}\n{}",
+                i + 1,
+                report.txid,
+                reason,
+                tx.raw_hex()
+            ),
+        }
+    }
+}
+
+pub trait Broadcaster {
+    // Broadcasts the transactions in order, so parents must come before their children.
+    // Stops at the first rejected transaction, since its children can't be accepted either.
+    // The caller prints the reports, see `print_reports`.
+    fn broadcast(&mut self, txs: &[transaction::Transaction]) -> Vec<SubmissionReport>;
+}
+
+const EARLIER_REJECTION: &str = "not submitted: an earlier transaction was rejected";
+
+// Broadcasts `txs` one by one with `send`, which returns the rejection reason on failure
+fn broadcast_sequentially(
+    txs: &[transaction::Transaction],
+    mut send: impl FnMut(&transaction::Transaction) -> Result<(), String>,
+) -> Vec<SubmissionReport> {
+    let mut reports: Vec<SubmissionReport> = vec![];
+    for tx in txs {
+        let report = match reports.iter().any(|x| !x.allowed) {
+            true => SubmissionReport::rejected(tx, EARLIER_REJECTION.to_string()),
+            false => match send(tx) {
+                Ok(()) => SubmissionReport::submitted(tx),
+                Err(reason) => SubmissionReport::rejected(tx, reason),
+            },
+        };
+        reports.push(report);
+    }
+    reports
+}
+
+#[derive(Deserialize)]
+struct MempoolAcceptFees {
+    #[serde(with = "bitcoin::amount::serde::as_btc")]
+    base: Amount,
+}
+
+// An entry of the `testmempoolaccept` response
+#[derive(Deserialize)]
+pub struct MempoolAcceptResult {
+    txid: Txid,
+    allowed: Option<bool>,
+    #[serde(rename = "reject-reason")]
+    reject_reason: Option<String>,
+    #[serde(rename = "package-error")]
+    package_error: Option<String>,
+    vsize: Option<u64>,
+    fees: Option<MempoolAcceptFees>,
+}
+
+#[derive(Deserialize)]
+struct SubmitPackageTxResult {
+    txid: Txid,
+    vsize: Option<u64>,
+    fees: Option<MempoolAcceptFees>,
+    error: Option<String>,
+}
+
+// The `submitpackage` response
+#[derive(Deserialize)]
+pub struct SubmitPackageResult {
+    // Only returned by Bitcoin Core 28+
+    package_msg: Option<String>,
+    #[serde(rename = "tx-results")]
+    tx_results: HashMap<Wtxid, SubmitPackageTxResult>,
+}
+
+// One report per transaction from the `testmempoolaccept` results. Nothing is submitted.
+pub fn mempool_accept_reports(
+    txs: &[transaction::Transaction],
+    results: &[MempoolAcceptResult],
+) -> Vec<SubmissionReport> {
+    txs.iter()
+        .map(|tx| {
+            let txid = tx.compute_txid();
+            match results.iter().find(|x| x.txid == txid) {
+                None => SubmissionReport::rejected(tx, "missing from response".to_string()),
+                Some(result) => SubmissionReport {
+                    txid,
+                    allowed: result.allowed == Some(true),
+                    reject_reason: match result.allowed {
+                        Some(true) => None,
+                        _ => Some(
+                            result
+                                .reject_reason
+                                .clone()
+                                .or(result.package_error.clone())
+                                .unwrap_or("not fully validated".to_string()),
+                        ),
+                    },
+                    vsize: result.vsize,
+                    fees: result.fees.as_ref().map(|x| x.base),
+                    submitted: false,
+                },
+            }
+        })
+        .collect()
+}
+
+// One report per transaction from the `submitpackage` result. Transactions missing from it
+// weren't validated because of the package error.
+pub fn submit_package_reports(
+    txs: &[transaction::Transaction],
+    result: &SubmitPackageResult,
+) -> Vec<SubmissionReport> {
+    let package_error = result
+        .package_msg
+        .clone()
+        .filter(|x| x != "success")
+        .unwrap_or("not fully validated".to_string());
+    txs.iter()
+        .map(|tx| match result.tx_results.get(&tx.compute_wtxid()) {
+            None => SubmissionReport::rejected(tx, package_error.clone()),
+            Some(result) => SubmissionReport {
+                txid: result.txid,
+                allowed: result.error.is_none(),
+                reject_reason: result.error.clone(),
+                vsize: result.vsize,
+                fees: result.fees.as_ref().map(|x| x.base),
+                submitted: result.error.is_none(),
+            },
+        })
+        .collect()
+}
+
+// Cuts a chain of transactions, in order, into the packages `submitpackage` accepts: a
+// transaction with the next one if that spends it, on its own otherwise
+pub fn child_with_parent_packages(txs: &[transaction::Transaction]) -> Vec<Range<usize>> {
+    let mut packages = vec![];
+    let mut start = 0;
+    while start < txs.len() {
+        let parent = txs[start].compute_txid();
+        let end = match txs.get(start + 1) {
+            Some(child) if child.input.iter().any(|x| x.previous_output.txid == parent) => {
+                start + 2
+            }
+            _ => start + 1,
+        };
+        packages.push(start..end);
+        start = end;
+    }
+    packages
+}
+
+pub struct BitcoindBroadcaster<'a> {
+    pub rpc: &'a Client,
+    // Regtest only: mine a block to this address after every successful broadcast
+    pub miner_address: Option<Address>,
+    // Submit the transactions as packages instead of one by one
+    pub packages: bool,
+}
+
+impl<'a> BitcoindBroadcaster<'a> {
+    pub fn new(rpc: &'a Client) -> BitcoindBroadcaster<'a> {
+        BitcoindBroadcaster {
+            rpc,
+            miner_address: None,
+            packages: false,
+        }
+    }
+
+    pub fn regtest(rpc: &'a Client, miner_address: Address) -> BitcoindBroadcaster<'a> {
+        require_regtest(rpc);
+        BitcoindBroadcaster {
+            rpc,
+            miner_address: Some(miner_address),
+            packages: false,
+        }
+    }
+
+    // Tests the transactions for mempool acceptance as a package, so later transactions
+    // may spend earlier ones
+    pub fn test_mempool_accept(&self, txs: &[transaction::Transaction]) -> Vec<SubmissionReport> {
+        let hexes = txs.iter().map(|tx| tx.raw_hex()).collect::<Vec<String>>();
+        match self.rpc.call::<Vec<MempoolAcceptResult>>(
+            "testmempoolaccept",
+            &[serde_json::to_value(&hexes).unwrap()],
+        ) {
+            Err(error) => txs
+                .iter()
+                .map(|tx| SubmissionReport::rejected(tx, error.to_string()))
+                .collect(),
+            Ok(response) => mempool_accept_reports(txs, &response),
+        }
+    }
+
+    // Submits the transactions with `submitpackage`, so that e.g. a child can pay for a parent
+    // whose feerate is below the mempool minimum. Bitcoin Core only accepts child-with-parents
+    // packages (the last transaction spends all the others).
+    pub fn submit_package(&self, txs: &[transaction::Transaction]) -> Vec<SubmissionReport> {
+        let hexes = txs.iter().map(|tx| tx.raw_hex()).collect::<Vec<String>>();
+        match self
+            .rpc
+            .call::<SubmitPackageResult>("submitpackage", &[serde_json::to_value(&hexes).unwrap()])
+        {
+            Err(error) => txs
+                .iter()
+                .map(|tx| SubmissionReport::rejected(tx, error.to_string()))
+                .collect(),
+            Ok(response) => submit_package_reports(txs, &response),
+        }
+    }
+
+    // Submits a longer chain like peg-in -> handover -> peg-out pair by pair, see
+    // `child_with_parent_packages`. Stops at the first package that isn't fully accepted.
+    pub fn submit_packages(&self, txs: &[transaction::Transaction]) -> Vec<SubmissionReport> {
+        let mut reports: Vec<SubmissionReport> = vec![];
+        for package in child_with_parent_packages(txs) {
+            match reports.iter().all(|x| x.submitted) {
+                true => reports.extend(self.submit_package(&txs[package])),
+                false => reports.extend(
+                    txs[package]
+                        .iter()
+                        .map(|tx| SubmissionReport::rejected(tx, EARLIER_REJECTION.to_string())),
+                ),
+            }
+        }
+        reports
+    }
+
+    // Only broadcasts if the whole batch passes `testmempoolaccept`
+    fn test_and_send(&self, txs: &[transaction::Transaction]) -> Vec<SubmissionReport> {
+        let mut reports = self.test_mempool_accept(txs);
+        if reports.iter().any(|x| !x.allowed) {
+            println!("Mempool acceptance test failed, nothing was submitted.");
+            return reports;
+        }
+
+        let submitted = broadcast_sequentially(txs, |tx| {
+            self.rpc
+                .send_raw_transaction(tx.raw_hex())
+                .map(|_| ())
+                .map_err(|error| error.to_string())
+        });
+        for (report, submitted) in reports.iter_mut().zip(submitted) {
+            report.allowed = submitted.allowed;
+            report.reject_reason = submitted.reject_reason;
+            report.submitted = submitted.submitted;
+        }
+        reports
+    }
+
+    fn mine(&self) {
+        if let Some(miner_address) = &self.miner_address {
+            println!(
+                "Mined new block: {:#?}",
+                self.rpc.generate_to_address(1, miner_address).unwrap()
+            );
+        }
+    }
+}
+
+impl Broadcaster for BitcoindBroadcaster<'_> {
+    // Packages skip `testmempoolaccept`, which doesn't take a child paying for its parent into
+    // account. Nothing is mined after a failed submission, so the rejected transactions can be
+    // fixed and broadcast again on top of the same chain.
+    fn broadcast(&mut self, txs: &[transaction::Transaction]) -> Vec<SubmissionReport> {
+        let reports = match self.packages {
+            true => self.submit_packages(txs),
+            false => self.test_and_send(txs),
+        };
+        if reports.iter().all(|x| x.submitted) {
+            self.mine();
+        }
+        reports
+    }
+}
+
+// Esplora REST API (e.g. https://blockstream.info/api or https://mempool.space/api)
+pub struct EsploraBroadcaster {
+    pub base_url: String,
+    client: reqwest::blocking::Client,
+}
+
+impl EsploraBroadcaster {
+    pub fn new(base_url: &str) -> EsploraBroadcaster {
+        EsploraBroadcaster {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: reqwest::blocking::Client::new(),
+        }
+    }
+}
+
+impl Broadcaster for EsploraBroadcaster {
+    fn broadcast(&mut self, txs: &[transaction::Transaction]) -> Vec<SubmissionReport> {
+        broadcast_sequentially(txs, |tx| {
+            let response = self
+                .client
+                .post(format!("{}/tx", self.base_url))
+                .body(tx.raw_hex())
+                .send()
+                .map_err(|error| error.to_string())?;
+            let status = response.status();
+            let body = response.text().map_err(|error| error.to_string())?;
+            match status.is_success() {
+                true => Ok(()),
+                false => Err(format!("{status}: {body}")),
+            }
+        })
+    }
+}
+
+// Electrum protocol over plain TCP (newline-delimited JSON-RPC)
+pub struct ElectrumBroadcaster {
+    // host:port
+    pub server: String,
+}
+
+impl ElectrumBroadcaster {
+    pub fn new(server: &str) -> ElectrumBroadcaster {
+        ElectrumBroadcaster {
+            server: server.to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ElectrumResponse {
+    result: Option<serde_json::Value>,
+    error: Option<serde_json::Value>,
+}
+
+impl Broadcaster for ElectrumBroadcaster {
+    fn broadcast(&mut self, txs: &[transaction::Transaction]) -> Vec<SubmissionReport> {
+        let stream = match TcpStream::connect(&self.server) {
+            Ok(stream) => stream,
+            Err(error) => {
+                return txs
+                    .iter()
+                    .map(|tx| SubmissionReport::rejected(tx, error.to_string()))
+                    .collect()
+            }
+        };
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+
+        let mut id = 0;
+        broadcast_sequentially(txs, |tx| {
+            id += 1;
+            let request = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "blockchain.transaction.broadcast",
+                "params": [tx.raw_hex()],
+            });
+            writeln!(writer, "{request}").map_err(|error| error.to_string())?;
+
+            let mut line = String::new();
+            reader
+                .read_line(&mut line)
+                .map_err(|error| error.to_string())?;
+            let response: ElectrumResponse =
+                serde_json::from_str(&line).map_err(|error| error.to_string())?;
+            match (response.result, response.error) {
+                (Some(_), None) => Ok(()),
+                (_, Some(error)) => Err(error.to_string()),
+                (None, None) => Err("empty response".to_string()),
+            }
+        })
+    }
+}
+
+// Only records the transactions, e.g. for tests or to inspect what would be broadcast
+#[derive(Default)]
+pub struct DryRunBroadcaster {
+    pub transactions: Vec<transaction::Transaction>,
+}
+
+impl Broadcaster for DryRunBroadcaster {
+    fn broadcast(&mut self, txs: &[transaction::Transaction]) -> Vec<SubmissionReport> {
+        self.transactions.extend_from_slice(txs);
+        txs.iter()
+            .map(|tx| SubmissionReport {
+                submitted: false,
+                ..SubmissionReport::submitted(tx)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,398 @@
+use std::{path::PathBuf, str::FromStr};
+
+use axelar_btc::{
+    config::{Config, ConfigError},
+    deposit_address::RefundLeaf,
+    validator::is_signable_sighash_type,
+};
+use bitcoin::{
+    address::NetworkUnchecked, bip32::Xpriv, hex::FromHex, Address, Amount, Network, OutPoint,
+    PrivateKey, TapSighashType, Txid, XOnlyPublicKey,
+};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+#[derive(Parser)]
+#[command(about = "Operates the Axelar committee's Bitcoin multisig")]
+pub struct Cli {
+    #[arg(
+        long,
+        global = true,
+        help = "TOML configuration file [default: axelar-btc.toml, if it exists]"
+    )]
+    pub config: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        help = "File with the state kept between invocations"
+    )]
+    pub state: Option<PathBuf>,
+    #[arg(long, global = true)]
+    pub network: Option<Network>,
+    #[arg(
+        long,
+        global = true,
+        help = "Bitcoin Core RPC URL [default: localhost with the network's default port]"
+    )]
+    pub rpc_url: Option<String>,
+    #[arg(long, global = true, help = "Bitcoin Core wallet used for peg-ins")]
+    pub wallet: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        help = "Bitcoin Core data directory, to authenticate with its cookie file"
+    )]
+    pub datadir: Option<PathBuf>,
+    #[arg(long, global = true, conflicts_with_all = ["rpc_user", "rpc_pass"])]
+    pub rpc_cookie: Option<PathBuf>,
+    #[arg(long, global = true, requires = "rpc_pass")]
+    pub rpc_user: Option<String>,
+    #[arg(long, global = true, requires = "rpc_user")]
+    pub rpc_pass: Option<String>,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(about = "Runs the whole peg-in, handover & peg-out flow on regtest")]
+    Demo {
+        #[arg(help = "Path to the .bitcoin directory [default: --datadir]")]
+        bitcoin_dir: Option<PathBuf>,
+    },
+    #[command(about = "Sets the committee controlling the peg-ins")]
+    SetupCommittee {
+        #[arg(
+            long,
+            help = "Committee JSON file [default: the configured committee source]"
+        )]
+        from_file: Option<PathBuf>,
+        #[arg(long, help = "Also write the committee to this file")]
+        out: Option<PathBuf>,
+    },
+    #[command(
+        about = "Deposits BTC to the committee, from the RPC wallet or from the given UTXOs"
+    )]
+    PegIn {
+        #[arg(long, help = "e.g. \"0.5 BTC\" or \"10000 sat\"")]
+        amount: Amount,
+        #[arg(long, help = "<chain>:<address>[:<payload>]")]
+        destination: String,
+        #[arg(long, help = "Fee rate in sats/vbyte [default: from the fee policy]")]
+        fee_rate: Option<u64>,
+        #[arg(
+            long = "utxo",
+            help = "<txid>:<vout> to spend, instead of the RPC wallet's UTXOs (repeatable)"
+        )]
+        utxos: Vec<OutPoint>,
+        #[arg(
+            long,
+            help = "Address receiving the change [default: a new change address of the RPC wallet]"
+        )]
+        change_address: Option<Address<NetworkUnchecked>>,
+        #[arg(long, requires = "utxos", help = "WIF key of the P2WPKH/P2TR UTXOs")]
+        key: Option<PrivateKey>,
+        #[arg(
+            long,
+            conflicts_with = "key",
+            help = "Write an unsigned PSBT instead of signing"
+        )]
+        psbt_out: Option<PathBuf>,
+        #[command(flatten)]
+        refund: RefundArgs,
+    },
+    #[command(about = "Prints the committee's deposit address for a destination")]
+    DepositAddress {
+        #[arg(long, help = "<chain>:<address>[:<payload>]")]
+        destination: String,
+        #[command(flatten)]
+        refund: RefundArgs,
+    },
+    #[command(about = "Takes back a refundable deposit that was never processed")]
+    Refund {
+        #[arg(long, help = "<txid>:<vout> of the deposit")]
+        utxo: OutPoint,
+        #[arg(long, help = "WIF refund key")]
+        key: PrivateKey,
+        #[arg(long, help = "Address receiving the refund")]
+        to: Address<NetworkUnchecked>,
+        #[arg(long, help = "Fee rate in sats/vbyte [default: from the fee policy]")]
+        fee_rate: Option<u64>,
+    },
+    #[command(about = "Adds the outputs of a transaction paying to deposit addresses")]
+    RegisterDeposit {
+        #[arg(help = "Txid of the deposit, looked up with the RPC")]
+        txid: Txid,
+    },
+    #[command(about = "Moves all the committee's UTXOs to a new committee")]
+    Handover {
+        #[arg(long)]
+        to_committee: PathBuf,
+        #[arg(long, default_value_t = 2)]
+        max_outputs: usize,
+        #[arg(long, default_value_t = 100000)]
+        max_tx_size: usize,
+        #[arg(long, help = "Miner fee in sats [default: from the fee policy]")]
+        fee: Option<u64>,
+        #[arg(long, default_value_t = TapSighashType::Default, value_parser = parse_sighash_type)]
+        sighash_type: TapSighashType,
+    },
+    #[command(about = "Pays out BTC from the committee")]
+    PegOut {
+        #[arg(long, help = "CSV file with <address>,<amount in sats> lines")]
+        payouts: PathBuf,
+        #[arg(long, help = "Miner fee in sats/vbyte [default: from the fee policy]")]
+        fee_rate: Option<u64>,
+        #[arg(long, default_value_t = TapSighashType::Default, value_parser = parse_sighash_type)]
+        sighash_type: TapSighashType,
+    },
+    #[command(about = "Queues a withdrawal request for the next peg-out batch")]
+    RequestPegOut {
+        #[arg(long, help = "Source chain transaction of the request")]
+        id: String,
+        #[arg(long, help = "e.g. \"0.5 BTC\" or \"10000 sat\"")]
+        amount: Amount,
+        #[arg(long)]
+        address: Address<NetworkUnchecked>,
+        #[arg(long, help = "UNIX time by which the peg-out should be broadcast")]
+        deadline: Option<u64>,
+    },
+    #[command(about = "Pays the queued withdrawal requests once a batch is due")]
+    BatchPegOuts {
+        #[arg(long, help = "Cut a batch even if none is due yet")]
+        force: bool,
+        #[arg(long, help = "Miner fee in sats/vbyte [default: from the fee policy]")]
+        fee_rate: Option<u64>,
+        #[arg(long, default_value_t = TapSighashType::Default, value_parser = parse_sighash_type)]
+        sighash_type: TapSighashType,
+    },
+    #[command(
+        about = "Tells whether a withdrawal request is queued or parked, or which peg-out pays it"
+    )]
+    PegOutStatus {
+        #[arg(help = "Source chain transaction of the request")]
+        id: String,
+    },
+    #[command(about = "Signs the pending transactions as a validator")]
+    Sign {
+        #[arg(long, help = "Operator address of the validator")]
+        validator: String,
+        #[arg(
+            long,
+            help = "Validator's key [default: the demo key of its committee index]"
+        )]
+        key: Option<Xpriv>,
+        #[arg(
+            long,
+            conflicts_with = "key",
+            help = "URL of the validator's remote signer"
+        )]
+        signer_url: Option<String>,
+        #[arg(
+            long,
+            requires = "signer_url",
+            help = "Key of the validator in the remote signer [default: its operator address]"
+        )]
+        key_id: Option<String>,
+        #[arg(
+            long,
+            conflicts_with = "signer_url",
+            value_parser = parse_aux_seed,
+            help = "Hex seed of deterministic BIP340 auxiliary randomness, for reproducible signatures"
+        )]
+        aux_seed: Option<[u8; 32]>,
+    },
+    #[command(about = "Writes a PSBT of every pending transaction to <txid>.psbt")]
+    ExportPsbt {
+        #[arg(long, default_value = ".")]
+        out_dir: PathBuf,
+    },
+    #[command(about = "Adds a validator's signatures to a PSBT file, like its wallet would")]
+    SignPsbt {
+        file: PathBuf,
+        #[arg(long)]
+        key: Xpriv,
+        #[arg(long, value_parser = parse_aux_seed, help = "See `sign --aux-seed`")]
+        aux_seed: Option<[u8; 32]>,
+    },
+    #[command(about = "Combines signed PSBTs of a pending transaction and keeps their signatures")]
+    ImportPsbt {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    #[command(
+        about = "Finalizes the witnesses of the pending transactions with enough signatures"
+    )]
+    Finalize,
+    #[command(about = "Writes the evidence of validators signing conflicting transactions")]
+    ExportEvidence {
+        #[arg(long, help = "File to write [default: stdout]")]
+        out: Option<PathBuf>,
+    },
+    #[command(about = "Broadcasts the finalized transactions")]
+    Broadcast {
+        #[arg(long, value_enum, default_value_t = Backend::Bitcoind)]
+        backend: Backend,
+        #[arg(long, help = "Esplora base URL or Electrum host:port")]
+        url: Option<String>,
+        #[arg(
+            long,
+            help = "Regtest only: mine a block to this address after broadcasting"
+        )]
+        mine_to: Option<Address<NetworkUnchecked>>,
+        #[arg(
+            long,
+            help = "bitcoind only: submit parent/child pairs as packages, so children can pay for their parents"
+        )]
+        packages: bool,
+    },
+    #[command(about = "Checks the committee's UTXOs with bitcoind and writes a reserves report")]
+    Reserves {
+        #[arg(long, default_value = "reserves.json")]
+        out: PathBuf,
+        #[arg(long, help = "Wrapped BTC outstanding on Axelar, in sats")]
+        wrapped_supply: Option<u64>,
+    },
+    #[command(about = "Adds a committee member's signature to a reserves report")]
+    SignReserves {
+        file: PathBuf,
+        #[arg(long, help = "Operator address of the validator")]
+        validator: String,
+        #[arg(
+            long,
+            help = "Validator's key [default: the demo key of its committee index]"
+        )]
+        key: Option<Xpriv>,
+        #[arg(
+            long,
+            conflicts_with = "key",
+            help = "URL of the validator's remote signer"
+        )]
+        signer_url: Option<String>,
+        #[arg(
+            long,
+            requires = "signer_url",
+            help = "Key of the validator in the remote signer [default: its operator address]"
+        )]
+        key_id: Option<String>,
+    },
+    #[command(about = "Checks the committee signatures of a reserves report, offline")]
+    VerifyReserves {
+        file: PathBuf,
+        #[arg(long, help = "Committee file the report's committee has to match")]
+        committee: Option<PathBuf>,
+    },
+    #[command(about = "Prints the ledger's balances and reconciles them with the UTXOs")]
+    CheckLedger,
+    #[command(about = "Writes the ledger's entries for accounting")]
+    ExportLedger {
+        #[arg(long, value_enum, default_value_t = LedgerFormat::Csv)]
+        format: LedgerFormat,
+        #[arg(long, help = "File to write [default: stdout]")]
+        out: Option<PathBuf>,
+    },
+    #[command(about = "Prints the output descriptors and address of a committee")]
+    Descriptor {
+        #[arg(help = "Committee descriptor to decode [default: the current committee's]")]
+        descriptor: Option<String>,
+    },
+    #[command(about = "Imports the committee's scripts into the watch-only wallet")]
+    WatchCommittee {
+        #[arg(
+            long,
+            help = "Unix time to rescan the chain from, if the scripts have older UTXOs [default: now]"
+        )]
+        rescan_from: Option<u64>,
+    },
+    #[command(about = "Replaces the committee's UTXOs with the watch-only wallet's")]
+    SyncUtxos,
+    #[command(about = "Decodes a transaction and reports its signing status")]
+    InspectTx {
+        #[arg(help = "Txid of a transaction in the state, or a raw transaction in hex")]
+        tx: String,
+    },
+}
+
+// Refund leaf of a deposit address
+#[derive(Args)]
+pub struct RefundArgs {
+    #[arg(
+        long,
+        help = "Key (x-only, hex) allowed to take the deposit back if it isn't processed"
+    )]
+    pub refund_key: Option<XOnlyPublicKey>,
+    // About 30 days
+    #[arg(
+        long,
+        default_value_t = 4320,
+        help = "Blocks after which the refund key can spend the deposit"
+    )]
+    pub refund_after: u16,
+}
+
+impl RefundArgs {
+    pub fn leaf(&self) -> Option<RefundLeaf> {
+        self.refund_key.map(|key| RefundLeaf {
+            key,
+            delay: self.refund_after,
+        })
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Backend {
+    Bitcoind,
+    Esplora,
+    Electrum,
+    DryRun,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LedgerFormat {
+    Csv,
+    Json,
+}
+
+fn parse_aux_seed(seed: &str) -> Result<[u8; 32], String> {
+    <[u8; 32]>::from_hex(seed).map_err(|error| format!("expected 32 bytes in hex: {error}"))
+}
+
+// Only the sighash types that can commit to all the outputs, see `is_signable_sighash_type`
+fn parse_sighash_type(sighash_type: &str) -> Result<TapSighashType, String> {
+    let sighash_type = TapSighashType::from_str(sighash_type).map_err(|error| error.to_string())?;
+    if !is_signable_sighash_type(sighash_type) {
+        return Err(format!(
+            "{sighash_type} doesn't commit to all the outputs, use SIGHASH_DEFAULT, SIGHASH_ALL, SIGHASH_ALL|SIGHASH_ANYONECANPAY or SIGHASH_SINGLE|SIGHASH_ANYONECANPAY"
+        ));
+    }
+    Ok(sighash_type)
+}
+
+impl Cli {
+    // Configuration file & environment, overridden by the command-line flags
+    pub fn config(&self) -> Result<Config, ConfigError> {
+        let mut config = Config::load(self.config.as_deref())?;
+        if let Some(network) = self.network {
+            config.network = network;
+        }
+        if let Some(state) = &self.state {
+            config.state = state.clone();
+        }
+        if let Some(wallet) = &self.wallet {
+            config.wallet = Some(wallet.clone());
+        }
+        if let Some(url) = &self.rpc_url {
+            config.rpc.url = Some(url.clone());
+        }
+        if let Some(datadir) = &self.datadir {
+            config.rpc.datadir = Some(datadir.clone());
+        }
+        if let Some(cookie) = &self.rpc_cookie {
+            config.set_rpc_cookie(cookie.clone());
+        }
+        if let (Some(user), Some(pass)) = (&self.rpc_user, &self.rpc_pass) {
+            config.rpc.user = Some(user.clone());
+            config.rpc.pass = Some(pass.clone());
+        }
+        Ok(config)
+    }
+}
@@ -0,0 +1,1286 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axelar_btc::{
+    broadcaster::{
+        print_reports, BitcoindBroadcaster, Broadcaster, DryRunBroadcaster, ElectrumBroadcaster,
+        EsploraBroadcaster,
+    },
+    config::{CommitteeSource, Config},
+    deposit::{DepositBuilder, DepositSigner, KeySigner, RefundBuilder, WalletSigner},
+    deposit_address::{DepositAddress, RefundLeaf},
+    descriptor::CommitteeDescriptor,
+    evidence::EvidenceLog,
+    get_multisig_setup, get_private_key,
+    interpreter::verify_transaction,
+    multisig_prover::{HandoverParams, MultisigProver, PayoutError, Payouts},
+    peg_out_queue::{payouts, PegOutRequest},
+    psbt::{combine_psbts, finalize_psbt, PsbtError},
+    reserves::{ReservesReport, SignedReport, UtxoStatus},
+    signer::{RemoteSigner, SeededSigner, Signer},
+    state::{Committee, FinalizedTx, PendingTx, State, TxKind},
+    validator::Validator,
+    verify_signatures,
+    watch_wallet::{self, reconcile},
+    Utxo,
+};
+use bitcoin::{
+    address::NetworkUnchecked, bip32::Xpriv, consensus::encode::deserialize_hex, key::Secp256k1,
+    psbt::Psbt, transaction, Address, Amount, FeeRate, Network, OutPoint, PrivateKey, ScriptBuf,
+    TapSighashType, TxOut, Txid,
+};
+
+use bitcoincore_rpc::{Client, RpcApi};
+
+use crate::cli::{Backend, Cli, Command, LedgerFormat};
+
+const DUST_LIMIT: Amount = Amount::from_sat(330); // P2TR outputs
+
+pub fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    std::process::exit(1);
+}
+
+fn load_committee(path: &Path) -> Committee {
+    Committee::load(path).unwrap_or_else(|error| fail(&error.to_string()))
+}
+
+fn committee(state: &State) -> &Committee {
+    state
+        .committee
+        .as_ref()
+        .unwrap_or_else(|| fail("No committee set up yet, run `setup-committee` first"))
+}
+
+pub fn run(cli: &Cli, config: &Config, state: &mut State) {
+    // With a watch-only wallet, bitcoind decides which UTXOs new transactions can spend
+    if config.watch.wallet.is_some()
+        && matches!(
+            cli.command,
+            Command::Handover { .. } | Command::PegOut { .. } | Command::BatchPegOuts { .. }
+        )
+    {
+        sync_utxos(config, state);
+    }
+
+    match &cli.command {
+        Command::Demo { .. } => unreachable!("The demo doesn't use the state"),
+        Command::SetupCommittee { from_file, out } => {
+            let source = match from_file {
+                Some(path) => CommitteeSource::File { path: path.clone() },
+                None => config.committee.clone(),
+            };
+            setup_committee(state, &source, out.as_deref())
+        }
+        Command::PegIn {
+            amount,
+            destination,
+            fee_rate,
+            utxos,
+            change_address,
+            key,
+            psbt_out,
+            refund,
+        } => {
+            let mut builder = deposit_builder(
+                config,
+                state,
+                *amount,
+                destination,
+                fee_rate_or_default(config, *fee_rate),
+                utxos,
+                change_address.clone(),
+            );
+            // Refundable deposits go to a deposit address with the refund leaf
+            if let Some(leaf) = refund.leaf() {
+                builder.script_pubkey =
+                    new_deposit_address(state, destination, Some(leaf)).script_pubkey;
+            }
+            peg_in(config, state, builder, *key, psbt_out.as_deref())
+        }
+        Command::DepositAddress {
+            destination,
+            refund,
+        } => {
+            let deposit = new_deposit_address(state, destination, refund.leaf());
+            println!("{}", deposit.to_address(state.network));
+        }
+        Command::Refund {
+            utxo,
+            key,
+            to,
+            fee_rate,
+        } => refund(
+            config,
+            state,
+            utxo,
+            key,
+            to.clone(),
+            fee_rate_or_default(config, *fee_rate),
+        ),
+        Command::RegisterDeposit { txid } => register_deposit(config, state, txid),
+        Command::Handover {
+            to_committee,
+            max_outputs,
+            max_tx_size,
+            fee,
+            sighash_type,
+        } => handover(
+            state,
+            to_committee,
+            *max_outputs,
+            *max_tx_size,
+            Amount::from_sat(fee.unwrap_or(config.fees.handover_fee)),
+            *sighash_type,
+        ),
+        Command::PegOut {
+            payouts,
+            fee_rate,
+            sighash_type,
+        } => peg_out(
+            config,
+            state,
+            payouts,
+            Amount::from_sat(fee_rate_or_default(config, *fee_rate)),
+            *sighash_type,
+        ),
+        Command::RequestPegOut {
+            id,
+            amount,
+            address,
+            deadline,
+        } => request_peg_out(config, state, id, *amount, address.clone(), *deadline),
+        Command::BatchPegOuts {
+            force,
+            fee_rate,
+            sighash_type,
+        } => batch_peg_outs(
+            config,
+            state,
+            *force,
+            Amount::from_sat(fee_rate_or_default(config, *fee_rate)),
+            *sighash_type,
+        ),
+        Command::PegOutStatus { id } => peg_out_status(state, id),
+        Command::Sign {
+            validator,
+            key,
+            signer_url,
+            key_id,
+            aux_seed,
+        } => {
+            let signer = validator_signer(validator, *key, signer_url, key_id, *aux_seed);
+            sign(state, validator, signer, *aux_seed)
+        }
+        Command::ExportPsbt { out_dir } => export_psbt(state, out_dir),
+        Command::SignPsbt {
+            file,
+            key,
+            aux_seed,
+        } => sign_psbt(file, key, *aux_seed),
+        Command::ImportPsbt { files } => import_psbt(state, files),
+        Command::Finalize => finalize(state),
+        Command::ExportEvidence { out } => export_evidence(state, out.as_deref()),
+        Command::Broadcast {
+            backend,
+            url,
+            mine_to,
+            packages,
+        } => broadcast(
+            config,
+            state,
+            *backend,
+            url.as_deref(),
+            mine_to.clone(),
+            *packages,
+        ),
+        Command::InspectTx { tx } => inspect_tx(state, tx),
+        Command::Reserves {
+            out,
+            wrapped_supply,
+        } => reserves(config, state, out, wrapped_supply.map(Amount::from_sat)),
+        Command::SignReserves {
+            file,
+            validator,
+            key,
+            signer_url,
+            key_id,
+        } => sign_reserves(
+            file,
+            validator,
+            validator_signer(validator, *key, signer_url, key_id, None),
+        ),
+        Command::VerifyReserves { file, committee } => verify_reserves(file, committee.as_deref()),
+        Command::CheckLedger => check_ledger(state),
+        Command::WatchCommittee { rescan_from } => watch_committee(config, state, *rescan_from),
+        Command::SyncUtxos => sync_utxos(config, state),
+        Command::Descriptor { descriptor } => print_descriptor(state, descriptor.as_deref()),
+        Command::ExportLedger { format, out } => export_ledger(state, *format, out.as_deref()),
+    }
+}
+
+fn setup_committee(state: &mut State, source: &CommitteeSource, out: Option<&Path>) {
+    if state.committee.is_some() && !state.utxos.is_empty() {
+        fail("The current committee still holds UTXOs, use `handover` to replace it");
+    }
+
+    let secp = Secp256k1::new();
+    let committee = match source {
+        CommitteeSource::File { path } => load_committee(path),
+        CommitteeSource::Axelarscan => {
+            let (mut validators, threshold) = get_multisig_setup();
+            // Validators don't publish Bitcoin keys yet, so use the demo keys (see `sign`)
+            for (i, validator) in validators.iter_mut().enumerate() {
+                validator.signer = Some(Arc::new(demo_key(i, state.network)));
+            }
+            Committee::from_validators(&validators, threshold, &secp)
+        }
+    };
+
+    let (_, script_pubkey) = committee.scripts(&secp);
+    println!(
+        "Committee of {} validators with threshold {}: {}",
+        committee.members.len(),
+        committee.threshold,
+        Address::from_script(&script_pubkey, state.network).unwrap()
+    );
+    if let Some(out) = out {
+        committee.save(out);
+    }
+    state.committee = Some(committee);
+}
+
+// Fee rate in sats/vbyte, refusing rates above the policy's maximum
+fn fee_rate_or_default(config: &Config, fee_rate: Option<u64>) -> u64 {
+    let fee_rate = fee_rate.unwrap_or(config.fees.fee_rate);
+    if fee_rate > config.fees.max_fee_rate {
+        fail(&format!(
+            "The fee rate is above the maximum of {} sats/vbyte",
+            config.fees.max_fee_rate
+        ));
+    }
+    fee_rate
+}
+
+fn unspent_output(rpc: &Client, outpoint: &OutPoint) -> Utxo {
+    let txout = rpc
+        .get_tx_out(&outpoint.txid, outpoint.vout, Some(true))
+        .unwrap_or_else(|error| fail(&format!("Could not look up {outpoint}: {error}")))
+        .unwrap_or_else(|| fail(&format!("{outpoint} is spent or doesn't exist")));
+    Utxo {
+        outpoint: *outpoint,
+        txout: TxOut {
+            value: txout.value,
+            script_pubkey: ScriptBuf::from(txout.script_pub_key.hex),
+        },
+    }
+}
+
+// Deposit spending the given UTXOs, or the RPC wallet's if there are none
+fn deposit_builder(
+    config: &Config,
+    state: &State,
+    amount: Amount,
+    destination: &str,
+    fee_rate: u64,
+    outpoints: &[OutPoint],
+    change_address: Option<Address<NetworkUnchecked>>,
+) -> DepositBuilder {
+    let secp = Secp256k1::new();
+    let (_, script_pubkey) = committee(state).scripts(&secp);
+    let rpc = config.rpc_client();
+
+    let utxos = if outpoints.is_empty() {
+        rpc.list_unspent(Some(1), None, None, Some(false), None)
+            .unwrap_or_else(|error| fail(&format!("Could not list the wallet's UTXOs: {error}")))
+            .into_iter()
+            .filter(|x| x.spendable)
+            .map(|x| Utxo {
+                outpoint: OutPoint::new(x.txid, x.vout),
+                txout: TxOut {
+                    value: x.amount,
+                    script_pubkey: x.script_pub_key,
+                },
+            })
+            .collect()
+    } else {
+        outpoints.iter().map(|x| unspent_output(&rpc, x)).collect()
+    };
+
+    let change_address = match change_address {
+        Some(address) => address,
+        None if outpoints.is_empty() => rpc
+            .get_raw_change_address(None)
+            .unwrap_or_else(|error| fail(&format!("Could not get a change address: {error}"))),
+        None => fail("--change-address is needed with --utxo"),
+    };
+    let change_address = change_address
+        .require_network(state.network)
+        .unwrap_or_else(|error| fail(&format!("Invalid change address: {error}")));
+
+    DepositBuilder {
+        amount,
+        script_pubkey,
+        destination: destination.to_string(),
+        change_script_pubkey: change_address.script_pubkey(),
+        fee_rate: FeeRate::from_sat_per_vb(fee_rate).expect("Fee rate overflow"),
+        utxos,
+    }
+}
+
+// Signs the deposit with the key, or the RPC wallet if there is none. With `psbt_out`, writes an
+// unsigned PSBT instead, to be signed and broadcast by the user's wallet.
+fn peg_in(
+    config: &Config,
+    state: &mut State,
+    builder: DepositBuilder,
+    key: Option<PrivateKey>,
+    psbt_out: Option<&Path>,
+) {
+    let secp = Secp256k1::new();
+    let deposit = builder
+        .build()
+        .unwrap_or_else(|error| fail(&format!("Could not build the deposit: {error}")));
+    println!(
+        "Deposit {}:{} paying {} in fees",
+        deposit.tx.compute_txid(),
+        deposit.deposit_vout,
+        deposit.fee
+    );
+
+    if let Some(path) = psbt_out {
+        fs::write(path, deposit.psbt().to_string())
+            .unwrap_or_else(|error| fail(&format!("Could not write {}: {error}", path.display())));
+        println!("Unsigned PSBT written to {}", path.display());
+        return;
+    }
+
+    let tx = match key {
+        Some(key) => KeySigner { key }.sign_deposit(&deposit, &secp),
+        None => WalletSigner {
+            rpc: &config.rpc_client(),
+        }
+        .sign_deposit(&deposit, &secp),
+    }
+    .unwrap_or_else(|error| fail(&format!("Could not sign the deposit: {error}")));
+
+    state.add_utxos(&tx, &builder.script_pubkey);
+    let txid = tx.compute_txid();
+    for utxo in state.utxos.iter().filter(|x| x.outpoint.txid == txid) {
+        state.ledger.record_deposit(utxo, now());
+    }
+    state.finalized.push(FinalizedTx {
+        kind: TxKind::PegIn,
+        tx,
+        prevouts: deposit.prevouts,
+    });
+}
+
+// Deposit address of the destination, registered so that its deposits can be spent
+fn new_deposit_address(
+    state: &mut State,
+    destination: &str,
+    refund: Option<RefundLeaf>,
+) -> DepositAddress {
+    let secp = Secp256k1::new();
+    let mut deposit = DepositAddress::from_destination(committee(state), destination, &secp)
+        .unwrap_or_else(|| fail("The destination must be <chain>:<address>[:<payload>]"));
+    if let Some(refund) = refund {
+        deposit = deposit.with_refund(refund, &secp);
+    }
+    if !state.deposit_addresses.contains(&deposit) {
+        state.deposit_addresses.push(deposit.clone());
+    }
+    deposit
+}
+
+// Queues the refund for `broadcast`, which only succeeds once the refund delay has passed. The
+// deposit is no longer spent by the committee.
+fn refund(
+    config: &Config,
+    state: &mut State,
+    outpoint: &OutPoint,
+    key: &PrivateKey,
+    to: Address<NetworkUnchecked>,
+    fee_rate: u64,
+) {
+    let secp = Secp256k1::new();
+    let utxo = unspent_output(&config.rpc_client(), outpoint);
+    let deposit = state
+        .deposit_addresses
+        .iter()
+        .find(|x| x.script_pubkey == utxo.txout.script_pubkey)
+        .unwrap_or_else(|| {
+            fail(&format!(
+                "{outpoint} doesn't pay to a known deposit address"
+            ))
+        })
+        .clone();
+    let to = to
+        .require_network(state.network)
+        .unwrap_or_else(|error| fail(&format!("Invalid address: {error}")));
+
+    let refund = RefundBuilder {
+        deposit,
+        utxo,
+        script_pubkey: to.script_pubkey(),
+        fee_rate: FeeRate::from_sat_per_vb(fee_rate).expect("Fee rate overflow"),
+    }
+    .build(&secp)
+    .unwrap_or_else(|error| fail(&format!("Could not build the refund: {error}")));
+    let tx = refund
+        .sign(key, &secp)
+        .unwrap_or_else(|error| fail(&format!("Could not sign the refund: {error}")));
+    println!(
+        "Refund {} paying {} in fees, valid {} blocks after the deposit confirmed",
+        tx.compute_txid(),
+        refund.fee,
+        refund.leaf.delay
+    );
+
+    state.utxos.retain(|x| x.outpoint != *outpoint);
+    state
+        .ledger
+        .record_refund(&tx, std::slice::from_ref(&refund.prevout), now());
+    state.finalized.push(FinalizedTx {
+        kind: TxKind::Refund,
+        tx,
+        prevouts: vec![refund.prevout],
+    });
+}
+
+fn register_deposit(config: &Config, state: &mut State, txid: &Txid) {
+    let tx = config
+        .rpc_client()
+        .get_raw_transaction(txid, None)
+        .unwrap_or_else(|error| fail(&format!("Could not get {txid}: {error}")));
+    let deposits = state.add_deposits(&tx);
+    if deposits.is_empty() {
+        fail(&format!("{txid} doesn't pay to a known deposit address"));
+    }
+    for (utxo, deposit) in deposits {
+        state.ledger.record_deposit(&utxo, now());
+        println!(
+            "Deposit {} of {} to {}:{}",
+            utxo.outpoint, utxo.txout.value, deposit.chain, deposit.address
+        );
+    }
+}
+
+fn handover(
+    state: &mut State,
+    to_committee: &Path,
+    max_outputs: usize,
+    max_tx_size: usize,
+    fee: Amount,
+    sighash_type: TapSighashType,
+) {
+    let secp = Secp256k1::new();
+    let old_committee = committee(state).clone();
+    let new_committee = load_committee(to_committee);
+    if state.utxos.is_empty() {
+        fail("The committee has no UTXOs to hand over");
+    }
+
+    let (_, new_script_pubkey) = new_committee.scripts(&secp);
+    let multisig_prover = MultisigProver {
+        available_utxos: state.utxos.clone(),
+    };
+    let pending = multisig_prover
+        .create_handover_psbts(
+            HandoverParams {
+                max_output_no: max_outputs,
+                max_tx_size,
+                miner_fee: fee,
+                dust_limit: DUST_LIMIT,
+            },
+            &old_committee,
+            &new_script_pubkey,
+            sighash_type,
+            &secp,
+        )
+        .unwrap_or_else(|error| fail(&format!("Can't hand over: {error}")))
+        .iter()
+        .map(|psbt| {
+            let mut pending = PendingTx::from_psbt(TxKind::Handover, psbt, old_committee.clone());
+            pending.deposits = state.spent_deposits(&pending.prevouts);
+            pending
+        })
+        .collect::<Vec<_>>();
+
+    state.utxos.clear();
+    for handover in &pending {
+        println!("Handover {}", handover.tx.compute_txid());
+        state.add_utxos(&handover.tx, &new_script_pubkey);
+        state
+            .ledger
+            .record_handover(&handover.tx, &handover.prevouts, now());
+    }
+    state.pending.extend(pending);
+    state.committee = Some(new_committee);
+}
+
+fn parse_payouts(path: &Path, network: Network) -> Vec<(Amount, Address)> {
+    let csv = fs::read_to_string(path)
+        .unwrap_or_else(|error| fail(&format!("Could not read {}: {error}", path.display())));
+
+    csv.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with("address"))
+        .map(|(i, line)| {
+            let invalid = |error: String| fail(&format!("Line {}: {error}", i + 1));
+            let Some((address, amount)) = line.split_once(',') else {
+                invalid("expected <address>,<amount in sats>".to_string())
+            };
+            let address = Address::<NetworkUnchecked>::from_str(address.trim())
+                .map_err(|error| error.to_string())
+                .and_then(|x| {
+                    x.require_network(network)
+                        .map_err(|error| error.to_string())
+                })
+                .unwrap_or_else(|error| invalid(error));
+            let amount = amount
+                .trim()
+                .parse::<u64>()
+                .map_err(|error| error.to_string())
+                .unwrap_or_else(|error| invalid(error));
+            (Amount::from_sat(amount), address)
+        })
+        .collect()
+}
+
+fn peg_out(
+    config: &Config,
+    state: &mut State,
+    payouts: &Path,
+    fee_rate: Amount,
+    sighash_type: TapSighashType,
+) {
+    let payouts = parse_payouts(payouts, state.network);
+    if payouts.is_empty() {
+        fail("No payouts");
+    }
+    create_peg_out(config, state, payouts, fee_rate, sighash_type)
+        .unwrap_or_else(|error| fail(&format!("Could not create the peg-out: {error}")));
+}
+
+// Prints how much of each withdrawal goes to the miners, the bridge and the recipient
+fn create_peg_out(
+    config: &Config,
+    state: &mut State,
+    payouts: Payouts,
+    fee_rate: Amount,
+    sighash_type: TapSighashType,
+) -> Result<Txid, PayoutError> {
+    let secp = Secp256k1::new();
+    let committee = committee(state).clone();
+    let (_, script_pubkey) = committee.scripts(&secp);
+    let mut multisig_prover = MultisigProver {
+        available_utxos: state.utxos.clone(),
+    };
+    let (psbt, accounting) = multisig_prover.create_peg_out_psbt(
+        fee_rate,
+        payouts,
+        &config.protocol_fee,
+        &committee,
+        sighash_type,
+        &secp,
+    )?;
+    println!("Peg-out {}", psbt.unsigned_tx.compute_txid());
+    for payout in &accounting {
+        println!("  {payout}");
+    }
+
+    state.utxos = multisig_prover.available_utxos;
+    state.add_utxos(&psbt.unsigned_tx, &script_pubkey);
+    let mut pending = PendingTx::from_psbt(TxKind::PegOut, &psbt, committee);
+    pending.deposits = state.spent_deposits(&pending.prevouts);
+    state
+        .ledger
+        .record_peg_out(&pending.tx, &pending.prevouts, &accounting, now());
+    state.pending.push(pending);
+    Ok(psbt.unsigned_tx.compute_txid())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("The clock is before 1970")
+        .as_secs()
+}
+
+fn request_peg_out(
+    config: &Config,
+    state: &mut State,
+    id: &str,
+    amount: Amount,
+    address: Address<NetworkUnchecked>,
+    deadline: Option<u64>,
+) {
+    let request = PegOutRequest {
+        id: id.to_string(),
+        amount,
+        address,
+        deadline,
+        received_at: now(),
+    };
+    state
+        .peg_out_queue
+        .push(request, state.network, &config.protocol_fee)
+        .unwrap_or_else(|error| fail(&format!("Request refused: {error}")));
+    println!("{} requests queued", state.peg_out_queue.requests.len());
+}
+
+// Meant to run periodically: pays the due batches, if any. A request that its share of the
+// miner fee leaves as dust is parked, and its batch cut again without it. A batch that can't be
+// paid otherwise, e.g. for lack of funds, goes back to the queue until the next run.
+fn batch_peg_outs(
+    config: &Config,
+    state: &mut State,
+    force: bool,
+    fee_rate: Amount,
+    sighash_type: TapSighashType,
+) {
+    let now = now();
+    let mut force = force;
+    while let Some(mut requests) = state.peg_out_queue.next_batch(&config.peg_outs, now, force) {
+        let error = match create_peg_out(config, state, payouts(&requests), fee_rate, sighash_type)
+        {
+            Ok(txid) => {
+                println!("Batch of {} requests", requests.len());
+                state.peg_out_queue.record_batch(txid, requests);
+                force = false;
+                continue;
+            }
+            Err(error) => error,
+        };
+        let dust = match &error {
+            PayoutError::Dust { address, .. } => requests
+                .iter()
+                .enumerate()
+                .filter(|(_, x)| x.address.assume_checked_ref() == address)
+                .min_by_key(|(_, x)| x.amount)
+                .map(|(i, _)| i),
+            _ => None,
+        };
+        match dust {
+            Some(i) => {
+                let request = requests.remove(i);
+                println!("Request {} parked: {error}", request.id);
+                state.peg_out_queue.park(request, error.to_string());
+                state.peg_out_queue.requeue(requests);
+            }
+            None => {
+                println!("Batch of {} requests not paid: {error}", requests.len());
+                state.peg_out_queue.requeue(requests);
+                break;
+            }
+        }
+    }
+    if !state.peg_out_queue.requests.is_empty() {
+        println!("{} requests queued", state.peg_out_queue.requests.len());
+    }
+}
+
+fn peg_out_status(state: &State, id: &str) {
+    let queue = &state.peg_out_queue;
+    if let Some(batch) = queue.batch_of(id) {
+        println!("Paid by peg-out {}", batch.txid);
+    } else if let Some(parked) = queue.parked_request(id) {
+        println!("Parked: {}", parked.reason);
+    } else if queue.contains(id) {
+        println!("Queued");
+    } else {
+        fail(&format!("Unknown request {id}"));
+    }
+}
+
+// Signs with deterministic auxiliary randomness if there is a seed
+fn key_signer(key: Xpriv, aux_seed: Option<[u8; 32]>) -> Arc<dyn Signer> {
+    match aux_seed {
+        Some(seed) => Arc::new(SeededSigner::new(key, seed)),
+        None => Arc::new(key),
+    }
+}
+
+// The demo key of the `index`th validator. Everyone knows them, so only regtest can use them.
+fn demo_key(index: usize, network: Network) -> Xpriv {
+    if network != Network::Regtest {
+        fail(&format!(
+            "Demo keys are only allowed on regtest, not {network}: use --key, --signer-url or a committee file"
+        ));
+    }
+    get_private_key(index, network).unwrap()
+}
+
+// The given key or remote signer, None for the demo key
+fn validator_signer(
+    operator_address: &str,
+    key: Option<Xpriv>,
+    signer_url: &Option<String>,
+    key_id: &Option<String>,
+    aux_seed: Option<[u8; 32]>,
+) -> Option<Arc<dyn Signer>> {
+    match (key, signer_url) {
+        (Some(key), _) => Some(key_signer(key, aux_seed)),
+        (None, Some(url)) => Some(Arc::new(RemoteSigner::new(
+            url,
+            key_id.as_deref().unwrap_or(operator_address),
+        ))),
+        (None, None) => None,
+    }
+}
+
+fn sign(
+    state: &mut State,
+    operator_address: &str,
+    signer: Option<Arc<dyn Signer>>,
+    aux_seed: Option<[u8; 32]>,
+) {
+    let secp = Secp256k1::new();
+    let network = state.network;
+
+    let mut signed = 0;
+    for pending in state.pending.iter_mut() {
+        let Some(index) = pending.committee.member_index(operator_address) else {
+            continue;
+        };
+        let member = &pending.committee.members[index];
+        let validator = Validator {
+            operator_address: member.operator_address.clone(),
+            weight: member.weight,
+            signer: Some(
+                signer
+                    .clone()
+                    .unwrap_or_else(|| key_signer(demo_key(index, network), aux_seed)),
+            ),
+        };
+        if validator.public_key(&secp) != member.public_key {
+            fail(&format!(
+                "The signer's key doesn't match {operator_address}'s public key {}",
+                member.public_key
+            ));
+        }
+
+        let mut psbt = pending.psbt(&secp);
+        let signatures = validator
+            .sign_psbt(&mut psbt, &secp)
+            .unwrap_or_else(|error| {
+                fail(&format!(
+                    "Can't sign {}: {error}",
+                    pending.tx.compute_txid()
+                ))
+            });
+        // None of the inputs are the validator's to sign
+        if signatures == 0 {
+            continue;
+        }
+        pending.import_psbt(&psbt);
+        record_signatures(&mut state.evidence, pending);
+        signed += 1;
+    }
+
+    println!("Signed {signed} pending transactions as {operator_address}");
+}
+
+fn export_psbt(state: &State, out_dir: &Path) {
+    let secp = Secp256k1::new();
+    for pending in &state.pending {
+        let path = out_dir.join(format!("{}.psbt", pending.tx.compute_txid()));
+        fs::write(&path, pending.psbt(&secp).to_string())
+            .unwrap_or_else(|error| fail(&format!("Could not write {}: {error}", path.display())));
+        println!("{}", path.display());
+    }
+}
+
+fn read_psbt(path: &Path) -> Psbt {
+    let base64 = fs::read_to_string(path)
+        .unwrap_or_else(|error| fail(&format!("Could not read {}: {error}", path.display())));
+    Psbt::from_str(base64.trim())
+        .unwrap_or_else(|error| fail(&format!("Could not parse {}: {error}", path.display())))
+}
+
+// Stands in for a validator's wallet: signs with the key alone, without the state
+fn sign_psbt(path: &Path, key: &Xpriv, aux_seed: Option<[u8; 32]>) {
+    let secp = Secp256k1::new();
+    let validator = Validator {
+        operator_address: String::new(),
+        weight: 0,
+        signer: Some(key_signer(*key, aux_seed)),
+    };
+    let mut psbt = read_psbt(path);
+    let signed = validator
+        .sign_psbt(&mut psbt, &secp)
+        .unwrap_or_else(|error| fail(&format!("Can't sign {}: {error}", path.display())));
+    fs::write(path, psbt.to_string())
+        .unwrap_or_else(|error| fail(&format!("Could not write {}: {error}", path.display())));
+    println!("Added {signed} signatures");
+}
+
+fn import_psbt(state: &mut State, paths: &[PathBuf]) {
+    let psbt = combine_psbts(paths.iter().map(|x| read_psbt(x)).collect())
+        .unwrap_or_else(|error| fail(&format!("Could not combine the PSBTs: {error}")));
+    let txid = psbt.unsigned_tx.compute_txid();
+    let pending = state
+        .pending
+        .iter_mut()
+        .find(|x| x.tx.compute_txid() == txid)
+        .unwrap_or_else(|| fail(&format!("{txid} isn't pending")));
+    println!("Imported {} signatures", pending.import_psbt(&psbt));
+    record_signatures(&mut state.evidence, pending);
+}
+
+// Warns about the validators that signed another transaction spending the same UTXOs
+fn record_signatures(evidence: &mut EvidenceLog, pending: &PendingTx) {
+    let secp = Secp256k1::new();
+    let conflicts = evidence.record_signatures(
+        &pending.tx,
+        &pending.prevouts,
+        &pending.committee,
+        &pending.signatures,
+        &secp,
+    );
+    for conflict in &evidence.conflicts[evidence.conflicts.len() - conflicts..] {
+        eprintln!(
+            "{} signed conflicting transactions spending {}, see `export-evidence`",
+            conflict.operator_address, conflict.outpoint
+        );
+    }
+}
+
+fn export_evidence(state: &State, out: Option<&Path>) {
+    let export = state.evidence.export();
+    match out {
+        Some(path) => fs::write(path, export)
+            .unwrap_or_else(|error| fail(&format!("Could not write {}: {error}", path.display()))),
+        None => println!("{export}"),
+    }
+}
+
+fn finalize(state: &mut State) {
+    let secp = Secp256k1::new();
+
+    for pending in std::mem::take(&mut state.pending) {
+        let mut psbt = pending.psbt(&secp);
+        let txid = pending.tx.compute_txid();
+        match finalize_psbt(
+            &mut psbt,
+            &pending.committee.pks_weights(),
+            pending.committee.threshold,
+            &secp,
+        ) {
+            Ok(_) => {
+                println!("Finalized {txid}");
+                state.finalized.push(FinalizedTx {
+                    kind: pending.kind,
+                    tx: psbt.extract_tx_unchecked_fee_rate(),
+                    prevouts: pending.prevouts,
+                });
+            }
+            Err(PsbtError::BelowThreshold(report)) => {
+                println!(
+                    "{txid} is below the threshold on inputs {:?}",
+                    report.inputs_below_threshold
+                );
+                state.pending.push(pending);
+            }
+            Err(error) => {
+                println!("{txid} can't be finalized: {error}");
+                state.pending.push(pending);
+            }
+        }
+    }
+}
+
+fn broadcast(
+    config: &Config,
+    state: &mut State,
+    backend: Backend,
+    url: Option<&str>,
+    mine_to: Option<Address<NetworkUnchecked>>,
+    packages: bool,
+) {
+    let txs = state
+        .finalized
+        .iter()
+        .map(|x| x.tx.clone())
+        .collect::<Vec<_>>();
+    if txs.is_empty() {
+        println!("Nothing to broadcast");
+        return;
+    }
+    let url = || url.unwrap_or_else(|| fail("--url is required for this backend"));
+    if packages && !matches!(backend, Backend::Bitcoind) {
+        fail("Only bitcoind takes packages");
+    }
+
+    let reports = match backend {
+        Backend::Bitcoind => {
+            let rpc = config.rpc_client();
+            let mut broadcaster = match mine_to {
+                None => BitcoindBroadcaster::new(&rpc),
+                Some(address) => {
+                    if !config.is_regtest() {
+                        fail("Blocks can only be mined on regtest");
+                    }
+                    let address = address
+                        .require_network(state.network)
+                        .unwrap_or_else(|error| fail(&error.to_string()));
+                    BitcoindBroadcaster::regtest(&rpc, address)
+                }
+            };
+            broadcaster.packages = packages;
+            broadcaster.broadcast(&txs)
+        }
+        Backend::Esplora => EsploraBroadcaster::new(url()).broadcast(&txs),
+        Backend::Electrum => ElectrumBroadcaster::new(url()).broadcast(&txs),
+        Backend::DryRun => DryRunBroadcaster::default().broadcast(&txs),
+    };
+    print_reports(&reports, &txs);
+
+    state.finalized.retain(|x| {
+        let txid = x.tx.compute_txid();
+        !reports
+            .iter()
+            .any(|report| report.submitted && report.txid == txid)
+    });
+}
+
+fn print_tx(tx: &transaction::Transaction, network: Network) {
+    println!("Txid: {}", tx.compute_txid());
+    println!("Wtxid: {}", tx.compute_wtxid());
+    println!(
+        "Version {}, lock time {}, {} vbytes, {} WU",
+        tx.version,
+        tx.lock_time,
+        tx.vsize(),
+        tx.weight()
+    );
+    println!("Inputs:");
+    for (i, txin) in tx.input.iter().enumerate() {
+        println!(
+            "  #{i} {} sequence {:#x}, {} witness elements",
+            txin.previous_output,
+            txin.sequence.0,
+            txin.witness.len()
+        );
+    }
+    println!("Outputs:");
+    for (i, txout) in tx.output.iter().enumerate() {
+        let destination = Address::from_script(&txout.script_pubkey, network)
+            .map(|x| x.to_string())
+            .unwrap_or(txout.script_pubkey.to_asm_string());
+        println!("  #{i} {} to {destination}", txout.value);
+    }
+}
+
+fn inspect_tx(state: &State, tx: &str) {
+    let secp = Secp256k1::new();
+    let txid = Txid::from_str(tx).ok();
+
+    if let Some(pending) = state
+        .pending
+        .iter()
+        .find(|x| Some(x.tx.compute_txid()) == txid)
+    {
+        print_tx(&pending.tx, state.network);
+        let report = verify_signatures(
+            &pending
+                .sighashes(&secp)
+                .unwrap_or_else(|error| fail(&error.to_string())),
+            pending.sighash_type,
+            &mut pending.signatures.clone(),
+            &pending.committee.pks_weights(),
+            pending.committee.threshold,
+            &secp,
+        );
+        println!(
+            "Pending {:?} signed with {}, threshold {}",
+            pending.kind, pending.sighash_type, pending.committee.threshold
+        );
+        println!("{report:#?}");
+        return;
+    }
+
+    let (kind, tx, prevouts) = match state
+        .finalized
+        .iter()
+        .find(|x| Some(x.tx.compute_txid()) == txid)
+    {
+        Some(finalized) => (
+            Some(finalized.kind),
+            finalized.tx.clone(),
+            Some(finalized.prevouts.clone()).filter(|x| !x.is_empty()),
+        ),
+        None => {
+            let tx: transaction::Transaction = deserialize_hex(tx).unwrap_or_else(|_| {
+                fail("Neither a transaction in the state nor a raw transaction")
+            });
+            let prevouts = state.prevouts(&tx);
+            (None, tx, prevouts)
+        }
+    };
+
+    print_tx(&tx, state.network);
+    if let Some(kind) = kind {
+        println!("Finalized {kind:?}");
+    }
+    match prevouts {
+        None => println!("Spent outputs unknown, can't verify the witnesses"),
+        Some(prevouts) => match verify_transaction(&tx, &prevouts, &secp) {
+            Ok(()) => println!("Witnesses verified"),
+            Err(error) => println!("Witness verification failed: {error:?}"),
+        },
+    }
+}
+
+fn reserves(config: &Config, state: &State, out: &Path, wrapped_supply: Option<Amount>) {
+    let secp = Secp256k1::new();
+    let rpc = config.rpc_client();
+    let height = rpc
+        .get_block_count()
+        .unwrap_or_else(|error| fail(&format!("Could not get the block count: {error}")));
+    let lookup = |outpoint: &OutPoint| {
+        let txout = rpc
+            .get_tx_out(&outpoint.txid, outpoint.vout, Some(true))
+            .unwrap_or_else(|error| fail(&format!("Could not look up {outpoint}: {error}")));
+        match txout {
+            Some(txout) if txout.confirmations > 0 => UtxoStatus::Confirmed {
+                confirmations: txout.confirmations,
+            },
+            Some(_) => UtxoStatus::Unconfirmed,
+            None => UtxoStatus::Missing,
+        }
+    };
+    let report = ReservesReport::new(
+        state,
+        committee(state),
+        lookup,
+        now(),
+        height,
+        wrapped_supply,
+        &secp,
+    );
+    print_reserves(&report);
+
+    let json = serde_json::to_string_pretty(&SignedReport::new(report))
+        .expect("Could not serialize report");
+    fs::write(out, json)
+        .unwrap_or_else(|error| fail(&format!("Could not write {}: {error}", out.display())));
+    println!("Wrote {}, for the committee to sign", out.display());
+}
+
+fn print_reserves(report: &ReservesReport) {
+    for script in &report.scripts {
+        let address = Address::from_script(&script.script_pubkey, report.network)
+            .map_or_else(|_| script.script_pubkey.to_hex_string(), |x| x.to_string());
+        println!(
+            "{address}: {} confirmed, {} unconfirmed, {} missing, {} pending peg-ins, {} unbroadcast peg-outs",
+            script.confirmed.to_sat(),
+            script.unconfirmed.to_sat(),
+            script.missing.to_sat(),
+            script.pending_peg_ins.to_sat(),
+            script.unbroadcast_peg_outs.to_sat()
+        );
+        for utxo in script
+            .utxos
+            .iter()
+            .filter(|x| x.status == UtxoStatus::Missing)
+        {
+            println!("  {} is spent or unknown to bitcoind", utxo.outpoint);
+        }
+    }
+    println!(
+        "Reserves at height {}: {} confirmed, {} unconfirmed",
+        report.height,
+        report.confirmed.to_sat(),
+        report.unconfirmed.to_sat()
+    );
+    if let Some(supply) = report.wrapped_supply {
+        println!("Wrapped supply: {}", supply.to_sat());
+    }
+}
+
+fn read_report(path: &Path) -> SignedReport {
+    let json = fs::read_to_string(path)
+        .unwrap_or_else(|error| fail(&format!("Could not read {}: {error}", path.display())));
+    serde_json::from_str(&json)
+        .unwrap_or_else(|error| fail(&format!("Could not parse {}: {error}", path.display())))
+}
+
+fn sign_reserves(path: &Path, operator_address: &str, signer: Option<Arc<dyn Signer>>) {
+    let secp = Secp256k1::new();
+    let mut signed = read_report(path);
+    let committee = &signed.report.committee;
+    let Some(index) = committee.member_index(operator_address) else {
+        fail(&format!(
+            "{operator_address} isn't a member of the report's committee"
+        ));
+    };
+    let validator = Validator {
+        operator_address: operator_address.to_owned(),
+        weight: committee.members[index].weight,
+        signer: Some(signer.unwrap_or_else(|| Arc::new(demo_key(index, signed.report.network)))),
+    };
+    signed.sign(&validator, &secp).unwrap_or_else(|error| {
+        fail(&format!(
+            "{operator_address} couldn't sign the report: {error}"
+        ))
+    });
+    fs::write(
+        path,
+        serde_json::to_string_pretty(&signed).expect("Could not serialize report"),
+    )
+    .unwrap_or_else(|error| fail(&format!("Could not write {}: {error}", path.display())));
+    println!("Signed report {}", signed.report.hash());
+}
+
+fn verify_reserves(path: &Path, committee: Option<&Path>) {
+    let secp = Secp256k1::new();
+    let signed = read_report(path);
+    if let Some(committee) = committee {
+        if load_committee(committee) != signed.report.committee {
+            fail("The report is signed by a different committee");
+        }
+    }
+    let (_, script_pubkey) = signed.report.committee.scripts(&secp);
+    let weight = signed
+        .verify(&secp)
+        .unwrap_or_else(|error| fail(&format!("Invalid report: {error}")));
+    print_reserves(&signed.report);
+    println!(
+        "Signed by {} members of committee {} with weight {weight}, threshold {}",
+        signed.signatures.len(),
+        Address::from_script(&script_pubkey, signed.report.network).unwrap(),
+        signed.report.committee.threshold
+    );
+}
+
+fn check_ledger(state: &State) {
+    for (account, balance) in state.ledger.balances() {
+        println!("{}: {}", account.name(state.network), balance.to_sat());
+    }
+    state
+        .ledger
+        .check(&state.utxos)
+        .unwrap_or_else(|error| fail(&format!("The ledger doesn't reconcile: {error}")));
+    println!(
+        "{} entries, reconciled with {} UTXOs",
+        state.ledger.entries.len(),
+        state.utxos.len()
+    );
+}
+
+// To stdout if there is no `out`
+fn export_ledger(state: &State, format: LedgerFormat, out: Option<&Path>) {
+    let export = match format {
+        LedgerFormat::Csv => state.ledger.to_csv(state.network),
+        LedgerFormat::Json => state.ledger.to_json(),
+    };
+    match out {
+        Some(path) => fs::write(path, export)
+            .unwrap_or_else(|error| fail(&format!("Could not write {}: {error}", path.display()))),
+        None => print!("{export}"),
+    }
+}
+
+fn print_descriptor(state: &State, descriptor: Option<&str>) {
+    let secp = Secp256k1::new();
+    let descriptor = match descriptor {
+        Some(descriptor) => CommitteeDescriptor::from_str(descriptor)
+            .unwrap_or_else(|error| fail(&error.to_string())),
+        None => CommitteeDescriptor::new(committee(state)),
+    };
+    println!("Descriptor: {descriptor}");
+    println!("Watch-only: {}", descriptor.watch_only(&secp));
+    println!("Address: {}", descriptor.address(state.network, &secp));
+}
+
+// Client bound to the watch-only wallet, which is created if needed
+fn watch_client(config: &Config) -> Client {
+    let wallet = config
+        .watch
+        .wallet
+        .clone()
+        .unwrap_or_else(|| fail("No watch-only wallet configured (`wallet` in `[watch]`)"));
+    let rpc = Config {
+        wallet: Some(wallet.clone()),
+        ..config.clone()
+    }
+    .rpc_client();
+    watch_wallet::load_or_create(&rpc, &wallet)
+        .unwrap_or_else(|error| fail(&format!("Could not open wallet {wallet}: {error}")));
+    rpc
+}
+
+// The committee's, the deposit addresses' and the previous committees' with UTXOs left
+fn watched_scripts(state: &State) -> Vec<ScriptBuf> {
+    let secp = Secp256k1::new();
+    let mut scripts = vec![committee(state).scripts(&secp).1];
+    let others = state
+        .deposit_addresses
+        .iter()
+        .map(|x| &x.script_pubkey)
+        .chain(state.utxos.iter().map(|x| &x.txout.script_pubkey));
+    for script_pubkey in others {
+        if !scripts.contains(script_pubkey) {
+            scripts.push(script_pubkey.clone());
+        }
+    }
+    scripts
+}
+
+fn watch_committee(config: &Config, state: &State, rescan_from: Option<u64>) {
+    let scripts = watched_scripts(state);
+    watch_wallet::import_scripts(&watch_client(config), &scripts, rescan_from)
+        .unwrap_or_else(|error| fail(&error.to_string()));
+    println!("Watching {} scripts", scripts.len());
+}
+
+// New scripts (e.g. deposit addresses) are imported without a rescan, so they have to be created
+// by the CLI before they are paid
+fn sync_utxos(config: &Config, state: &mut State) {
+    let rpc = watch_client(config);
+    let scripts = watched_scripts(state);
+    watch_wallet::import_scripts(&rpc, &scripts, None)
+        .unwrap_or_else(|error| fail(&error.to_string()));
+    let unspent = watch_wallet::list_unspent(&rpc, &scripts)
+        .unwrap_or_else(|error| fail(&format!("Could not list the UTXOs: {error}")));
+
+    let unbroadcast: Vec<&transaction::Transaction> = state
+        .pending
+        .iter()
+        .map(|x| &x.tx)
+        .chain(state.finalized.iter().map(|x| &x.tx))
+        .collect();
+    let reconciliation = reconcile(
+        &state.utxos,
+        &unspent,
+        &unbroadcast,
+        config.watch.min_confirmations,
+        |outpoint| {
+            rpc.get_tx_out(&outpoint.txid, outpoint.vout, Some(true))
+                .unwrap_or_else(|error| fail(&format!("Could not look up {outpoint}: {error}")))
+                .is_some()
+        },
+    );
+
+    for utxo in &reconciliation.discovered {
+        println!("Found {} of {}", utxo.outpoint, utxo.txout.value);
+        state.ledger.record_deposit(utxo, now());
+    }
+    for utxo in &reconciliation.vanished {
+        eprintln!(
+            "{} of {} was spent outside of the CLI",
+            utxo.outpoint, utxo.txout.value
+        );
+        state.ledger.record_external_spend(utxo, now());
+    }
+    state.utxos = reconciliation.utxos;
+    println!("{} UTXOs", state.utxos.len());
+}
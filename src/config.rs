@@ -0,0 +1,315 @@
+// Configuration of the operator, loaded from a TOML file (see `axelar-btc.example.toml`) and
+// overridden by `AXELAR_BTC_*` environment variables. Everything defaults to a local regtest
+// node, but only regtest allows mining and funding the wallet with coinbase outputs.
+
+use std::{env, fmt, fs, io, path::Path, path::PathBuf, str::FromStr};
+
+use bitcoin::Network;
+use bitcoincore_rpc::{Auth, Client};
+use serde::Deserialize;
+
+use crate::protocol_fee::FeeSchedule;
+
+pub const DEFAULT_CONFIG_FILE: &str = "axelar-btc.toml";
+const ENV_PREFIX: &str = "AXELAR_BTC_";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    // An `AXELAR_BTC_*` variable that doesn't parse
+    Env { name: String, value: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, error) => {
+                write!(f, "Could not read {}: {error}", path.display())
+            }
+            ConfigError::Parse(path, error) => {
+                write!(f, "Could not parse {}: {error}", path.display())
+            }
+            ConfigError::Env { name, value } => write!(f, "Invalid value for {name}: {value}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub network: Network,
+    // File with the state kept between invocations
+    pub state: PathBuf,
+    // Wallet used for peg-ins, if bitcoind has more than one loaded
+    pub wallet: Option<String>,
+    pub rpc: RpcConfig,
+    pub committee: CommitteeSource,
+    pub fees: FeePolicy,
+    // Bridge fee charged on every withdrawal
+    pub protocol_fee: FeeSchedule,
+    pub peg_outs: BatchPolicy,
+    pub watch: WatchConfig,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RpcConfig {
+    // Defaults to localhost with the network's default port
+    pub url: Option<String>,
+    // Data directory of bitcoind, to authenticate with its cookie file
+    pub datadir: Option<PathBuf>,
+    pub cookie: Option<PathBuf>,
+    pub user: Option<String>,
+    pub pass: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(tag = "source", rename_all = "lowercase", deny_unknown_fields)]
+pub enum CommitteeSource {
+    // Current Bitcoin chain maintainers, as reported by axelarscan
+    #[default]
+    Axelarscan,
+    // Committee JSON file, as written by `setup-committee --out`
+    File {
+        path: PathBuf,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeePolicy {
+    // Peg-out fee rate in sats/vbyte
+    pub fee_rate: u64,
+    // Peg-outs with a higher fee rate are refused, to guard against typos
+    pub max_fee_rate: u64,
+    // Miner fee of each handover in sats
+    pub handover_fee: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatchPolicy {
+    // Requests paid by a single peg-out
+    pub max_batch_size: usize,
+    // Seconds a request waits for other requests to share its peg-out
+    pub batch_window: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchConfig {
+    // Watch-only wallet listing the committee's UTXOs. Without one, the state keeps track of them.
+    pub wallet: Option<String>,
+    // Deposits found by the wallet are only spent after this many confirmations
+    pub min_confirmations: u32,
+}
+
+impl Default for WatchConfig {
+    fn default() -> WatchConfig {
+        WatchConfig {
+            wallet: None,
+            min_confirmations: 1,
+        }
+    }
+}
+
+impl Default for BatchPolicy {
+    fn default() -> BatchPolicy {
+        BatchPolicy {
+            max_batch_size: 50,
+            batch_window: 3600,
+        }
+    }
+}
+
+impl Default for FeePolicy {
+    fn default() -> FeePolicy {
+        FeePolicy {
+            fee_rate: 10,
+            max_fee_rate: 500,
+            handover_fee: 1000,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            network: Network::Regtest,
+            state: PathBuf::from("axelar-btc-state.json"),
+            wallet: None,
+            rpc: RpcConfig::default(),
+            committee: CommitteeSource::default(),
+            fees: FeePolicy::default(),
+            protocol_fee: FeeSchedule::default(),
+            peg_outs: BatchPolicy::default(),
+            watch: WatchConfig::default(),
+        }
+    }
+}
+
+fn env_var<T: FromStr>(name: &str) -> Result<Option<T>, ConfigError> {
+    let name = ENV_PREFIX.to_owned() + name;
+    let Ok(value) = env::var(&name) else {
+        return Ok(None);
+    };
+    match value.parse() {
+        Ok(value) => Ok(Some(value)),
+        Err(_) => Err(ConfigError::Env { name, value }),
+    }
+}
+
+impl Config {
+    // Reads `path`, or `DEFAULT_CONFIG_FILE` if it exists, and applies the environment
+    pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+        let path = path.or(Some(Path::new(DEFAULT_CONFIG_FILE)).filter(|x| x.exists()));
+        let mut config = match path {
+            None => Config::default(),
+            Some(path) => {
+                let contents = fs::read_to_string(path)
+                    .map_err(|error| ConfigError::Read(path.into(), error))?;
+                toml::from_str(&contents).map_err(|error| ConfigError::Parse(path.into(), error))?
+            }
+        };
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Some(network) = env_var("NETWORK")? {
+            self.network = network;
+        }
+        if let Some(state) = env_var("STATE")? {
+            self.state = state;
+        }
+        if let Some(wallet) = env_var("WALLET")? {
+            self.wallet = Some(wallet);
+        }
+        if let Some(url) = env_var("RPC_URL")? {
+            self.rpc.url = Some(url);
+        }
+        if let Some(datadir) = env_var("DATADIR")? {
+            self.rpc.datadir = Some(datadir);
+        }
+        if let Some(cookie) = env_var("RPC_COOKIE")? {
+            self.set_rpc_cookie(cookie);
+        }
+        if let Some(user) = env_var("RPC_USER")? {
+            self.rpc.user = Some(user);
+        }
+        if let Some(pass) = env_var("RPC_PASS")? {
+            self.rpc.pass = Some(pass);
+        }
+        if let Some(path) = env_var("COMMITTEE_FILE")? {
+            self.committee = CommitteeSource::File { path };
+        }
+        if let Some(fee_rate) = env_var("FEE_RATE")? {
+            self.fees.fee_rate = fee_rate;
+        }
+        if let Some(max_fee_rate) = env_var("MAX_FEE_RATE")? {
+            self.fees.max_fee_rate = max_fee_rate;
+        }
+        if let Some(handover_fee) = env_var("HANDOVER_FEE")? {
+            self.fees.handover_fee = handover_fee;
+        }
+        if let Some(flat_fee) = env_var("PROTOCOL_FLAT_FEE")? {
+            self.protocol_fee.flat_fee = flat_fee;
+        }
+        if let Some(percentage_bps) = env_var("PROTOCOL_FEE_BPS")? {
+            self.protocol_fee.percentage_bps = percentage_bps;
+        }
+        if let Some(minimum_fee) = env_var("PROTOCOL_MIN_FEE")? {
+            self.protocol_fee.minimum_fee = minimum_fee;
+        }
+        if let Some(max_batch_size) = env_var("MAX_BATCH_SIZE")? {
+            self.peg_outs.max_batch_size = max_batch_size;
+        }
+        if let Some(batch_window) = env_var("BATCH_WINDOW")? {
+            self.peg_outs.batch_window = batch_window;
+        }
+        if let Some(wallet) = env_var("WATCH_WALLET")? {
+            self.watch.wallet = Some(wallet);
+        }
+        if let Some(min_confirmations) = env_var("MIN_CONFIRMATIONS")? {
+            self.watch.min_confirmations = min_confirmations;
+        }
+        Ok(())
+    }
+
+    pub fn is_regtest(&self) -> bool {
+        self.network == Network::Regtest
+    }
+
+    pub fn require_regtest(&self, action: &str) {
+        assert!(
+            self.is_regtest(),
+            "{action} is only allowed on regtest, not {}",
+            self.network
+        );
+    }
+
+    // Directory where bitcoind keeps the files of the configured network
+    pub fn network_dir(&self) -> Option<PathBuf> {
+        self.rpc
+            .datadir
+            .as_ref()
+            .map(|x| x.join(network_subdir(self.network)))
+    }
+
+    pub fn rpc_url(&self) -> String {
+        let url = self.rpc.url.clone().unwrap_or(format!(
+            "http://127.0.0.1:{}",
+            default_rpc_port(self.network)
+        ));
+        match &self.wallet {
+            Some(wallet) if !url.contains("/wallet/") => format!("{url}/wallet/{wallet}"),
+            _ => url,
+        }
+    }
+
+    // Authenticates with the cookie file instead of a user & password from the file
+    pub fn set_rpc_cookie(&mut self, cookie: PathBuf) {
+        self.rpc.cookie = Some(cookie);
+        self.rpc.user = None;
+        self.rpc.pass = None;
+    }
+
+    pub fn rpc_auth(&self) -> Option<Auth> {
+        match (&self.rpc.user, &self.rpc.pass, &self.rpc.cookie) {
+            (Some(user), Some(pass), _) => Some(Auth::UserPass(user.clone(), pass.clone())),
+            (_, _, Some(cookie)) => Some(Auth::CookieFile(cookie.clone())),
+            _ => self
+                .network_dir()
+                .map(|x| Auth::CookieFile(x.join(".cookie"))),
+        }
+    }
+
+    pub fn rpc_client(&self) -> Client {
+        let auth = self.rpc_auth().expect(
+            "RPC authentication needed: a data directory, a cookie file or a user & password",
+        );
+        Client::new(&self.rpc_url(), auth).unwrap()
+    }
+}
+
+pub fn default_rpc_port(network: Network) -> u16 {
+    match network {
+        Network::Bitcoin => 8332,
+        Network::Testnet => 18332,
+        Network::Signet => 38332,
+        Network::Regtest => 18443,
+        _ => 48332, // testnet4
+    }
+}
+
+// Subdirectory of the data directory where bitcoind keeps the network's files
+pub fn network_subdir(network: Network) -> &'static str {
+    match network {
+        Network::Bitcoin => "",
+        Network::Testnet => "testnet3",
+        Network::Signet => "signet",
+        Network::Regtest => "regtest",
+        _ => "testnet4",
+    }
+}
@@ -0,0 +1,406 @@
+// Peg-in transactions built on the user's side, without a bitcoind wallet: the deposit to the
+// committee, the GMP OP_RETURN and change back to the user, paying a fee computed from a fee
+// rate. Signing is left to a `DepositSigner` (the RPC wallet, or a local P2WPKH/P2TR key), or
+// to any PSBT signer through `UnsignedDeposit::psbt`.
+//
+// Deposits to a deposit address with a refund leaf can be taken back by the depositor with a
+// `RefundBuilder` transaction once the refund delay has passed.
+
+use std::fmt;
+
+use bitcoin::{
+    absolute::LockTime,
+    ecdsa,
+    key::{Secp256k1, TapTweak},
+    psbt::Psbt,
+    secp256k1::{All, Message},
+    sighash::{EcdsaSighashType, Prevouts, SighashCache},
+    taproot::{self, ControlBlock, LeafVersion, TapLeafHash},
+    transaction::{predict_weight, InputWeightPrediction},
+    Amount, CompressedPublicKey, FeeRate, PrivateKey, ScriptBuf, Sequence, TapSighashType,
+    Transaction, TxIn, TxOut, Witness,
+};
+use bitcoin_hashes::Hash;
+use bitcoincore_rpc::{Client, RpcApi};
+
+use crate::{
+    create_op_return,
+    deposit_address::{DepositAddress, RefundLeaf},
+    Utxo, MAX_OP_RETURN_DATA,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DepositError {
+    InsufficientFunds { needed: Amount, available: Amount },
+    // The deposit itself would be dust
+    DustDeposit { minimum: Amount },
+    DestinationTooLong,
+    // Only P2PKH, P2WPKH and P2TR (key path) inputs can be sized
+    UnsupportedInput(ScriptBuf),
+    // The deposit address has no refund leaf
+    NotRefundable,
+    Signing(String),
+}
+
+impl fmt::Display for DepositError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DepositError::InsufficientFunds { needed, available } => {
+                write!(
+                    f,
+                    "insufficient funds: {needed} needed, {available} available"
+                )
+            }
+            DepositError::DustDeposit { minimum } => {
+                write!(f, "the deposit must be at least {minimum}")
+            }
+            DepositError::DestinationTooLong => {
+                write!(f, "GMP data is longer than {MAX_OP_RETURN_DATA} bytes")
+            }
+            DepositError::UnsupportedInput(script_pubkey) => {
+                write!(f, "can't spend {script_pubkey}")
+            }
+            DepositError::NotRefundable => write!(f, "the deposit address has no refund path"),
+            DepositError::Signing(error) => write!(f, "signing failed: {error}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DepositBuilder {
+    pub amount: Amount,
+    // Output of the committee (or deposit address) receiving the BTC
+    pub script_pubkey: ScriptBuf,
+    // GMP data: `<destination chain>:<destination address>[:<payload>]`
+    pub destination: String,
+    pub change_script_pubkey: ScriptBuf,
+    pub fee_rate: FeeRate,
+    // The user's UTXOs, of which as few as possible are spent
+    pub utxos: Vec<Utxo>,
+}
+
+#[derive(Debug, Clone)]
+pub struct UnsignedDeposit {
+    pub tx: Transaction,
+    // Outputs spent by `tx`, in input order
+    pub prevouts: Vec<TxOut>,
+    pub deposit_vout: u32,
+    pub fee: Amount,
+}
+
+// Size of the input once signed
+fn input_weight(script_pubkey: &ScriptBuf) -> Result<InputWeightPrediction, DepositError> {
+    if script_pubkey.is_p2wpkh() {
+        Ok(InputWeightPrediction::P2WPKH_MAX)
+    } else if script_pubkey.is_p2tr() {
+        Ok(InputWeightPrediction::P2TR_KEY_DEFAULT_SIGHASH)
+    } else if script_pubkey.is_p2pkh() {
+        Ok(InputWeightPrediction::P2PKH_COMPRESSED_MAX)
+    } else {
+        Err(DepositError::UnsupportedInput(script_pubkey.clone()))
+    }
+}
+
+impl DepositBuilder {
+    fn fee(&self, inputs: &[Utxo], outputs: &[TxOut]) -> Result<Amount, DepositError> {
+        let inputs = inputs
+            .iter()
+            .map(|x| input_weight(&x.txout.script_pubkey))
+            .collect::<Result<Vec<_>, _>>()?;
+        let weight = predict_weight(inputs, outputs.iter().map(|x| x.script_pubkey.len()));
+        // Relay policy compares the fee rate with the rounded-up virtual size
+        Ok(self
+            .fee_rate
+            .fee_vb(weight.to_vbytes_ceil())
+            .expect("Fee overflow"))
+    }
+
+    // Spends the largest UTXOs first. Change that would be dust is left to the miners.
+    pub fn build(&self) -> Result<UnsignedDeposit, DepositError> {
+        if self.destination.len() > MAX_OP_RETURN_DATA {
+            return Err(DepositError::DestinationTooLong);
+        }
+        let minimum = self.script_pubkey.minimal_non_dust();
+        if self.amount < minimum {
+            return Err(DepositError::DustDeposit { minimum });
+        }
+
+        let mut outputs = vec![
+            TxOut {
+                value: self.amount,
+                script_pubkey: self.script_pubkey.clone(),
+            },
+            TxOut {
+                value: Amount::ZERO,
+                script_pubkey: create_op_return(&self.destination),
+            },
+        ];
+        let change = TxOut {
+            value: Amount::ZERO,
+            script_pubkey: self.change_script_pubkey.clone(),
+        };
+        let with_change = [outputs.clone(), vec![change.clone()]].concat();
+
+        let mut utxos = self.utxos.clone();
+        utxos.sort_by_key(|x| std::cmp::Reverse(x.txout.value));
+        let available = utxos.iter().map(|x| x.txout.value).sum::<Amount>();
+
+        let mut selected = vec![];
+        let mut selected_value = Amount::ZERO;
+        for utxo in utxos {
+            selected_value += utxo.txout.value;
+            selected.push(utxo);
+
+            let fee = self.fee(&selected, &outputs)?;
+            if selected_value < self.amount + fee {
+                continue;
+            }
+            let change_fee = self.fee(&selected, &with_change)?;
+            let change_value = selected_value
+                .checked_sub(self.amount + change_fee)
+                .unwrap_or(Amount::ZERO);
+            let fee = if change_value >= change.script_pubkey.minimal_non_dust() {
+                outputs.push(TxOut {
+                    value: change_value,
+                    script_pubkey: change.script_pubkey.clone(),
+                });
+                change_fee
+            } else {
+                selected_value - self.amount
+            };
+
+            let tx = Transaction {
+                version: bitcoin::transaction::Version::TWO,
+                lock_time: LockTime::ZERO,
+                input: selected
+                    .iter()
+                    .map(|x| TxIn {
+                        previous_output: x.outpoint,
+                        script_sig: ScriptBuf::new(),
+                        sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                        witness: Witness::new(),
+                    })
+                    .collect(),
+                output: outputs,
+            };
+            return Ok(UnsignedDeposit {
+                tx,
+                prevouts: selected.into_iter().map(|x| x.txout).collect(),
+                deposit_vout: 0,
+                fee,
+            });
+        }
+
+        Err(DepositError::InsufficientFunds {
+            needed: self.amount + self.fee(&self.utxos, &outputs)?,
+            available,
+        })
+    }
+}
+
+impl UnsignedDeposit {
+    // Unsigned PSBT for external wallets, with the UTXOs they need to sign segwit inputs
+    pub fn psbt(&self) -> Psbt {
+        let mut psbt = Psbt::from_unsigned_tx(self.tx.clone()).unwrap();
+        for (input, prevout) in psbt.inputs.iter_mut().zip(&self.prevouts) {
+            input.witness_utxo = Some(prevout.clone());
+        }
+        psbt
+    }
+}
+
+pub trait DepositSigner {
+    fn sign_deposit(
+        &self,
+        deposit: &UnsignedDeposit,
+        secp: &Secp256k1<All>,
+    ) -> Result<Transaction, DepositError>;
+}
+
+// Signs with the keys of bitcoind's wallet
+pub struct WalletSigner<'a> {
+    pub rpc: &'a Client,
+}
+
+impl DepositSigner for WalletSigner<'_> {
+    fn sign_deposit(
+        &self,
+        deposit: &UnsignedDeposit,
+        _secp: &Secp256k1<All>,
+    ) -> Result<Transaction, DepositError> {
+        let signed = self
+            .rpc
+            .sign_raw_transaction_with_wallet(&deposit.tx, None, None)
+            .map_err(|error| DepositError::Signing(error.to_string()))?;
+        if !signed.complete {
+            return Err(DepositError::Signing(format!("{:?}", signed.errors)));
+        }
+        signed
+            .transaction()
+            .map_err(|error| DepositError::Signing(error.to_string()))
+    }
+}
+
+// Signs P2WPKH and P2TR (key path, without script tree) inputs with a single key
+pub struct KeySigner {
+    pub key: PrivateKey,
+}
+
+impl DepositSigner for KeySigner {
+    fn sign_deposit(
+        &self,
+        deposit: &UnsignedDeposit,
+        secp: &Secp256k1<All>,
+    ) -> Result<Transaction, DepositError> {
+        let public_key = CompressedPublicKey::from_private_key(secp, &self.key)
+            .map_err(|error| DepositError::Signing(error.to_string()))?;
+        let keypair = self.key.inner.keypair(secp);
+        let p2wpkh = ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash());
+        let p2tr = ScriptBuf::new_p2tr(secp, keypair.x_only_public_key().0, None);
+
+        let mut tx = deposit.tx.clone();
+        let mut sighash_cache = SighashCache::new(&deposit.tx);
+        for (i, prevout) in deposit.prevouts.iter().enumerate() {
+            let witness = if prevout.script_pubkey == p2wpkh {
+                let sighash = sighash_cache
+                    .p2wpkh_signature_hash(i, &p2wpkh, prevout.value, EcdsaSighashType::All)
+                    .expect("Could not compute sighash");
+                let signature = ecdsa::Signature {
+                    signature: secp.sign_ecdsa(&Message::from(sighash), &self.key.inner),
+                    sighash_type: EcdsaSighashType::All,
+                };
+                Witness::p2wpkh(&signature, &public_key.0)
+            } else if prevout.script_pubkey == p2tr {
+                let sighash = sighash_cache
+                    .taproot_key_spend_signature_hash(
+                        i,
+                        &Prevouts::All(&deposit.prevouts),
+                        TapSighashType::Default,
+                    )
+                    .expect("Could not compute sighash");
+                let tweaked = keypair.tap_tweak(secp, None).to_keypair();
+                let msg = Message::from_digest(sighash.to_byte_array());
+                Witness::p2tr_key_spend(&taproot::Signature {
+                    signature: secp.sign_schnorr(&msg, &tweaked),
+                    sighash_type: TapSighashType::Default,
+                })
+            } else {
+                return Err(DepositError::Signing(format!(
+                    "input {i} doesn't belong to the key"
+                )));
+            };
+            tx.input[i].witness = witness;
+        }
+        Ok(tx)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RefundBuilder {
+    pub deposit: DepositAddress,
+    // The deposit output, paying to `deposit`
+    pub utxo: Utxo,
+    pub script_pubkey: ScriptBuf,
+    pub fee_rate: FeeRate,
+}
+
+#[derive(Debug, Clone)]
+pub struct UnsignedRefund {
+    pub tx: Transaction,
+    pub prevout: TxOut,
+    pub leaf: RefundLeaf,
+    pub control_block: ControlBlock,
+    pub fee: Amount,
+}
+
+impl RefundBuilder {
+    // Sends the whole deposit minus the fee to `script_pubkey`, through the refund leaf
+    pub fn build(&self, secp: &Secp256k1<All>) -> Result<UnsignedRefund, DepositError> {
+        if self.utxo.txout.script_pubkey != self.deposit.script_pubkey {
+            return Err(DepositError::UnsupportedInput(
+                self.utxo.txout.script_pubkey.clone(),
+            ));
+        }
+        let leaf = self.deposit.refund.ok_or(DepositError::NotRefundable)?;
+        let control_block = self.deposit.refund_control_block(secp).unwrap();
+
+        // Signature, refund script and control block
+        let input = InputWeightPrediction::new(0, [64, leaf.script().len(), control_block.size()]);
+        let weight = predict_weight([input], [self.script_pubkey.len()]);
+        let fee = self
+            .fee_rate
+            .fee_vb(weight.to_vbytes_ceil())
+            .expect("Fee overflow");
+        let minimum = self.script_pubkey.minimal_non_dust();
+        let value = self
+            .utxo
+            .txout
+            .value
+            .checked_sub(fee)
+            .filter(|x| *x >= minimum)
+            .ok_or(DepositError::InsufficientFunds {
+                needed: fee + minimum,
+                available: self.utxo.txout.value,
+            })?;
+
+        let tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: self.utxo.outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: leaf.sequence(),
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value,
+                script_pubkey: self.script_pubkey.clone(),
+            }],
+        };
+        Ok(UnsignedRefund {
+            tx,
+            prevout: self.utxo.txout.clone(),
+            leaf,
+            control_block,
+            fee,
+        })
+    }
+}
+
+impl UnsignedRefund {
+    // Signs with the refund key. The transaction is only valid once the deposit has `leaf.delay`
+    // confirmations.
+    pub fn sign(
+        &self,
+        key: &PrivateKey,
+        secp: &Secp256k1<All>,
+    ) -> Result<Transaction, DepositError> {
+        let keypair = key.inner.keypair(secp);
+        if keypair.x_only_public_key().0 != self.leaf.key {
+            return Err(DepositError::Signing(
+                "the key isn't the refund key".to_string(),
+            ));
+        }
+        let script = self.leaf.script();
+        let sighash = SighashCache::new(&self.tx)
+            .taproot_script_spend_signature_hash(
+                0,
+                &Prevouts::All(&[&self.prevout]),
+                TapLeafHash::from_script(&script, LeafVersion::TapScript),
+                TapSighashType::Default,
+            )
+            .expect("Could not compute sighash");
+        let signature = taproot::Signature {
+            signature: secp.sign_schnorr(&Message::from_digest(sighash.to_byte_array()), &keypair),
+            sighash_type: TapSighashType::Default,
+        };
+
+        let mut tx = self.tx.clone();
+        tx.input[0].witness = Witness::from_slice(&[
+            signature.to_vec(),
+            script.into_bytes(),
+            self.control_block.serialize(),
+        ]);
+        Ok(tx)
+    }
+}
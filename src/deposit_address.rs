@@ -0,0 +1,202 @@
+// Deposit addresses bound to a GMP destination, for wallets and exchanges that can't add an
+// OP_RETURN to their payments. The taproot tree of a deposit address has two leaves: the
+// committee's multisig script, which is how the committee spends the deposit, and an
+// unspendable `OP_RETURN <hash>` leaf committing to the destination. Every destination thus
+// gets its own address, and the destination can be proven from the address by revealing it.
+//
+// A deposit address can also have a refund leaf, letting the depositor take the BTC back once
+// the deposit is old enough, in case Axelar never processes it (bad destination, destination
+// chain unavailable...). The committee leaf then stays at depth 1, so the committee's spends
+// are no larger than without the refund leaf.
+
+use bitcoin::{
+    hashes::{sha256, Hash, HashEngine},
+    key::Secp256k1,
+    opcodes::all::{OP_CHECKSIG, OP_CSV, OP_DROP, OP_RETURN},
+    script::Builder,
+    secp256k1::All,
+    taproot::{ControlBlock, LeafVersion, TaprootBuilder, TaprootSpendInfo},
+    transaction, Address, Network, ScriptBuf, Sequence, XOnlyPublicKey,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::state::Committee;
+
+const DEPOSIT_TAG: &[u8] = b"axelar-btc/deposit";
+
+// Tagged hash (BIP340 style) of the destination chain, address and payload. Every field is
+// length-prefixed, so that no two destinations hash the same.
+pub fn destination_hash(chain: &str, address: &str, payload: &[u8]) -> sha256::Hash {
+    let tag = sha256::Hash::hash(DEPOSIT_TAG);
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_ref());
+    engine.input(tag.as_ref());
+    for field in [chain.as_bytes(), address.as_bytes(), payload] {
+        engine.input(&(field.len() as u64).to_le_bytes());
+        engine.input(field);
+    }
+    sha256::Hash::from_engine(engine)
+}
+
+// `<delay> OP_CSV OP_DROP <key> OP_CHECKSIG`: the depositor's key, after `delay` blocks
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RefundLeaf {
+    pub key: XOnlyPublicKey,
+    pub delay: u16,
+}
+
+impl RefundLeaf {
+    pub fn script(&self) -> ScriptBuf {
+        Builder::new()
+            .push_int(self.delay as i64)
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_DROP)
+            .push_x_only_key(&self.key)
+            .push_opcode(OP_CHECKSIG)
+            .into_script()
+    }
+
+    // Sequence of the refund input (BIP68 relative lock time in blocks)
+    pub fn sequence(&self) -> Sequence {
+        Sequence::from_height(self.delay)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DepositAddress {
+    pub chain: String,
+    pub address: String,
+    #[serde(default)]
+    pub payload: Vec<u8>,
+    // The committee receiving the deposits
+    pub committee_script: ScriptBuf,
+    pub internal_key: XOnlyPublicKey,
+    #[serde(default)]
+    pub refund: Option<RefundLeaf>,
+    pub script_pubkey: ScriptBuf,
+}
+
+impl DepositAddress {
+    pub fn new(
+        committee: &Committee,
+        chain: &str,
+        address: &str,
+        payload: &[u8],
+        secp: &Secp256k1<All>,
+    ) -> DepositAddress {
+        let (committee_script, _) = committee.scripts(secp);
+        let mut deposit = DepositAddress {
+            chain: chain.to_string(),
+            address: address.to_string(),
+            payload: payload.to_vec(),
+            committee_script,
+            internal_key: committee.internal_key,
+            refund: None,
+            script_pubkey: ScriptBuf::new(),
+        };
+        deposit.script_pubkey = ScriptBuf::new_p2tr_tweaked(deposit.spend_info(secp).output_key());
+        deposit
+    }
+
+    // The same deposit address, refundable to `refund.key`
+    pub fn with_refund(mut self, refund: RefundLeaf, secp: &Secp256k1<All>) -> DepositAddress {
+        self.refund = Some(refund);
+        self.script_pubkey = ScriptBuf::new_p2tr_tweaked(self.spend_info(secp).output_key());
+        self
+    }
+
+    // From the `<chain>:<address>[:<payload>]` GMP data used in OP_RETURNs
+    pub fn from_destination(
+        committee: &Committee,
+        destination: &str,
+        secp: &Secp256k1<All>,
+    ) -> Option<DepositAddress> {
+        let mut fields = destination.splitn(3, ':');
+        let chain = fields.next().filter(|x| !x.is_empty())?;
+        let address = fields.next().filter(|x| !x.is_empty())?;
+        let payload = fields.next().unwrap_or_default();
+        Some(DepositAddress::new(
+            committee,
+            chain,
+            address,
+            payload.as_bytes(),
+            secp,
+        ))
+    }
+
+    // `<chain>:<address>[:<payload>]`, as in `from_destination`
+    pub fn destination(&self) -> String {
+        if self.payload.is_empty() {
+            format!("{}:{}", self.chain, self.address)
+        } else {
+            format!(
+                "{}:{}:{}",
+                self.chain,
+                self.address,
+                String::from_utf8_lossy(&self.payload)
+            )
+        }
+    }
+
+    pub fn destination_hash(&self) -> sha256::Hash {
+        destination_hash(&self.chain, &self.address, &self.payload)
+    }
+
+    // The unspendable leaf committing to the destination
+    pub fn commitment_script(&self) -> ScriptBuf {
+        Builder::new()
+            .push_opcode(OP_RETURN)
+            .push_slice(self.destination_hash().to_byte_array())
+            .into_script()
+    }
+
+    pub fn spend_info(&self, secp: &Secp256k1<All>) -> TaprootSpendInfo {
+        let builder = TaprootBuilder::new()
+            .add_leaf(1, self.committee_script.clone())
+            .unwrap();
+        let builder = match &self.refund {
+            None => builder.add_leaf(1, self.commitment_script()).unwrap(),
+            Some(refund) => builder
+                .add_leaf(2, self.commitment_script())
+                .unwrap()
+                .add_leaf(2, refund.script())
+                .unwrap(),
+        };
+        builder.finalize(secp, self.internal_key).unwrap()
+    }
+
+    // Control block of the committee leaf, which differs from the one of the committee's own
+    // output by the hash of the other leaves
+    pub fn control_block(&self, secp: &Secp256k1<All>) -> ControlBlock {
+        self.spend_info(secp)
+            .control_block(&(self.committee_script.clone(), LeafVersion::TapScript))
+            .unwrap()
+    }
+
+    pub fn refund_control_block(&self, secp: &Secp256k1<All>) -> Option<ControlBlock> {
+        let refund = self.refund.as_ref()?;
+        self.spend_info(secp)
+            .control_block(&(refund.script(), LeafVersion::TapScript))
+    }
+
+    pub fn to_address(&self, network: Network) -> Address {
+        Address::from_script(&self.script_pubkey, network).unwrap()
+    }
+}
+
+// Outputs of `tx` paying to one of the `deposits` addresses, as (vout, deposit address)
+pub fn find_deposits<'a>(
+    tx: &transaction::Transaction,
+    deposits: &'a [DepositAddress],
+) -> Vec<(u32, &'a DepositAddress)> {
+    tx.output
+        .iter()
+        .enumerate()
+        .filter_map(|(vout, txout)| {
+            deposits
+                .iter()
+                .find(|x| x.script_pubkey == txout.script_pubkey)
+                .map(|x| (vout as u32, x))
+        })
+        .collect()
+}
@@ -0,0 +1,215 @@
+// Output descriptor of a committee's address. The weighted multisig leaf has no miniscript
+// equivalent, so the descriptor uses a fragment of its own:
+//
+//   tr(<internal key>,weighted_multi(<threshold>,<key>:<weight>,...))#<checksum>
+//
+// with x-only keys in hex, in committee order, and the BIP380 checksum. It parses back into the
+// same (script, script_pubkey). Wallets that don't know the fragment can watch the address with
+// the equivalent `rawtr(<output key>)` descriptor.
+
+use std::{fmt, str::FromStr};
+
+use bitcoin::{key::Secp256k1, secp256k1::All, Address, Network, ScriptBuf, XOnlyPublicKey};
+use bitcoin_rs::script::MultisigScript;
+
+use crate::state::Committee;
+
+const INPUT_CHARSET: &str =
+    "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+fn polymod(c: u64, value: u64) -> u64 {
+    let c0 = c >> 35;
+    let mut c = ((c & 0x7ffffffff) << 5) ^ value;
+    for (bit, generator) in [
+        0xf5dee51989,
+        0xa9fdca3312,
+        0x1bab10e32d,
+        0x3706b1677a,
+        0x644d626ffd,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        if c0 & (1 << bit) != 0 {
+            c ^= generator;
+        }
+    }
+    c
+}
+
+// BIP380 checksum of a descriptor without its `#`. None if it has characters descriptors can't
+// contain.
+pub fn checksum(descriptor: &str) -> Option<String> {
+    let mut c = 1;
+    let mut classes = 0;
+    let mut count = 0;
+    for ch in descriptor.chars() {
+        let position = INPUT_CHARSET.find(ch)? as u64;
+        c = polymod(c, position & 31);
+        classes = classes * 3 + (position >> 5);
+        count += 1;
+        if count == 3 {
+            c = polymod(c, classes);
+            classes = 0;
+            count = 0;
+        }
+    }
+    if count > 0 {
+        c = polymod(c, classes);
+    }
+    for _ in 0..8 {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+    Some(
+        (0..8)
+            .map(|i| CHECKSUM_CHARSET[((c >> (5 * (7 - i))) & 31) as usize] as char)
+            .collect(),
+    )
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DescriptorError {
+    Checksum { expected: String, found: String },
+    Syntax(String),
+    Key(String),
+}
+
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DescriptorError::Checksum { expected, found } => {
+                write!(f, "Invalid checksum {found}, expected {expected}")
+            }
+            DescriptorError::Syntax(message) => write!(f, "Invalid descriptor: {message}"),
+            DescriptorError::Key(key) => write!(f, "Invalid key {key}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommitteeDescriptor {
+    pub internal_key: XOnlyPublicKey,
+    pub threshold: i64,
+    pub pks_weights: Vec<(XOnlyPublicKey, i64)>,
+}
+
+impl CommitteeDescriptor {
+    pub fn new(committee: &Committee) -> CommitteeDescriptor {
+        CommitteeDescriptor {
+            internal_key: committee.internal_key,
+            threshold: committee.threshold,
+            pks_weights: committee.pks_weights(),
+        }
+    }
+
+    // Same as `Committee::scripts`
+    pub fn scripts(&self, secp: &Secp256k1<All>) -> (ScriptBuf, ScriptBuf) {
+        ScriptBuf::create_threshold_multisig_with_weights(
+            &self.pks_weights,
+            &self.internal_key,
+            self.threshold,
+            secp,
+        )
+    }
+
+    pub fn address(&self, network: Network, secp: &Secp256k1<All>) -> Address {
+        let (_, script_pubkey) = self.scripts(secp);
+        Address::from_script(&script_pubkey, network).expect("Committee outputs are P2TR")
+    }
+
+    // `rawtr` descriptor of the same output, e.g. for bitcoind's `importdescriptors`. It can't
+    // sign or be checked against the committee.
+    pub fn watch_only(&self, secp: &Secp256k1<All>) -> String {
+        let (_, script_pubkey) = self.scripts(secp);
+        let output_key = XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..])
+            .expect("Committee outputs are P2TR");
+        with_checksum(format!("rawtr({output_key})"))
+    }
+}
+
+pub fn with_checksum(descriptor: String) -> String {
+    let checksum = checksum(&descriptor).expect("Descriptors are ASCII");
+    format!("{descriptor}#{checksum}")
+}
+
+impl fmt::Display for CommitteeDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let keys = self
+            .pks_weights
+            .iter()
+            .map(|(key, weight)| format!(",{key}:{weight}"))
+            .collect::<String>();
+        let descriptor = format!(
+            "tr({},weighted_multi({}{keys}))",
+            self.internal_key, self.threshold
+        );
+        write!(f, "{}", with_checksum(descriptor))
+    }
+}
+
+fn parse_key(key: &str) -> Result<XOnlyPublicKey, DescriptorError> {
+    XOnlyPublicKey::from_str(key).map_err(|_| DescriptorError::Key(key.to_string()))
+}
+
+fn parse_number(number: &str) -> Result<i64, DescriptorError> {
+    number
+        .parse()
+        .map_err(|_| DescriptorError::Syntax(format!("{number} isn't a number")))
+}
+
+// The checksum is optional, like in bitcoind
+impl FromStr for CommitteeDescriptor {
+    type Err = DescriptorError;
+
+    fn from_str(s: &str) -> Result<CommitteeDescriptor, DescriptorError> {
+        let descriptor = match s.split_once('#') {
+            Some((descriptor, found)) => {
+                let expected = checksum(descriptor)
+                    .ok_or_else(|| DescriptorError::Syntax("unexpected characters".to_string()))?;
+                if found != expected {
+                    return Err(DescriptorError::Checksum {
+                        expected,
+                        found: found.to_string(),
+                    });
+                }
+                descriptor
+            }
+            None => s,
+        };
+
+        let syntax = |message: &str| DescriptorError::Syntax(message.to_string());
+        let inner = descriptor
+            .strip_prefix("tr(")
+            .and_then(|x| x.strip_suffix("))"))
+            .ok_or_else(|| syntax("expected tr(<internal key>,weighted_multi(...))"))?;
+        let (internal_key, multi) = inner
+            .split_once(",weighted_multi(")
+            .ok_or_else(|| syntax("expected a weighted_multi leaf"))?;
+
+        let mut arguments = multi.split(',');
+        let threshold = parse_number(arguments.next().unwrap_or_default())?;
+        let pks_weights = arguments
+            .map(|argument| {
+                let (key, weight) = argument
+                    .split_once(':')
+                    .ok_or_else(|| syntax("expected <key>:<weight>"))?;
+                Ok((parse_key(key)?, parse_number(weight)?))
+            })
+            .collect::<Result<Vec<_>, DescriptorError>>()?;
+        let total = pks_weights.iter().map(|(_, weight)| weight).sum::<i64>();
+        if pks_weights.iter().any(|(_, weight)| *weight <= 0) {
+            return Err(syntax("weights have to be positive"));
+        }
+        if threshold <= 0 || threshold > total {
+            return Err(syntax("the threshold has to be reachable"));
+        }
+
+        Ok(CommitteeDescriptor {
+            internal_key: parse_key(internal_key)?,
+            threshold,
+            pks_weights,
+        })
+    }
+}
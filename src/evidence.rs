@@ -0,0 +1,258 @@
+// Validators sign raw sighashes, so nothing stops a malicious validator from signing two
+// different transactions spending the same committee UTXO. This module keeps track of the
+// signatures imported per validator and spent outpoint, and produces self-contained evidence
+// when a validator signs transactions making different payouts with the same UTXO, so Axelar
+// governance can verify it independently (e.g. to slash the validator). A payout is an output
+// with its amount, and paying a recipient another amount is a conflict too. Signing the same
+// transaction under another sighash type, or a fee bump only lowering the committee's change,
+// isn't a conflict.
+
+use bitcoin::{
+    consensus::serde::{Hex, With},
+    key::Secp256k1,
+    secp256k1::{All, Message},
+    taproot::Signature,
+    transaction, OutPoint, ScriptBuf, TapSighash, TapSighashType, TxOut, Txid, XOnlyPublicKey,
+};
+use bitcoin_hashes::Hash;
+use serde::{Deserialize, Serialize};
+
+use crate::{state::Committee, taproot_sighash};
+
+// A validator's signature on one input of a transaction, along with everything needed to
+// recompute the sighash it signs.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SignedInput {
+    #[serde(with = "With::<Hex>")]
+    pub tx: transaction::Transaction,
+    pub input_index: usize,
+    pub prevouts: Vec<TxOut>,
+    pub signature: Signature,
+}
+
+impl SignedInput {
+    // None if the signed input can't have been signed by a validator, e.g. when it's made up
+    fn sighash(&self, script: &ScriptBuf) -> Option<TapSighash> {
+        taproot_sighash(
+            &self.tx,
+            self.input_index,
+            &self.prevouts,
+            script,
+            self.signature.sighash_type,
+        )
+        .ok()
+    }
+
+    fn outpoint(&self) -> OutPoint {
+        self.tx.input[self.input_index].previous_output
+    }
+
+    // Outputs the signature commits to, other than the ones paying the scripts it spends (e.g.
+    // the committee's change). `SINGLE` only commits to the output with the input's index.
+    pub fn payouts(&self) -> Vec<TxOut> {
+        let committed = match self.signature.sighash_type {
+            TapSighashType::Single | TapSighashType::SinglePlusAnyoneCanPay => {
+                self.tx.output.get(self.input_index).into_iter().collect()
+            }
+            _ => self.tx.output.iter().collect::<Vec<_>>(),
+        };
+        let mut payouts: Vec<TxOut> = committed
+            .into_iter()
+            .filter(|x| {
+                !self
+                    .prevouts
+                    .iter()
+                    .any(|prevout| prevout.script_pubkey == x.script_pubkey)
+            })
+            .cloned()
+            .collect();
+        payouts.sort();
+        payouts
+    }
+
+    // Whether both signatures spend the same outpoint to different payouts
+    fn conflicts_with(&self, other: &SignedInput) -> bool {
+        self.outpoint() == other.outpoint()
+            && self.tx.compute_txid() != other.tx.compute_txid()
+            && self.payouts() != other.payouts()
+    }
+}
+
+// Proof that `operator_address` signed two transactions spending `outpoint` to different
+// payouts
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConflictEvidence {
+    pub operator_address: String,
+    pub public_key: XOnlyPublicKey,
+    // Committee script whose leaf both signatures commit to
+    pub script: ScriptBuf,
+    pub outpoint: OutPoint,
+    pub first: SignedInput,
+    pub second: SignedInput,
+}
+
+impl ConflictEvidence {
+    // Checks the evidence from scratch: both signed inputs spend `outpoint`, the transactions make
+    // different payouts, and both signatures verify against the validator's key for the
+    // sighashes recomputed from the transactions & prevouts. Made-up inputs don't verify.
+    pub fn verify(&self, secp: &Secp256k1<All>) -> bool {
+        let (Some(first_sighash), Some(second_sighash)) = (
+            self.first.sighash(&self.script),
+            self.second.sighash(&self.script),
+        ) else {
+            return false;
+        };
+
+        self.first.outpoint() == self.outpoint
+            && self.first.conflicts_with(&self.second)
+            && [(&self.first, first_sighash), (&self.second, second_sighash)]
+                .iter()
+                .all(|(signed_input, sighash)| {
+                    secp.verify_schnorr(
+                        &signed_input.signature.signature,
+                        &Message::from_digest(sighash.to_byte_array()),
+                        &self.public_key,
+                    )
+                    .is_ok()
+                })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Could not serialize evidence")
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<ConflictEvidence> {
+        serde_json::from_str(json)
+    }
+}
+
+// The first signature of a validator on an input spending `outpoint`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct SignatureRecord {
+    operator_address: String,
+    outpoint: OutPoint,
+    txid: Txid,
+    input_index: usize,
+    signature: Signature,
+}
+
+// A transaction signed by the committee, with the outputs it spends
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct SignedTx {
+    #[serde(with = "With::<Hex>")]
+    tx: transaction::Transaction,
+    prevouts: Vec<TxOut>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EvidenceLog {
+    transactions: Vec<SignedTx>,
+    signatures: Vec<SignatureRecord>,
+    pub conflicts: Vec<ConflictEvidence>,
+}
+
+impl EvidenceLog {
+    fn signed_input(&self, record: &SignatureRecord) -> SignedInput {
+        let signed_tx = self
+            .transactions
+            .iter()
+            .find(|x| x.tx.compute_txid() == record.txid)
+            .expect("Signatures are recorded with their transaction");
+        SignedInput {
+            tx: signed_tx.tx.clone(),
+            input_index: record.input_index,
+            prevouts: signed_tx.prevouts.clone(),
+            signature: record.signature,
+        }
+    }
+
+    // Records the signatures of `committee` on `tx` (one vector per input, in committee order,
+    // as returned by `collect_signatures`), which can be recorded again as more come in.
+    // Signatures that don't verify can't be attributed to the validator and are ignored.
+    // Returns the number of new conflicts.
+    pub fn record_signatures(
+        &mut self,
+        tx: &transaction::Transaction,
+        prevouts: &[TxOut],
+        committee: &Committee,
+        committee_signatures: &[Vec<Option<Signature>>],
+        secp: &Secp256k1<All>,
+    ) -> usize {
+        let conflicts_before = self.conflicts.len();
+        let (script, _) = committee.scripts(secp);
+        let txid = tx.compute_txid();
+
+        for (input_index, signatures) in committee_signatures.iter().enumerate() {
+            for (signature, member) in signatures.iter().zip(&committee.members) {
+                let Some(signature) = signature else {
+                    continue;
+                };
+                let signed_input = SignedInput {
+                    tx: tx.clone(),
+                    input_index,
+                    prevouts: prevouts.to_vec(),
+                    signature: *signature,
+                };
+                let Some(sighash) = signed_input.sighash(&script) else {
+                    continue;
+                };
+                let msg = Message::from_digest(sighash.to_byte_array());
+                if secp
+                    .verify_schnorr(&signature.signature, &msg, &member.public_key)
+                    .is_err()
+                {
+                    continue;
+                }
+
+                if !self
+                    .transactions
+                    .iter()
+                    .any(|x| x.tx.compute_txid() == txid)
+                {
+                    self.transactions.push(SignedTx {
+                        tx: tx.clone(),
+                        prevouts: prevouts.to_vec(),
+                    });
+                }
+                let outpoint = signed_input.outpoint();
+                let Some(first) = self.signatures.iter().find(|x| {
+                    x.operator_address == member.operator_address && x.outpoint == outpoint
+                }) else {
+                    self.signatures.push(SignatureRecord {
+                        operator_address: member.operator_address.clone(),
+                        outpoint,
+                        txid,
+                        input_index,
+                        signature: *signature,
+                    });
+                    continue;
+                };
+
+                let first = self.signed_input(first);
+                let known = self.conflicts.iter().any(|x| {
+                    x.operator_address == member.operator_address
+                        && x.outpoint == outpoint
+                        && x.second.tx.compute_txid() == txid
+                });
+                if known || !first.conflicts_with(&signed_input) {
+                    continue;
+                }
+                self.conflicts.push(ConflictEvidence {
+                    operator_address: member.operator_address.clone(),
+                    public_key: member.public_key,
+                    script: script.clone(),
+                    outpoint,
+                    first,
+                    second: signed_input,
+                });
+            }
+        }
+
+        self.conflicts.len() - conflicts_before
+    }
+
+    // Evidence bundle with all the conflicts detected so far
+    pub fn export(&self) -> String {
+        serde_json::to_string_pretty(&self.conflicts).expect("Could not serialize evidence")
+    }
+}
@@ -0,0 +1,783 @@
+// EXPERIMENTAL: FROST threshold signing (https://datatracker.ietf.org/doc/rfc9591/) over
+// secp256k1, producing BIP340 signatures for a taproot key-path spend of the committee
+// output. Compared to the weighted script multisig, a spend costs a single signature
+// regardless of the committee size.
+//
+// FROST has no notion of weights, so weighted voting is emulated by handing each
+// validator a number of shares proportional to its (rescaled) weight, see `allocate_shares`.
+// Every share is a separate FROST participant with its own non-zero identifier.
+//
+// All rounds are plain functions over plain data, so the whole protocol can be run
+// in-process (`run_dkg` & `collect_frost_signatures`) or over any transport.
+
+use std::collections::BTreeMap;
+
+use bitcoin::{
+    hashes::{sha256, Hash, HashEngine},
+    key::{rand, Secp256k1},
+    secp256k1::{schnorr, All, Message, Parity, PublicKey, Scalar, SecretKey},
+    sighash::{Prevouts, SighashCache},
+    taproot::{self, TapTweakHash},
+    transaction, ScriptBuf, TapSighash, TapSighashType, TxOut, Witness, XOnlyPublicKey,
+};
+
+use crate::validator::Validator;
+
+// secp256k1 group order minus 2, used for inversion through Fermat's little theorem
+const ORDER_MINUS_TWO: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+    0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x3f,
+];
+// 2^256 - group order, added (mod 2^256) to reduce a 256-bit hash that exceeds the order
+const ORDER_COMPLEMENT: [u8; 32] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0x45, 0x51, 0x23, 0x19, 0x50, 0xb7, 0x5f,
+    0xc4, 0x40, 0x2d, 0xa1, 0x73, 0x2f, 0xc9, 0xbe, 0xbf,
+];
+
+#[derive(Debug)]
+pub enum FrostError {
+    // Participant's proof of knowledge of its DKG secret doesn't verify
+    InvalidProofOfKnowledge(u32),
+    // Secret share `from` -> `to` doesn't match `from`'s public commitments
+    InvalidSecretShare {
+        from: u32,
+        to: u32,
+    },
+    // Participant's DKG package doesn't commit to exactly `threshold` coefficients
+    InvalidCommitments {
+        identifier: u32,
+        commitments: usize,
+        threshold: u32,
+    },
+    // Missing DKG package or secret share from the given participant
+    MissingParticipant(u32),
+    // Signature share doesn't verify against the participant's verifying share
+    InvalidSignatureShare(u32),
+    // Fewer shares than the threshold took part in signing
+    NotEnoughSigners {
+        shares: u32,
+        threshold: u32,
+    },
+    // The aggregated signature doesn't verify against the committee output key
+    InvalidSignature,
+}
+
+// Scalar modulo the group order. `None` stands for zero, which `SecretKey` can't represent.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Fe(Option<SecretKey>);
+
+impl Fe {
+    const ZERO: Fe = Fe(None);
+
+    fn random() -> Fe {
+        Fe(Some(SecretKey::new(&mut rand::thread_rng())))
+    }
+
+    fn from_u32(value: u32) -> Fe {
+        let mut bytes = [0u8; 32];
+        bytes[28..].copy_from_slice(&value.to_be_bytes());
+        Fe(SecretKey::from_slice(&bytes).ok())
+    }
+
+    // Interprets a 32-byte hash as an integer and reduces it modulo the group order
+    fn from_hash(hash: [u8; 32]) -> Fe {
+        if hash == [0u8; 32] {
+            return Fe::ZERO;
+        }
+        if let Ok(key) = SecretKey::from_slice(&hash) {
+            return Fe(Some(key));
+        }
+        // hash >= order, so hash - order == hash + (2^256 - order) (mod 2^256)
+        let mut reduced = [0u8; 32];
+        let mut carry = 0u16;
+        for i in (0..32).rev() {
+            let sum = hash[i] as u16 + ORDER_COMPLEMENT[i] as u16 + carry;
+            reduced[i] = sum as u8;
+            carry = sum >> 8;
+        }
+        Fe(SecretKey::from_slice(&reduced).ok())
+    }
+
+    fn from_scalar(scalar: &Scalar) -> Fe {
+        Fe(SecretKey::from_slice(&scalar.to_be_bytes()).ok())
+    }
+
+    fn to_scalar(self) -> Scalar {
+        self.0.map_or(Scalar::ZERO, Scalar::from)
+    }
+
+    fn add(self, other: Fe) -> Fe {
+        match (self.0, other.0) {
+            (None, x) | (x, None) => Fe(x),
+            (Some(a), Some(b)) => Fe(a.add_tweak(&Scalar::from(b)).ok()),
+        }
+    }
+
+    fn mul(self, other: Fe) -> Fe {
+        match (self.0, other.0) {
+            (Some(a), Some(b)) => Fe(Some(
+                a.mul_tweak(&Scalar::from(b))
+                    .expect("Product of non-zero scalars modulo a prime is non-zero"),
+            )),
+            _ => Fe::ZERO,
+        }
+    }
+
+    fn negate(self) -> Fe {
+        Fe(self.0.map(SecretKey::negate))
+    }
+
+    fn sub(self, other: Fe) -> Fe {
+        self.add(other.negate())
+    }
+
+    fn invert(self) -> Fe {
+        assert!(self != Fe::ZERO, "Zero has no inverse");
+        let mut result = Fe::from_u32(1);
+        for byte in ORDER_MINUS_TWO {
+            for bit in (0..8).rev() {
+                result = result.mul(result);
+                if (byte >> bit) & 1 == 1 {
+                    result = result.mul(self);
+                }
+            }
+        }
+        result
+    }
+}
+
+// Curve point. `None` stands for the point at infinity.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Pt(Option<PublicKey>);
+
+impl Pt {
+    fn base_mul(scalar: Fe, secp: &Secp256k1<All>) -> Pt {
+        Pt(scalar.0.map(|key| PublicKey::from_secret_key(secp, &key)))
+    }
+
+    fn add(self, other: Pt) -> Pt {
+        match (self.0, other.0) {
+            (None, x) | (x, None) => Pt(x),
+            (Some(a), Some(b)) => Pt(a.combine(&b).ok()),
+        }
+    }
+
+    fn mul(self, scalar: Fe, secp: &Secp256k1<All>) -> Pt {
+        match (self.0, scalar.0) {
+            (Some(point), Some(key)) => Pt(point.mul_tweak(secp, &Scalar::from(key)).ok()),
+            _ => Pt(None),
+        }
+    }
+
+    fn has_odd_y(self) -> bool {
+        self.0.is_some_and(|point| point.serialize()[0] == 0x03)
+    }
+}
+
+fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+    let tag_hash = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag_hash.as_ref());
+    engine.input(tag_hash.as_ref());
+    for chunk in data {
+        engine.input(chunk);
+    }
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+// Number of FROST shares held by each validator (same order as the committee) and the
+// number of shares required to sign.
+pub struct ShareAllocation {
+    pub shares: Vec<u32>,
+    pub threshold: u32,
+}
+
+// Scales the validators' weights (as rescaled by `set_threshold_and_weights`) down to at
+// most `max_shares` shares in total, and the weight threshold to the matching share threshold.
+// Validators whose weight rounds down to zero shares can't take part in FROST signing.
+pub fn allocate_shares(
+    validators: &[Validator],
+    threshold: i64,
+    max_shares: u32,
+) -> ShareAllocation {
+    let total_weight = validators.iter().map(|x| x.weight as i128).sum::<i128>();
+    assert!(total_weight > 0, "Committee has no weight");
+
+    let shares = validators
+        .iter()
+        .map(|x| (x.weight as i128 * max_shares as i128 / total_weight) as u32)
+        .collect::<Vec<_>>();
+    let total_shares = shares.iter().sum::<u32>();
+    assert!(
+        total_shares > 0,
+        "max_shares is too low for the committee weights"
+    );
+
+    // Round up, so that reaching the share threshold never requires less than `threshold` weight
+    // modulo the rounding of the individual shares
+    let share_threshold =
+        (threshold as i128 * total_shares as i128 + total_weight - 1) / total_weight;
+
+    ShareAllocation {
+        shares,
+        threshold: (share_threshold as u32).clamp(1, total_shares),
+    }
+}
+
+// Public output of the DKG, known to everyone.
+#[derive(Clone)]
+pub struct FrostCommittee {
+    // Aggregate public key, normalized to even y. Used as the taproot internal key.
+    pub group_key: XOnlyPublicKey,
+    pub threshold: u32,
+    // Public counterpart of every participant's secret share, used to verify signature shares
+    pub verifying_shares: BTreeMap<u32, PublicKey>,
+    // FROST identifiers held by each validator (same order as the committee)
+    pub holders: Vec<Vec<u32>>,
+}
+
+// A participant's secret share of the group key, consistent with `FrostCommittee::group_key`
+#[derive(Clone)]
+pub struct KeyShare {
+    pub identifier: u32,
+    secret: Fe,
+}
+
+// Kept private by the participant between DKG rounds
+pub struct DkgSecretPackage {
+    pub identifier: u32,
+    coefficients: Vec<Fe>,
+}
+
+// Broadcast by every participant in the first DKG round
+#[derive(Clone)]
+pub struct DkgRound1Package {
+    pub identifier: u32,
+    // Commitments to the polynomial coefficients, constant term first
+    pub commitments: Vec<PublicKey>,
+    // Schnorr proof of knowledge of the constant term: (R, mu)
+    pub proof: (PublicKey, Scalar),
+}
+
+fn proof_of_knowledge_challenge(identifier: u32, commitment: &PublicKey, nonce: &PublicKey) -> Fe {
+    Fe::from_hash(tagged_hash(
+        "FROST/dkg-pok",
+        &[
+            &identifier.to_be_bytes(),
+            &commitment.serialize(),
+            &nonce.serialize(),
+        ],
+    ))
+}
+
+fn check_commitments(package: &DkgRound1Package, threshold: u32) -> Result<(), FrostError> {
+    if package.commitments.len() != threshold as usize {
+        return Err(FrostError::InvalidCommitments {
+            identifier: package.identifier,
+            commitments: package.commitments.len(),
+            threshold,
+        });
+    }
+    Ok(())
+}
+
+// Evaluates the public polynomial at `identifier`, i.e. the expected `share * G`
+fn evaluate_commitments(commitments: &[PublicKey], identifier: u32, secp: &Secp256k1<All>) -> Pt {
+    let x = Fe::from_u32(identifier);
+    let mut power = Fe::from_u32(1);
+    let mut result = Pt(None);
+    for commitment in commitments {
+        result = result.add(Pt(Some(*commitment)).mul(power, secp));
+        power = power.mul(x);
+    }
+    result
+}
+
+pub fn dkg_part1(
+    identifier: u32,
+    threshold: u32,
+    secp: &Secp256k1<All>,
+) -> (DkgSecretPackage, DkgRound1Package) {
+    assert!(identifier != 0, "FROST identifiers must be non-zero");
+    assert!(threshold != 0, "FROST thresholds must be non-zero");
+
+    let coefficients = (0..threshold).map(|_| Fe::random()).collect::<Vec<_>>();
+    let commitments = coefficients
+        .iter()
+        .map(|x| Pt::base_mul(*x, secp).0.unwrap())
+        .collect::<Vec<_>>();
+
+    let nonce = Fe::random();
+    let nonce_commitment = Pt::base_mul(nonce, secp).0.unwrap();
+    let challenge = proof_of_knowledge_challenge(identifier, &commitments[0], &nonce_commitment);
+    let mu = nonce.add(coefficients[0].mul(challenge));
+
+    (
+        DkgSecretPackage {
+            identifier,
+            coefficients,
+        },
+        DkgRound1Package {
+            identifier,
+            commitments,
+            proof: (nonce_commitment, mu.to_scalar()),
+        },
+    )
+}
+
+// Verifies everybody's commitments and proof of knowledge, and computes the secret shares to
+// send privately to every other participant.
+pub fn dkg_part2(
+    secret_package: &DkgSecretPackage,
+    round1_packages: &[DkgRound1Package],
+    secp: &Secp256k1<All>,
+) -> Result<BTreeMap<u32, Scalar>, FrostError> {
+    let threshold = secret_package.coefficients.len() as u32;
+    let mut shares = BTreeMap::new();
+    for package in round1_packages {
+        check_commitments(package, threshold)?;
+        let challenge = proof_of_knowledge_challenge(
+            package.identifier,
+            &package.commitments[0],
+            &package.proof.0,
+        );
+        let expected =
+            Pt(Some(package.proof.0)).add(Pt(Some(package.commitments[0])).mul(challenge, secp));
+        if Pt::base_mul(Fe::from_scalar(&package.proof.1), secp) != expected {
+            return Err(FrostError::InvalidProofOfKnowledge(package.identifier));
+        }
+
+        // Evaluate our polynomial at the recipient's identifier (Horner's method)
+        let x = Fe::from_u32(package.identifier);
+        let share = secret_package
+            .coefficients
+            .iter()
+            .rev()
+            .fold(Fe::ZERO, |acc, coefficient| acc.mul(x).add(*coefficient));
+        shares.insert(package.identifier, share.to_scalar());
+    }
+    Ok(shares)
+}
+
+// Verifies the received secret shares against the senders' commitments and combines them
+// into the participant's key share. Also returns the public output of the DKG.
+pub fn dkg_part3(
+    identifier: u32,
+    threshold: u32,
+    round1_packages: &[DkgRound1Package],
+    received_shares: &BTreeMap<u32, Scalar>,
+    secp: &Secp256k1<All>,
+) -> Result<(KeyShare, FrostCommittee), FrostError> {
+    let mut secret = Fe::ZERO;
+    let mut group_key = Pt(None);
+    for package in round1_packages {
+        check_commitments(package, threshold)?;
+        let share = Fe::from_scalar(
+            received_shares
+                .get(&package.identifier)
+                .ok_or(FrostError::MissingParticipant(package.identifier))?,
+        );
+        if Pt::base_mul(share, secp) != evaluate_commitments(&package.commitments, identifier, secp)
+        {
+            return Err(FrostError::InvalidSecretShare {
+                from: package.identifier,
+                to: identifier,
+            });
+        }
+        secret = secret.add(share);
+        group_key = group_key.add(Pt(Some(package.commitments[0])));
+    }
+
+    // BIP340 keys have even y, so if the aggregate key doesn't, everybody negates their share
+    let negate = group_key.has_odd_y();
+    let verifying_shares = round1_packages
+        .iter()
+        .map(|package| {
+            let mut verifying_share = round1_packages.iter().fold(Pt(None), |acc, x| {
+                acc.add(evaluate_commitments(
+                    &x.commitments,
+                    package.identifier,
+                    secp,
+                ))
+            });
+            if negate {
+                verifying_share = Pt(verifying_share.0.map(|x| x.negate(secp)));
+            }
+            (package.identifier, verifying_share.0.unwrap())
+        })
+        .collect();
+    if negate {
+        secret = secret.negate();
+    }
+
+    Ok((
+        KeyShare { identifier, secret },
+        FrostCommittee {
+            group_key: group_key.0.unwrap().x_only_public_key().0,
+            threshold,
+            verifying_shares,
+            holders: vec![],
+        },
+    ))
+}
+
+// Runs the DKG for the whole committee in-process. Returns the public output and the key
+// shares of all participants, grouped per validator like `FrostCommittee::holders`.
+pub fn run_dkg(
+    allocation: &ShareAllocation,
+    secp: &Secp256k1<All>,
+) -> Result<(FrostCommittee, Vec<Vec<KeyShare>>), FrostError> {
+    let mut holders = vec![];
+    let mut next_identifier = 1;
+    for shares in &allocation.shares {
+        holders.push((next_identifier..next_identifier + shares).collect::<Vec<_>>());
+        next_identifier += shares;
+    }
+
+    let (secret_packages, round1_packages): (Vec<_>, Vec<_>) = holders
+        .iter()
+        .flatten()
+        .map(|identifier| dkg_part1(*identifier, allocation.threshold, secp))
+        .unzip();
+
+    let mut received_shares: BTreeMap<u32, BTreeMap<u32, Scalar>> = BTreeMap::new();
+    for secret_package in &secret_packages {
+        for (recipient, share) in dkg_part2(secret_package, &round1_packages, secp)? {
+            received_shares
+                .entry(recipient)
+                .or_default()
+                .insert(secret_package.identifier, share);
+        }
+    }
+
+    let mut committee = None;
+    let mut key_shares = vec![];
+    for identifiers in &holders {
+        let mut validator_key_shares = vec![];
+        for identifier in identifiers {
+            let (key_share, output) = dkg_part3(
+                *identifier,
+                allocation.threshold,
+                &round1_packages,
+                &received_shares[identifier],
+                secp,
+            )?;
+            validator_key_shares.push(key_share);
+            committee = Some(output);
+        }
+        key_shares.push(validator_key_shares);
+    }
+
+    let mut committee = committee.expect("Committee has no shares");
+    committee.holders = holders;
+    Ok((committee, key_shares))
+}
+
+impl FrostCommittee {
+    // The taproot tweak applied to `group_key` for a key-path-only output
+    fn tweak(&self) -> Fe {
+        Fe::from_scalar(&TapTweakHash::from_key_and_tweak(self.group_key, None).to_scalar())
+    }
+
+    // Tweaked output key and whether it had to be negated to have even y
+    fn output_key(&self, secp: &Secp256k1<All>) -> (XOnlyPublicKey, bool) {
+        let output_key =
+            Pt(Some(self.group_key.public_key(Parity::Even))).add(Pt::base_mul(self.tweak(), secp));
+        (
+            output_key.0.unwrap().x_only_public_key().0,
+            output_key.has_odd_y(),
+        )
+    }
+
+    // Committee output spendable only through the key path, i.e. through a FROST signature
+    pub fn script_pubkey(&self, secp: &Secp256k1<All>) -> ScriptBuf {
+        ScriptBuf::new_p2tr(secp, self.group_key, None)
+    }
+
+    fn verifying_share(&self, identifier: u32) -> Result<Pt, FrostError> {
+        self.verifying_shares
+            .get(&identifier)
+            .map(|x| Pt(Some(*x)))
+            .ok_or(FrostError::MissingParticipant(identifier))
+    }
+}
+
+// Kept private by the participant between the two signing rounds. Must never be reused.
+pub struct SigningNonces {
+    pub identifier: u32,
+    hiding: Fe,
+    binding: Fe,
+}
+
+// Broadcast by every signer in the first signing round
+#[derive(Clone)]
+pub struct SigningCommitment {
+    pub identifier: u32,
+    pub hiding: PublicKey,
+    pub binding: PublicKey,
+}
+
+#[derive(Clone)]
+pub struct SignatureShare {
+    pub identifier: u32,
+    pub share: Scalar,
+}
+
+pub fn commit(key_share: &KeyShare, secp: &Secp256k1<All>) -> (SigningNonces, SigningCommitment) {
+    let (hiding, binding) = (Fe::random(), Fe::random());
+    (
+        SigningNonces {
+            identifier: key_share.identifier,
+            hiding,
+            binding,
+        },
+        SigningCommitment {
+            identifier: key_share.identifier,
+            hiding: Pt::base_mul(hiding, secp).0.unwrap(),
+            binding: Pt::base_mul(binding, secp).0.unwrap(),
+        },
+    )
+}
+
+// Everything derived from the set of commitments that both signers and the aggregator need
+struct SigningContext {
+    // Binding factor per signer
+    binding_factors: BTreeMap<u32, Fe>,
+    // Lagrange coefficient at zero per signer
+    lagrange: BTreeMap<u32, Fe>,
+    // Group nonce, normalized to even y
+    nonce: XOnlyPublicKey,
+    // Whether the nonces have to be negated for the group nonce to have even y
+    negate_nonces: bool,
+    // Whether the secret has to be negated for the output key to have even y
+    negate_key: bool,
+    challenge: Fe,
+}
+
+impl SigningContext {
+    fn new(
+        committee: &FrostCommittee,
+        commitments: &[SigningCommitment],
+        sighash: &TapSighash,
+        secp: &Secp256k1<All>,
+    ) -> Result<SigningContext, FrostError> {
+        if (commitments.len() as u32) < committee.threshold {
+            return Err(FrostError::NotEnoughSigners {
+                shares: commitments.len() as u32,
+                threshold: committee.threshold,
+            });
+        }
+
+        let mut encoded_commitments = vec![];
+        for commitment in commitments {
+            encoded_commitments.extend_from_slice(&commitment.identifier.to_be_bytes());
+            encoded_commitments.extend_from_slice(&commitment.hiding.serialize());
+            encoded_commitments.extend_from_slice(&commitment.binding.serialize());
+        }
+
+        let mut binding_factors = BTreeMap::new();
+        let mut lagrange = BTreeMap::new();
+        let mut group_nonce = Pt(None);
+        for commitment in commitments {
+            let binding_factor = Fe::from_hash(tagged_hash(
+                "FROST/rho",
+                &[
+                    &committee.group_key.serialize(),
+                    sighash.as_ref(),
+                    &encoded_commitments,
+                    &commitment.identifier.to_be_bytes(),
+                ],
+            ));
+            binding_factors.insert(commitment.identifier, binding_factor);
+            group_nonce = group_nonce
+                .add(Pt(Some(commitment.hiding)))
+                .add(Pt(Some(commitment.binding)).mul(binding_factor, secp));
+
+            let x_i = Fe::from_u32(commitment.identifier);
+            let (mut numerator, mut denominator) = (Fe::from_u32(1), Fe::from_u32(1));
+            for other in commitments {
+                if other.identifier == commitment.identifier {
+                    continue;
+                }
+                let x_j = Fe::from_u32(other.identifier);
+                numerator = numerator.mul(x_j);
+                denominator = denominator.mul(x_j.sub(x_i));
+            }
+            lagrange.insert(commitment.identifier, numerator.mul(denominator.invert()));
+        }
+
+        // The group nonce is infinity only with negligible probability, or if signers collude
+        let group_nonce_point = group_nonce.0.ok_or(FrostError::InvalidSignature)?;
+        let nonce = group_nonce_point.x_only_public_key().0;
+        let (output_key, negate_key) = committee.output_key(secp);
+        let challenge = Fe::from_hash(tagged_hash(
+            "BIP0340/challenge",
+            &[
+                &nonce.serialize(),
+                &output_key.serialize(),
+                sighash.as_ref(),
+            ],
+        ));
+
+        Ok(SigningContext {
+            binding_factors,
+            lagrange,
+            nonce,
+            negate_nonces: group_nonce.has_odd_y(),
+            negate_key,
+            challenge,
+        })
+    }
+
+    fn signed(&self, value: Fe, negate: bool) -> Fe {
+        if negate {
+            value.negate()
+        } else {
+            value
+        }
+    }
+}
+
+pub fn sign(
+    key_share: &KeyShare,
+    nonces: SigningNonces,
+    commitments: &[SigningCommitment],
+    sighash: &TapSighash,
+    committee: &FrostCommittee,
+    secp: &Secp256k1<All>,
+) -> Result<SignatureShare, FrostError> {
+    assert_eq!(key_share.identifier, nonces.identifier);
+    let context = SigningContext::new(committee, commitments, sighash, secp)?;
+
+    let binding_factor = context
+        .binding_factors
+        .get(&key_share.identifier)
+        .ok_or(FrostError::MissingParticipant(key_share.identifier))?;
+    let nonce = nonces.hiding.add(nonces.binding.mul(*binding_factor));
+    let key_part = context.lagrange[&key_share.identifier]
+        .mul(key_share.secret)
+        .mul(context.challenge);
+
+    Ok(SignatureShare {
+        identifier: key_share.identifier,
+        share: context
+            .signed(nonce, context.negate_nonces)
+            .add(context.signed(key_part, context.negate_key))
+            .to_scalar(),
+    })
+}
+
+// Verifies every signature share, so that misbehaving signers can be identified, and
+// aggregates them into a BIP340 signature for the committee output key.
+pub fn aggregate(
+    commitments: &[SigningCommitment],
+    signature_shares: &[SignatureShare],
+    sighash: &TapSighash,
+    committee: &FrostCommittee,
+    secp: &Secp256k1<All>,
+) -> Result<schnorr::Signature, FrostError> {
+    let context = SigningContext::new(committee, commitments, sighash, secp)?;
+
+    let mut z = Fe::ZERO;
+    for commitment in commitments {
+        let share = signature_shares
+            .iter()
+            .find(|x| x.identifier == commitment.identifier)
+            .ok_or(FrostError::MissingParticipant(commitment.identifier))?;
+        let share_value = Fe::from_scalar(&share.share);
+
+        let nonce = Pt(Some(commitment.hiding)).add(
+            Pt(Some(commitment.binding)).mul(context.binding_factors[&commitment.identifier], secp),
+        );
+        let nonce = if context.negate_nonces {
+            Pt(nonce.0.map(|x| x.negate(secp)))
+        } else {
+            nonce
+        };
+        let key_factor = context.signed(
+            context.lagrange[&commitment.identifier].mul(context.challenge),
+            context.negate_key,
+        );
+        let expected = nonce.add(
+            committee
+                .verifying_share(commitment.identifier)?
+                .mul(key_factor, secp),
+        );
+        if Pt::base_mul(share_value, secp) != expected {
+            return Err(FrostError::InvalidSignatureShare(commitment.identifier));
+        }
+
+        z = z.add(share_value);
+    }
+
+    // The taproot tweak is public, so the aggregator adds its contribution
+    z = z.add(context.signed(context.challenge.mul(committee.tweak()), context.negate_key));
+
+    let mut signature = [0u8; 64];
+    signature[..32].copy_from_slice(&context.nonce.serialize());
+    signature[32..].copy_from_slice(&z.to_scalar().to_be_bytes());
+    let signature = schnorr::Signature::from_slice(&signature).unwrap();
+
+    let msg = Message::from_digest(sighash.to_byte_array());
+    secp.verify_schnorr(&signature, &msg, &committee.output_key(secp).0)
+        .map_err(|_| FrostError::InvalidSignature)?;
+
+    Ok(signature)
+}
+
+// Sighashes for spending committee outputs through the key path
+pub fn key_spend_sighashes(tx: &transaction::Transaction, prevouts: &[TxOut]) -> Vec<TapSighash> {
+    let mut sighash_cache = SighashCache::new(tx);
+    (0..tx.input.len())
+        .map(|i| {
+            sighash_cache
+                .taproot_key_spend_signature_hash(
+                    i,
+                    &Prevouts::All(prevouts),
+                    TapSighashType::Default,
+                )
+                .unwrap()
+        })
+        .collect()
+}
+
+// Runs both signing rounds in-process for the given validators (indices into the committee),
+// using all of their shares. Returns one signature per sighash.
+pub fn collect_frost_signatures(
+    sighashes: &[TapSighash],
+    committee: &FrostCommittee,
+    key_shares: &[Vec<KeyShare>],
+    signers: &[usize],
+    secp: &Secp256k1<All>,
+) -> Result<Vec<schnorr::Signature>, FrostError> {
+    let signer_shares = signers
+        .iter()
+        .flat_map(|i| key_shares[*i].iter())
+        .collect::<Vec<_>>();
+
+    sighashes
+        .iter()
+        .map(|sighash| {
+            let (nonces, commitments): (Vec<_>, Vec<_>) =
+                signer_shares.iter().map(|x| commit(x, secp)).unzip();
+            let signature_shares = signer_shares
+                .iter()
+                .zip(nonces)
+                .map(|(key_share, nonces)| {
+                    sign(key_share, nonces, &commitments, sighash, committee, secp)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            aggregate(&commitments, &signature_shares, sighash, committee, secp)
+        })
+        .collect()
+}
+
+// Key-path witness: just the signature (SIGHASH_DEFAULT, so no sighash type byte)
+pub fn finalize_key_spend(tx: &mut transaction::Transaction, signatures: &[schnorr::Signature]) {
+    for (input, signature) in tx.input.iter_mut().zip(signatures) {
+        input.witness = Witness::p2tr_key_spend(&taproot::Signature {
+            signature: *signature,
+            sighash_type: TapSighashType::Default,
+        });
+    }
+}
@@ -0,0 +1,595 @@
+// In-process verification of taproot spends, so that broken handover or peg-out witnesses
+// are caught (and pinpointed) without a bitcoind's `testmempoolaccept`.
+//
+// Supports key-path spends and tapscript (BIP342) script-path spends with the opcodes used by
+// the committee & deposit scripts: pushes, flow control, stack manipulation, arithmetic &
+// comparisons, hashes, signature checks and timelocks. Scripts containing an `OP_SUCCESSx`
+// succeed unconditionally, like in consensus. Annexes, `OP_CODESEPARATOR` and future leaf
+// versions are reported as unsupported rather than interpreted.
+
+use bitcoin::{
+    hashes::{hash160, ripemd160, sha256, sha256d, Hash},
+    key::Secp256k1,
+    opcodes::{all::*, Class, ClassifyContext, Opcode},
+    script::{self, Instruction},
+    secp256k1::{All, Message},
+    sighash::{Prevouts, SighashCache},
+    taproot::{ControlBlock, LeafVersion, Signature, TapLeafHash},
+    transaction, Script, Sequence, TxOut, XOnlyPublicKey,
+};
+
+const MAX_STACK_SIZE: usize = 1000;
+const MAX_ELEMENT_SIZE: usize = 520;
+const ANNEX_TAG: u8 = 0x50;
+// BIP68 relative lock-time flags
+const SEQUENCE_DISABLE_FLAG: u32 = 1 << 31;
+const SEQUENCE_TYPE_FLAG: u32 = 1 << 22;
+const SEQUENCE_VALUE_MASK: u32 = 0xffff;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptError {
+    MissingPrevout,
+    NotTaproot,
+    EmptyWitness,
+    UnsupportedAnnex,
+    InvalidControlBlock,
+    // The script isn't committed to by the output key
+    WrongCommitment,
+    UnsupportedLeafVersion,
+    InvalidScript,
+    InvalidSignature,
+    InvalidSighashType,
+    InvalidPublicKey,
+    IllegalOpcode,
+    UnsupportedOpcode,
+    OpReturn,
+    StackUnderflow,
+    StackSize,
+    PushSize,
+    InvalidNumber,
+    // Tapscript only accepts an empty vector or 0x01 as argument of OP_IF/OP_NOTIF
+    MinimalIf,
+    UnbalancedConditional,
+    VerifyFailed,
+    UnsatisfiedLocktime,
+    // The script left a false value on the stack
+    EvalFalse,
+    // The script didn't leave exactly one element on the stack
+    CleanStack,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerificationError {
+    pub input: usize,
+    // Byte offset in the script and opcode being executed when the script failed
+    pub opcode: Option<(usize, Opcode)>,
+    // Key of the failing signature check, if the failure was caused by a signature
+    pub public_key: Option<XOnlyPublicKey>,
+    pub reason: ScriptError,
+}
+
+impl VerificationError {
+    fn new(input: usize, reason: ScriptError) -> VerificationError {
+        VerificationError {
+            input,
+            opcode: None,
+            public_key: None,
+            reason,
+        }
+    }
+
+    // Committee index of the validator whose signature caused the failure
+    pub fn validator(&self, validators_pks_weights: &[(XOnlyPublicKey, i64)]) -> Option<usize> {
+        let public_key = self.public_key?;
+        validators_pks_weights
+            .iter()
+            .position(|(x, _)| *x == public_key)
+    }
+}
+
+// Verifies all the inputs of `tx`, which must all spend taproot outputs. `prevouts` are the
+// outputs spent by the inputs, in the same order.
+pub fn verify_transaction(
+    tx: &transaction::Transaction,
+    prevouts: &[TxOut],
+    secp: &Secp256k1<All>,
+) -> Result<(), VerificationError> {
+    (0..tx.input.len()).try_for_each(|i| verify_input(tx, i, prevouts, secp))
+}
+
+pub fn verify_input(
+    tx: &transaction::Transaction,
+    input: usize,
+    prevouts: &[TxOut],
+    secp: &Secp256k1<All>,
+) -> Result<(), VerificationError> {
+    let error = |reason| VerificationError::new(input, reason);
+
+    if prevouts.len() != tx.input.len() {
+        return Err(error(ScriptError::MissingPrevout));
+    }
+    let script_pubkey = &prevouts[input].script_pubkey;
+    if !script_pubkey.is_p2tr() {
+        return Err(error(ScriptError::NotTaproot));
+    }
+    let output_key = XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..34])
+        .map_err(|_| error(ScriptError::InvalidPublicKey))?;
+
+    let mut witness = tx.input[input].witness.iter().collect::<Vec<_>>();
+    if witness.len() >= 2 && witness.last().unwrap().first() == Some(&ANNEX_TAG) {
+        return Err(error(ScriptError::UnsupportedAnnex));
+    }
+
+    let mut interpreter = Interpreter {
+        tx,
+        input,
+        prevouts,
+        secp,
+        sighash_cache: SighashCache::new(tx),
+    };
+
+    match witness.len() {
+        0 => Err(error(ScriptError::EmptyWitness)),
+        // Key path
+        1 => interpreter
+            .check_signature(witness[0], &output_key, None)
+            .and_then(|valid| match valid {
+                true => Ok(()),
+                false => Err(ScriptError::InvalidSignature),
+            })
+            .map_err(|reason| VerificationError {
+                public_key: Some(output_key),
+                ..error(reason)
+            }),
+        // Script path
+        _ => {
+            let control_block = ControlBlock::decode(witness.pop().unwrap())
+                .map_err(|_| error(ScriptError::InvalidControlBlock))?;
+            let script = Script::from_bytes(witness.pop().unwrap());
+            if !control_block.verify_taproot_commitment(secp, output_key, script) {
+                return Err(error(ScriptError::WrongCommitment));
+            }
+            if control_block.leaf_version != LeafVersion::TapScript {
+                return Err(error(ScriptError::UnsupportedLeafVersion));
+            }
+
+            let stack = witness.into_iter().map(|x| x.to_vec()).collect();
+            interpreter.execute(script, stack)
+        }
+    }
+}
+
+struct Interpreter<'a> {
+    tx: &'a transaction::Transaction,
+    input: usize,
+    prevouts: &'a [TxOut],
+    secp: &'a Secp256k1<All>,
+    sighash_cache: SighashCache<&'a transaction::Transaction>,
+}
+
+// Parses a script number of at most `max_len` bytes (4 for arithmetic, 5 for timelocks)
+fn read_number(bytes: &[u8], max_len: usize) -> Result<i64, ScriptError> {
+    if bytes.len() > max_len {
+        return Err(ScriptError::InvalidNumber);
+    }
+    let Some(last) = bytes.last() else {
+        return Ok(0);
+    };
+    let mut value = bytes
+        .iter()
+        .enumerate()
+        .fold(0i64, |acc, (i, x)| acc | ((*x as i64) << (8 * i)));
+    if last & 0x80 != 0 {
+        value &= !(0x80i64 << (8 * (bytes.len() - 1)));
+        value = -value;
+    }
+    Ok(value)
+}
+
+fn write_number(value: i64) -> Vec<u8> {
+    let mut buf = [0u8; 8];
+    let len = script::write_scriptint(&mut buf, value);
+    buf[..len].to_vec()
+}
+
+fn write_bool(value: bool) -> Vec<u8> {
+    match value {
+        true => vec![1],
+        false => vec![],
+    }
+}
+
+impl Interpreter<'_> {
+    // BIP340 signature check against the sighash of the current input. An empty signature is
+    // a valid "no" vote (Ok(false)), a non-empty invalid one fails the whole script.
+    fn check_signature(
+        &mut self,
+        signature: &[u8],
+        public_key: &XOnlyPublicKey,
+        leaf_hash: Option<TapLeafHash>,
+    ) -> Result<bool, ScriptError> {
+        if signature.is_empty() {
+            return Ok(false);
+        }
+        let signature =
+            Signature::from_slice(signature).map_err(|_| ScriptError::InvalidSignature)?;
+        let prevouts = Prevouts::All(self.prevouts);
+        let sighash = match leaf_hash {
+            None => self.sighash_cache.taproot_key_spend_signature_hash(
+                self.input,
+                &prevouts,
+                signature.sighash_type,
+            ),
+            Some(leaf_hash) => self.sighash_cache.taproot_script_spend_signature_hash(
+                self.input,
+                &prevouts,
+                leaf_hash,
+                signature.sighash_type,
+            ),
+        }
+        .map_err(|_| ScriptError::InvalidSighashType)?;
+
+        self.secp
+            .verify_schnorr(
+                &signature.signature,
+                &Message::from_digest(sighash.to_byte_array()),
+                public_key,
+            )
+            .map_err(|_| ScriptError::InvalidSignature)?;
+        Ok(true)
+    }
+
+    fn check_sequence(&self, required: i64) -> Result<(), ScriptError> {
+        if required < 0 {
+            return Err(ScriptError::UnsatisfiedLocktime);
+        }
+        let required = required as u32;
+        if required & SEQUENCE_DISABLE_FLAG != 0 {
+            return Ok(());
+        }
+
+        let sequence = self.tx.input[self.input].sequence.to_consensus_u32();
+        let mask = SEQUENCE_TYPE_FLAG | SEQUENCE_VALUE_MASK;
+        if self.tx.version.0 < 2
+            || sequence & SEQUENCE_DISABLE_FLAG != 0
+            || (sequence & SEQUENCE_TYPE_FLAG) != (required & SEQUENCE_TYPE_FLAG)
+            || (sequence & mask) < (required & mask)
+        {
+            return Err(ScriptError::UnsatisfiedLocktime);
+        }
+        Ok(())
+    }
+
+    fn check_lock_time(&self, required: i64) -> Result<(), ScriptError> {
+        if required < 0 {
+            return Err(ScriptError::UnsatisfiedLocktime);
+        }
+        let lock_time = self.tx.lock_time.to_consensus_u32() as i64;
+        // Both must be block heights or both timestamps
+        let threshold = bitcoin::absolute::LOCK_TIME_THRESHOLD as i64;
+        if (lock_time < threshold) != (required < threshold)
+            || lock_time < required
+            || self.tx.input[self.input].sequence == Sequence::MAX
+        {
+            return Err(ScriptError::UnsatisfiedLocktime);
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, script: &Script, stack: Vec<Vec<u8>>) -> Result<(), VerificationError> {
+        let leaf_hash = TapLeafHash::from_script(script, LeafVersion::TapScript);
+        let mut instructions = vec![];
+        for instruction in script.instruction_indices() {
+            let (position, instruction) = instruction
+                .map_err(|_| VerificationError::new(self.input, ScriptError::InvalidScript))?;
+            if let Instruction::Op(op) = instruction {
+                if op.classify(ClassifyContext::TapScript) == Class::SuccessOp {
+                    return Ok(());
+                }
+            }
+            instructions.push((position, instruction));
+        }
+
+        let mut stack = stack;
+        let mut exec_stack: Vec<bool> = vec![];
+        if stack.iter().any(|x| x.len() > MAX_ELEMENT_SIZE) {
+            return Err(VerificationError::new(self.input, ScriptError::PushSize));
+        }
+
+        for (position, instruction) in instructions {
+            let mut public_key = None;
+            let executing = exec_stack.iter().all(|x| *x);
+            let result = match instruction {
+                Instruction::PushBytes(bytes) => {
+                    if bytes.len() > MAX_ELEMENT_SIZE {
+                        Err(ScriptError::PushSize)
+                    } else {
+                        if executing {
+                            stack.push(bytes.as_bytes().to_vec());
+                        }
+                        Ok(())
+                    }
+                }
+                Instruction::Op(op) => self.step(
+                    op,
+                    executing,
+                    &mut stack,
+                    &mut exec_stack,
+                    &mut public_key,
+                    leaf_hash,
+                ),
+            };
+
+            let result = match stack.len() > MAX_STACK_SIZE {
+                true => result.and(Err(ScriptError::StackSize)),
+                false => result,
+            };
+            if let Err(reason) = result {
+                let opcode = match instruction {
+                    Instruction::Op(op) => op,
+                    Instruction::PushBytes(_) => script.as_bytes()[position].into(),
+                };
+                return Err(VerificationError {
+                    input: self.input,
+                    opcode: Some((position, opcode)),
+                    public_key: public_key.filter(|_| {
+                        matches!(
+                            reason,
+                            ScriptError::InvalidSignature | ScriptError::InvalidSighashType
+                        )
+                    }),
+                    reason,
+                });
+            }
+        }
+
+        let error = |reason| VerificationError::new(self.input, reason);
+        if !exec_stack.is_empty() {
+            return Err(error(ScriptError::UnbalancedConditional));
+        }
+        if stack.len() != 1 {
+            return Err(error(ScriptError::CleanStack));
+        }
+        if !script::read_scriptbool(&stack[0]) {
+            return Err(error(ScriptError::EvalFalse));
+        }
+        Ok(())
+    }
+
+    fn step(
+        &mut self,
+        op: Opcode,
+        executing: bool,
+        stack: &mut Vec<Vec<u8>>,
+        exec_stack: &mut Vec<bool>,
+        public_key: &mut Option<XOnlyPublicKey>,
+        leaf_hash: TapLeafHash,
+    ) -> Result<(), ScriptError> {
+        macro_rules! pop {
+            () => {
+                stack.pop().ok_or(ScriptError::StackUnderflow)?
+            };
+        }
+        macro_rules! pop_number {
+            () => {
+                read_number(&pop!(), 4)?
+            };
+        }
+        macro_rules! peek {
+            ($depth:expr) => {
+                stack
+                    .len()
+                    .checked_sub($depth + 1)
+                    .map(|i| stack[i].clone())
+                    .ok_or(ScriptError::StackUnderflow)?
+            };
+        }
+
+        // Flow control is evaluated even in non-executed branches
+        match op {
+            OP_IF | OP_NOTIF => {
+                let mut value = false;
+                if executing {
+                    let condition = pop!();
+                    value = match condition.as_slice() {
+                        [] => false,
+                        [1] => true,
+                        _ => return Err(ScriptError::MinimalIf),
+                    };
+                    if op == OP_NOTIF {
+                        value = !value;
+                    }
+                }
+                exec_stack.push(value);
+                return Ok(());
+            }
+            OP_ELSE => {
+                let last = exec_stack
+                    .last_mut()
+                    .ok_or(ScriptError::UnbalancedConditional)?;
+                *last = !*last;
+                return Ok(());
+            }
+            OP_ENDIF => {
+                exec_stack.pop().ok_or(ScriptError::UnbalancedConditional)?;
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        match op.classify(ClassifyContext::TapScript) {
+            Class::IllegalOp => return Err(ScriptError::IllegalOpcode),
+            _ if !executing => return Ok(()),
+            Class::PushNum(value) => {
+                stack.push(write_number(value as i64));
+                return Ok(());
+            }
+            Class::ReturnOp => return Err(ScriptError::OpReturn),
+            Class::NoOp if op != OP_CLTV && op != OP_CSV => return Ok(()),
+            _ => {}
+        }
+
+        match op {
+            OP_CSV => self.check_sequence(read_number(&peek!(0), 5)?)?,
+            OP_CLTV => self.check_lock_time(read_number(&peek!(0), 5)?)?,
+            OP_VERIFY => {
+                if !script::read_scriptbool(&pop!()) {
+                    return Err(ScriptError::VerifyFailed);
+                }
+            }
+
+            OP_DROP => {
+                pop!();
+            }
+            OP_2DROP => {
+                pop!();
+                pop!();
+            }
+            OP_DUP => stack.push(peek!(0)),
+            OP_2DUP => {
+                let (a, b) = (peek!(1), peek!(0));
+                stack.push(a);
+                stack.push(b);
+            }
+            OP_IFDUP => {
+                let top = peek!(0);
+                if script::read_scriptbool(&top) {
+                    stack.push(top);
+                }
+            }
+            OP_NIP => {
+                let top = pop!();
+                pop!();
+                stack.push(top);
+            }
+            OP_OVER => stack.push(peek!(1)),
+            OP_SWAP => {
+                let (b, a) = (pop!(), pop!());
+                stack.push(b);
+                stack.push(a);
+            }
+            OP_ROT => {
+                let (c, b, a) = (pop!(), pop!(), pop!());
+                stack.push(b);
+                stack.push(c);
+                stack.push(a);
+            }
+            OP_TUCK => {
+                let (b, a) = (pop!(), pop!());
+                stack.push(b.clone());
+                stack.push(a);
+                stack.push(b);
+            }
+            OP_DEPTH => stack.push(write_number(stack.len() as i64)),
+            OP_SIZE => stack.push(write_number(peek!(0).len() as i64)),
+
+            OP_EQUAL | OP_EQUALVERIFY => {
+                let equal = pop!() == pop!();
+                if op == OP_EQUALVERIFY {
+                    if !equal {
+                        return Err(ScriptError::VerifyFailed);
+                    }
+                } else {
+                    stack.push(write_bool(equal));
+                }
+            }
+
+            OP_1ADD | OP_1SUB | OP_NEGATE | OP_ABS | OP_NOT | OP_0NOTEQUAL => {
+                let a = pop_number!();
+                stack.push(match op {
+                    OP_1ADD => write_number(a + 1),
+                    OP_1SUB => write_number(a - 1),
+                    OP_NEGATE => write_number(-a),
+                    OP_ABS => write_number(a.abs()),
+                    OP_NOT => write_bool(a == 0),
+                    _ => write_bool(a != 0),
+                });
+            }
+            OP_ADD
+            | OP_SUB
+            | OP_BOOLAND
+            | OP_BOOLOR
+            | OP_NUMEQUAL
+            | OP_NUMEQUALVERIFY
+            | OP_NUMNOTEQUAL
+            | OP_LESSTHAN
+            | OP_GREATERTHAN
+            | OP_LESSTHANOREQUAL
+            | OP_GREATERTHANOREQUAL
+            | OP_MIN
+            | OP_MAX => {
+                let (b, a) = (pop_number!(), pop_number!());
+                let result = match op {
+                    OP_ADD => write_number(a + b),
+                    OP_SUB => write_number(a - b),
+                    OP_BOOLAND => write_bool(a != 0 && b != 0),
+                    OP_BOOLOR => write_bool(a != 0 || b != 0),
+                    OP_NUMEQUAL | OP_NUMEQUALVERIFY => write_bool(a == b),
+                    OP_NUMNOTEQUAL => write_bool(a != b),
+                    OP_LESSTHAN => write_bool(a < b),
+                    OP_GREATERTHAN => write_bool(a > b),
+                    OP_LESSTHANOREQUAL => write_bool(a <= b),
+                    OP_GREATERTHANOREQUAL => write_bool(a >= b),
+                    OP_MIN => write_number(a.min(b)),
+                    _ => write_number(a.max(b)),
+                };
+                if op == OP_NUMEQUALVERIFY {
+                    if result.is_empty() {
+                        return Err(ScriptError::VerifyFailed);
+                    }
+                } else {
+                    stack.push(result);
+                }
+            }
+            OP_WITHIN => {
+                let (max, min, x) = (pop_number!(), pop_number!(), pop_number!());
+                stack.push(write_bool(min <= x && x < max));
+            }
+
+            OP_SHA256 | OP_HASH256 | OP_RIPEMD160 | OP_HASH160 => {
+                let data = pop!();
+                stack.push(match op {
+                    OP_SHA256 => sha256::Hash::hash(&data).to_byte_array().to_vec(),
+                    OP_HASH256 => sha256d::Hash::hash(&data).to_byte_array().to_vec(),
+                    OP_RIPEMD160 => ripemd160::Hash::hash(&data).to_byte_array().to_vec(),
+                    _ => hash160::Hash::hash(&data).to_byte_array().to_vec(),
+                });
+            }
+
+            OP_CHECKSIG | OP_CHECKSIGVERIFY | OP_CHECKSIGADD => {
+                let key = pop!();
+                let n = match op {
+                    OP_CHECKSIGADD => Some(pop_number!()),
+                    _ => None,
+                };
+                let signature = pop!();
+
+                let valid = match key.len() {
+                    0 => return Err(ScriptError::InvalidPublicKey),
+                    32 => {
+                        let key = XOnlyPublicKey::from_slice(&key)
+                            .map_err(|_| ScriptError::InvalidPublicKey)?;
+                        *public_key = Some(key);
+                        self.check_signature(&signature, &key, Some(leaf_hash))?
+                    }
+                    // Unknown public key types are reserved for upgrades and always succeed
+                    _ => !signature.is_empty(),
+                };
+
+                match op {
+                    OP_CHECKSIG => stack.push(write_bool(valid)),
+                    OP_CHECKSIGADD => stack.push(write_number(n.unwrap() + valid as i64)),
+                    _ => {
+                        if !valid {
+                            return Err(ScriptError::VerifyFailed);
+                        }
+                    }
+                }
+            }
+
+            _ => return Err(ScriptError::UnsupportedOpcode),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,357 @@
+// Double-entry journal of the BTC flowing through the committee. Every entry moves an amount from
+// one account to another, so the balances always add up to zero, and the committee's balance per
+// script has to match its unspent UTXOs. Entries are keyed by the txid that moves the funds, and
+// recorded when the transaction is created, like the UTXOs in the state.
+
+use std::fmt;
+
+use bitcoin::{Address, Amount, Network, ScriptBuf, SignedAmount, Transaction, TxOut, Txid};
+use serde::{Deserialize, Serialize};
+
+use crate::{protocol_fee::PayoutAccounting, Utxo};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Account {
+    // Senders of peg-ins, and receivers of refunds
+    Depositors,
+    // Receivers of peg-outs
+    Recipients,
+    Miners,
+    // Outputs locked by a committee script or deposit address
+    Committee(ScriptBuf),
+    // Whoever spent committee UTXOs without the CLI, e.g. with another wallet
+    Unaccounted,
+}
+
+impl Account {
+    pub fn name(&self, network: Network) -> String {
+        match self {
+            Account::Depositors => "depositors".to_string(),
+            Account::Recipients => "recipients".to_string(),
+            Account::Miners => "miners".to_string(),
+            Account::Unaccounted => "unaccounted".to_string(),
+            Account::Committee(script_pubkey) => match Address::from_script(script_pubkey, network)
+            {
+                Ok(address) => format!("committee:{address}"),
+                Err(_) => format!("committee:{}", script_pubkey.to_hex_string()),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EntryKind {
+    Deposit,
+    // Gross amount of a withdrawal, before the fees the recipient pays
+    Payout,
+    MinerFee,
+    // Paid by the recipient of a payout to the committee
+    ProtocolFee,
+    Handover,
+    Change,
+    Refund,
+    // An input of another committee script than the transaction's first input
+    Sweep,
+    // A UTXO spent by a transaction that the CLI didn't create
+    ExternalSpend,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub txid: Txid,
+    // Output of the transaction, if the entry is about one
+    pub vout: Option<u32>,
+    pub kind: EntryKind,
+    pub from: Account,
+    pub to: Account,
+    pub amount: Amount,
+    // Unix time of the entry
+    pub time: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LedgerError {
+    // The committee's balance of a script differs from its UTXOs
+    Unbalanced {
+        script_pubkey: ScriptBuf,
+        balance: SignedAmount,
+        utxos: Amount,
+    },
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LedgerError::Unbalanced {
+                script_pubkey,
+                balance,
+                utxos,
+            } => write!(
+                f,
+                "The ledger has {} sats on {}, its UTXOs {}",
+                balance.to_sat(),
+                script_pubkey.to_hex_string(),
+                utxos.to_sat()
+            ),
+        }
+    }
+}
+
+// (vout, kind, from, to, amount) of an entry of a transaction
+type Transfer = (Option<u32>, EntryKind, Account, Account, Amount);
+
+// Sweeps the inputs of other scripts into the script of the first input, which pays for
+// everything. Returns that script.
+fn sweeps(prevouts: &[TxOut], transfers: &mut Vec<Transfer>) -> ScriptBuf {
+    let spender = prevouts
+        .first()
+        .expect("A committee transaction has inputs")
+        .script_pubkey
+        .clone();
+    for prevout in prevouts.iter().filter(|x| x.script_pubkey != spender) {
+        transfers.push((
+            None,
+            EntryKind::Sweep,
+            Account::Committee(prevout.script_pubkey.clone()),
+            Account::Committee(spender.clone()),
+            prevout.value,
+        ));
+    }
+    spender
+}
+
+// The part of the transaction's fee that isn't in `transfers` yet, paid by `spender`
+fn miner_fee(
+    tx: &Transaction,
+    prevouts: &[TxOut],
+    spender: &ScriptBuf,
+    transfers: &mut Vec<Transfer>,
+) {
+    let spent = prevouts.iter().map(|x| x.value).sum::<Amount>();
+    let paid = tx.output.iter().map(|x| x.value).sum::<Amount>();
+    let recorded = transfers
+        .iter()
+        .filter(|(_, kind, ..)| *kind == EntryKind::MinerFee)
+        .map(|(.., amount)| *amount)
+        .sum::<Amount>();
+    let fee = spent - paid - recorded;
+    if fee > Amount::ZERO {
+        transfers.push((
+            None,
+            EntryKind::MinerFee,
+            Account::Committee(spender.clone()),
+            Account::Miners,
+            fee,
+        ));
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Ledger {
+    pub entries: Vec<Entry>,
+}
+
+impl Ledger {
+    fn record(&mut self, txid: Txid, transfers: Vec<Transfer>, time: u64) {
+        self.entries.extend(
+            transfers
+                .into_iter()
+                .map(|(vout, kind, from, to, amount)| Entry {
+                    txid,
+                    vout,
+                    kind,
+                    from,
+                    to,
+                    amount,
+                    time,
+                }),
+        );
+    }
+
+    pub fn record_deposit(&mut self, utxo: &Utxo, time: u64) {
+        let transfer = (
+            Some(utxo.outpoint.vout),
+            EntryKind::Deposit,
+            Account::Depositors,
+            Account::Committee(utxo.txout.script_pubkey.clone()),
+            utxo.txout.value,
+        );
+        self.record(utxo.outpoint.txid, vec![transfer], time);
+    }
+
+    // The spending transaction is unknown, so the entry is keyed by the spent UTXO, like its
+    // deposit
+    pub fn record_external_spend(&mut self, utxo: &Utxo, time: u64) {
+        let transfer = (
+            Some(utxo.outpoint.vout),
+            EntryKind::ExternalSpend,
+            Account::Committee(utxo.txout.script_pubkey.clone()),
+            Account::Unaccounted,
+            utxo.txout.value,
+        );
+        self.record(utxo.outpoint.txid, vec![transfer], time);
+    }
+
+    // The first outputs pay the withdrawals in `accounting`, the others are change
+    pub fn record_peg_out(
+        &mut self,
+        tx: &Transaction,
+        prevouts: &[TxOut],
+        accounting: &[PayoutAccounting],
+        time: u64,
+    ) {
+        let mut transfers = vec![];
+        let spender = sweeps(prevouts, &mut transfers);
+        let committee = Account::Committee(spender.clone());
+        for (vout, payout) in accounting.iter().enumerate() {
+            let vout = Some(vout as u32);
+            transfers.extend([
+                (
+                    vout,
+                    EntryKind::Payout,
+                    committee.clone(),
+                    Account::Recipients,
+                    payout.gross,
+                ),
+                (
+                    vout,
+                    EntryKind::MinerFee,
+                    Account::Recipients,
+                    Account::Miners,
+                    payout.miner_fee,
+                ),
+                (
+                    vout,
+                    EntryKind::ProtocolFee,
+                    Account::Recipients,
+                    committee.clone(),
+                    payout.protocol_fee,
+                ),
+            ]);
+        }
+        for (vout, txout) in tx.output.iter().enumerate().skip(accounting.len()) {
+            transfers.push((
+                Some(vout as u32),
+                EntryKind::Change,
+                committee.clone(),
+                Account::Committee(txout.script_pubkey.clone()),
+                txout.value,
+            ));
+        }
+        miner_fee(tx, prevouts, &spender, &mut transfers);
+        self.record(tx.compute_txid(), transfers, time);
+    }
+
+    // Every output goes to the new committee
+    pub fn record_handover(&mut self, tx: &Transaction, prevouts: &[TxOut], time: u64) {
+        self.record_transfers(tx, prevouts, EntryKind::Handover, None, time);
+    }
+
+    // Every output goes back to the depositor
+    pub fn record_refund(&mut self, tx: &Transaction, prevouts: &[TxOut], time: u64) {
+        let to = Some(Account::Depositors);
+        self.record_transfers(tx, prevouts, EntryKind::Refund, to, time);
+    }
+
+    // To the committee script of each output if there is no `to`
+    fn record_transfers(
+        &mut self,
+        tx: &Transaction,
+        prevouts: &[TxOut],
+        kind: EntryKind,
+        to: Option<Account>,
+        time: u64,
+    ) {
+        let mut transfers = vec![];
+        let spender = sweeps(prevouts, &mut transfers);
+        for (vout, txout) in tx.output.iter().enumerate() {
+            transfers.push((
+                Some(vout as u32),
+                kind,
+                Account::Committee(spender.clone()),
+                to.clone()
+                    .unwrap_or_else(|| Account::Committee(txout.script_pubkey.clone())),
+                txout.value,
+            ));
+        }
+        miner_fee(tx, prevouts, &spender, &mut transfers);
+        self.record(tx.compute_txid(), transfers, time);
+    }
+
+    pub fn entries_of(&self, txid: &Txid) -> Vec<&Entry> {
+        self.entries.iter().filter(|x| x.txid == *txid).collect()
+    }
+
+    // In the order the accounts first appear. They add up to zero.
+    pub fn balances(&self) -> Vec<(Account, SignedAmount)> {
+        let mut balances: Vec<(Account, SignedAmount)> = vec![];
+        for entry in &self.entries {
+            let amount = entry.amount.to_signed().expect("Amount overflow");
+            for (account, amount) in [(&entry.from, -amount), (&entry.to, amount)] {
+                match balances.iter_mut().find(|(x, _)| x == account) {
+                    Some((_, balance)) => *balance += amount,
+                    None => balances.push((account.clone(), amount)),
+                }
+            }
+        }
+        balances
+    }
+
+    // The committee's balance of every script has to be the value of its UTXOs
+    pub fn check(&self, utxos: &[Utxo]) -> Result<(), LedgerError> {
+        let balances = self.balances();
+        let mut scripts: Vec<&ScriptBuf> = balances
+            .iter()
+            .filter_map(|(account, _)| match account {
+                Account::Committee(script_pubkey) => Some(script_pubkey),
+                _ => None,
+            })
+            .collect();
+        for utxo in utxos {
+            if !scripts.contains(&&utxo.txout.script_pubkey) {
+                scripts.push(&utxo.txout.script_pubkey);
+            }
+        }
+
+        for script_pubkey in scripts {
+            let balance = balances
+                .iter()
+                .find(|(x, _)| *x == Account::Committee(script_pubkey.clone()))
+                .map_or(SignedAmount::ZERO, |(_, balance)| *balance);
+            let unspent = utxos
+                .iter()
+                .filter(|x| x.txout.script_pubkey == *script_pubkey)
+                .map(|x| x.txout.value)
+                .sum::<Amount>();
+            if balance != unspent.to_signed().expect("Amount overflow") {
+                return Err(LedgerError::Unbalanced {
+                    script_pubkey: script_pubkey.clone(),
+                    balance,
+                    utxos: unspent,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_csv(&self, network: Network) -> String {
+        let mut csv = "txid,vout,kind,from,to,amount,time\n".to_string();
+        for entry in &self.entries {
+            csv += &format!(
+                "{},{},{:?},{},{},{},{}\n",
+                entry.txid,
+                entry.vout.map_or(String::new(), |x| x.to_string()),
+                entry.kind,
+                entry.from.name(network),
+                entry.to.name(network),
+                entry.amount.to_sat(),
+                entry.time
+            );
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.entries).expect("Could not serialize ledger")
+    }
+}
@@ -1,29 +1,94 @@
-mod validator;
-
-use std::collections::HashMap;
+pub mod broadcaster;
+pub mod config;
+pub mod deposit;
+pub mod deposit_address;
+pub mod descriptor;
+pub mod evidence;
+pub mod frost;
+pub mod interpreter;
+pub mod ledger;
+pub mod multisig_prover;
+pub mod peg_out_queue;
+pub mod protocol_fee;
+pub mod psbt;
+pub mod reserves;
+pub mod signer;
+pub mod state;
+pub mod validator;
+pub mod watch_wallet;
+
+use std::{cmp, collections::HashMap, fmt};
 
 use bitcoin::{
     bip32::Xpriv,
     key::{rand, Secp256k1},
-    secp256k1::All,
-    taproot::Signature,
-    transaction, Address, Network, OutPoint, ScriptBuf, TapSighash, TxOut,
+    script::{Instruction, PushBytes},
+    secp256k1::{All, Message},
+    sighash::{Prevouts, SighashCache, TaprootError},
+    taproot::{LeafVersion, Signature, TapLeafHash, TAPROOT_CONTROL_BASE_SIZE},
+    transaction, Address, Network, OutPoint, ScriptBuf, TapSighash, TapSighashType, TxOut, Witness,
+    XOnlyPublicKey,
 };
-use bitcoincore_rpc::{Client, RawTx, RpcApi};
-use serde::Deserialize;
-use validator::Validator;
+use bitcoin_hashes::Hash;
+use bitcoin_rs::transaction::WitnessControl;
+use bitcoincore_rpc::{Client, RpcApi};
+pub use broadcaster::SubmissionReport;
+use broadcaster::{print_reports, BitcoindBroadcaster, Broadcaster};
+use serde::{Deserialize, Serialize};
+use validator::{is_signable_sighash_type, Validator};
 
 pub const SIG_SIZE: usize = 64; // Schnorr sig size (https://github.com/bitcoin/bips/blob/master/bip-0340.mediawiki#verification)
 const REST_SCRIPT_SIZE: usize = 42; // TODO: replace with sth that isn't the answer to everything
 const FIXED_INPUT_OVERHEAD: usize = 42; // TODO: replace with sth that isn't the answer to everything
 const MAX_BTC_INT: i64 = 0x7fffffff;
+pub const MAX_OP_RETURN_DATA: usize = 80; // Default `-datacarriersize` minus the OP_RETURN & push opcodes
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Utxo {
     pub outpoint: OutPoint,
     pub txout: TxOut,
 }
 
+#[derive(Debug, Default)]
+pub struct SignatureReport {
+    // Weight of the verified signatures, per input
+    pub verified_weights: Vec<i64>,
+    // (input, validator) pairs whose signature was dropped because it didn't verify
+    pub invalid_signatures: Vec<(usize, usize)>,
+    // (input, validator) pairs without a signature
+    pub missing_signatures: Vec<(usize, usize)>,
+    // Inputs whose verified weight is below the threshold
+    pub inputs_below_threshold: Vec<usize>,
+}
+
+impl SignatureReport {
+    // Committee indices of the validators that provided at least one invalid signature
+    pub fn misbehaving_validators(&self) -> Vec<usize> {
+        let mut validators = self
+            .invalid_signatures
+            .iter()
+            .map(|(_, validator)| *validator)
+            .collect::<Vec<_>>();
+        validators.sort_unstable();
+        validators.dedup();
+        validators
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.inputs_below_threshold.is_empty()
+    }
+}
+
+// The committee leaf an input set is spent with, and the weighted keys that sign it, in
+// committee order
+#[derive(Clone, Copy)]
+pub struct MultisigSpend<'a> {
+    pub script: &'a ScriptBuf,
+    pub internal_key: &'a XOnlyPublicKey,
+    pub pks_weights: &'a [(XOnlyPublicKey, i64)],
+    pub threshold: i64,
+}
+
 #[derive(Deserialize)]
 struct BitcoinMaintainersResponse {
     maintainers: Vec<String>,
@@ -35,8 +100,13 @@ struct AllValidatorsResponse {
     data: Vec<Validator>,
 }
 
-pub fn create_op_return() -> ScriptBuf {
-    let data = b"ethereum:0x0000000000000000000000000000000000000000:foobar";
+// GMP data of a deposit: `<destination chain>:<destination address>[:<payload>]`
+pub fn create_op_return(destination: &str) -> ScriptBuf {
+    assert!(
+        destination.len() <= MAX_OP_RETURN_DATA,
+        "GMP data is longer than {MAX_OP_RETURN_DATA} bytes and won't be relayed"
+    );
+    let data = <&PushBytes>::try_from(destination.as_bytes()).unwrap();
     ScriptBuf::new_op_return(data)
 }
 
@@ -46,11 +116,20 @@ pub fn init_wallet(
     network: Network,
     wallet: &str,
 ) -> (Address, transaction::Transaction, u32) {
+    require_regtest(rpc);
+
     let random_number = rand::random::<usize>().to_string();
     let random_label = random_number.as_str();
 
-    let _ = rpc.create_wallet(&(bitcoin_dir.to_owned() + wallet), None, None, None, None);
-    let _ = rpc.load_wallet(&(bitcoin_dir.to_owned() + wallet));
+    // Creates the wallet, or loads it if it exists from a previous run
+    let wallet = bitcoin_dir.to_owned() + wallet;
+    if !rpc.list_wallets().unwrap().contains(&wallet) {
+        if let Err(create_error) = rpc.create_wallet(&wallet, None, None, None, None) {
+            rpc.load_wallet(&wallet).unwrap_or_else(|error| {
+                panic!("Could not create ({create_error}) or load ({error}) wallet {wallet}")
+            });
+        }
+    }
 
     // $ bitcoin-core.cli -rpcport=18443 -rpcpassword=1234 -regtest getnewaddress
     let address = rpc
@@ -81,50 +160,154 @@ pub fn init_wallet(
     (address, coinbase_tx, coinbase_vout)
 }
 
+// Mining and funding wallets with coinbase outputs only make sense on regtest. This asks the
+// node rather than trusting the configured network, in case the two don't match.
+pub fn require_regtest(rpc: &Client) {
+    let chain = rpc
+        .get_blockchain_info()
+        .expect("Could not get blockchain info")
+        .chain;
+    assert_eq!(
+        chain,
+        Network::Regtest,
+        "Refusing to mine on {chain}, only regtest is allowed"
+    );
+}
+
 pub fn test_and_submit(
     rpc: &Client,
     txs: Vec<transaction::Transaction>,
     miner_address: Address,
-) -> () {
-    let result =
-        rpc.test_mempool_accept(&txs.iter().map(|tx| tx.raw_hex()).collect::<Vec<String>>());
-
-    let mempool_failure = || {
-        println!("Mempool acceptance test failed. Try manually testing for mempool acceptance using the bitcoin cli for more information, with the following transactions:");
-        for (i, tx) in txs.iter().enumerate() {
-            println!("Transaction #{}: {}", i + 1, tx.raw_hex());
-        }
-    };
+) -> Vec<SubmissionReport> {
+    let reports = BitcoindBroadcaster::regtest(rpc, miner_address).broadcast(&txs);
+    print_reports(&reports, &txs);
+    reports
+}
 
-    match result {
-        Err(error) => {
-            println!("{:#?}", error);
-            mempool_failure();
-        }
-        Ok(response) => {
-            if !response[0].allowed || !response[1].allowed {
-                mempool_failure();
-                return;
-            }
+// Serialized signature size: non-default sighash types append the sighash type byte
+pub fn signature_size(sighash_type: TapSighashType) -> usize {
+    match sighash_type {
+        TapSighashType::Default => SIG_SIZE,
+        _ => SIG_SIZE + 1,
+    }
+}
+
+// Witness spending the committee `script` as its only leaf, with a signature from every member.
+// That's the largest it gets, so it's a safe stand-in to size unsigned transactions with.
+pub fn max_committee_witness(script: &ScriptBuf, sighash_type: TapSighashType) -> Witness {
+    let members = script
+        .instructions()
+        .filter(|x| matches!(x, Ok(Instruction::PushBytes(key)) if key.len() == 32))
+        .count();
+    let mut witness = Witness::new();
+    for _ in 0..members {
+        witness.push(vec![0; signature_size(sighash_type)]);
+    }
+    witness.push(script.as_bytes());
+    witness.push([0; TAPROOT_CONTROL_BASE_SIZE]);
+    witness
+}
+
+pub fn handover_input_size(sigs: usize, sighash_type: TapSighashType) -> usize {
+    // TODO: check me
+    signature_size(sighash_type) * sigs + REST_SCRIPT_SIZE + FIXED_INPUT_OVERHEAD
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SighashError {
+    // The validators don't sign it, see `is_signable_sighash_type`
+    Unsignable(TapSighashType),
+    // `SIGHASH_SINGLE` only commits to the output with the input's index, so the transaction
+    // needs exactly one output per input to have all of its outputs committed to
+    UncommittedOutputs { inputs: usize, outputs: usize },
+    // E.g. an input index or prevouts that don't match the transaction
+    Taproot(TaprootError),
+}
 
-            for (i, tx) in txs.iter().enumerate() {
-                println!(
-                    "Transaction #{}: {}",
-                    i + 1,
-                    rpc.send_raw_transaction(tx.raw_hex()).unwrap()
-                );
+impl fmt::Display for SighashError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SighashError::Unsignable(sighash_type) => {
+                write!(f, "Validators don't sign {sighash_type}")
             }
-            println!(
-                "Mined new block: {:#?}",
-                rpc.generate_to_address(1, &miner_address).unwrap()
-            );
+            SighashError::UncommittedOutputs { inputs, outputs } => write!(
+                f,
+                "SIGHASH_SINGLE needs one output per input, but there are {inputs} inputs and {outputs} outputs"
+            ),
+            SighashError::Taproot(error) => write!(f, "Could not compute the sighash: {error}"),
         }
     }
 }
 
-pub fn handover_input_size(sigs: usize) -> usize {
-    // TODO: check me
-    SIG_SIZE * sigs + REST_SCRIPT_SIZE + FIXED_INPUT_OVERHEAD
+// Whether the validators can sign the inputs of `tx` with `sighash_type`, i.e. whether their
+// signatures commit to all of its outputs
+pub fn check_sighash_type(
+    tx: &transaction::Transaction,
+    sighash_type: TapSighashType,
+) -> Result<(), SighashError> {
+    if !is_signable_sighash_type(sighash_type) {
+        return Err(SighashError::Unsignable(sighash_type));
+    }
+    if sighash_type == TapSighashType::SinglePlusAnyoneCanPay && tx.input.len() != tx.output.len() {
+        return Err(SighashError::UncommittedOutputs {
+            inputs: tx.input.len(),
+            outputs: tx.output.len(),
+        });
+    }
+    Ok(())
+}
+
+// Script-path sighash of input `input_index` of `tx`, which spends the committee `script`
+pub fn taproot_sighash(
+    tx: &transaction::Transaction,
+    input_index: usize,
+    prevouts: &[TxOut],
+    script: &ScriptBuf,
+    sighash_type: TapSighashType,
+) -> Result<TapSighash, SighashError> {
+    check_sighash_type(tx, sighash_type)?;
+    // Not every sighash type looks the input up
+    tx.tx_in(input_index)
+        .map_err(|error| SighashError::Taproot(error.into()))?;
+    script_spend_sighash(
+        &mut SighashCache::new(tx),
+        input_index,
+        prevouts,
+        script,
+        sighash_type,
+    )
+}
+
+// Script-path sighashes of all the inputs of `tx`, which all spend the committee `script`
+pub fn taproot_sighashes(
+    tx: &transaction::Transaction,
+    prevouts: &[TxOut],
+    script: &ScriptBuf,
+    sighash_type: TapSighashType,
+) -> Result<Vec<TapSighash>, SighashError> {
+    check_sighash_type(tx, sighash_type)?;
+    let mut sighash_cache = SighashCache::new(tx);
+    (0..tx.input.len())
+        .map(|i| script_spend_sighash(&mut sighash_cache, i, prevouts, script, sighash_type))
+        .collect()
+}
+
+fn script_spend_sighash(
+    sighash_cache: &mut SighashCache<&transaction::Transaction>,
+    input_index: usize,
+    prevouts: &[TxOut],
+    script: &ScriptBuf,
+    sighash_type: TapSighashType,
+) -> Result<TapSighash, SighashError> {
+    let leaf_hash = TapLeafHash::from_script(script, LeafVersion::TapScript);
+    sighash_cache
+        .taproot_script_spend_signature_hash(
+            input_index,
+            &Prevouts::All(prevouts),
+            leaf_hash,
+            sighash_type,
+        )
+        .map_err(SighashError::Taproot)
 }
 
 pub fn get_private_key(seed: usize, network: Network) -> Option<Xpriv> {
@@ -205,6 +388,7 @@ pub fn get_multisig_setup() -> (Vec<Validator>, i64) {
 
 pub fn collect_signatures(
     sighashes: &Vec<TapSighash>,
+    sighash_type: TapSighashType,
     validators: &Vec<Validator>,
     secp: &Secp256k1<All>,
 ) -> Vec<Vec<Option<Signature>>> {
@@ -213,11 +397,122 @@ pub fn collect_signatures(
     for i in 0..sighashes.len() {
         let mut committee_signatures_per_sighash = vec![];
         for validator in validators.clone() {
-            // Missing signatures should be represented with None. Order matters.
-            committee_signatures_per_sighash
-                .push(Some(validator.sign_sighash(&sighashes[i], secp)));
+            // Missing signatures should be represented with None. Order matters. Validators
+            // that refuse or fail to sign are no different from those that don't respond.
+            committee_signatures_per_sighash.push(
+                validator
+                    .sign_sighash(&sighashes[i], sighash_type, secp)
+                    .ok(),
+            );
         }
         committee_signatures.push(committee_signatures_per_sighash);
     }
     committee_signatures
 }
+
+// Verifies every collected signature against the corresponding validator's key and the
+// input's sighash, and replaces the ones that don't verify with None. A signature with a
+// different sighash type than the one the sighashes were computed with doesn't verify either.
+// Inputs without a sighash or without one signature slot per validator can't be attributed, so
+// they're below the threshold with all their signatures dropped.
+pub fn verify_signatures(
+    sighashes: &[TapSighash],
+    sighash_type: TapSighashType,
+    committee_signatures: &mut [Vec<Option<Signature>>],
+    validators_pks_weights: &[(XOnlyPublicKey, i64)],
+    threshold: i64,
+    secp: &Secp256k1<All>,
+) -> SignatureReport {
+    let mut report = SignatureReport::default();
+
+    let inputs = cmp::max(sighashes.len(), committee_signatures.len());
+    for i in 0..inputs {
+        let (Some(sighash), Some(signatures)) = (sighashes.get(i), committee_signatures.get_mut(i))
+        else {
+            report.inputs_below_threshold.push(i);
+            report.verified_weights.push(0);
+            continue;
+        };
+        if signatures.len() != validators_pks_weights.len() {
+            signatures.iter_mut().for_each(|x| *x = None);
+            report.inputs_below_threshold.push(i);
+            report.verified_weights.push(0);
+            continue;
+        }
+        let msg = Message::from_digest(sighash.to_byte_array());
+        let mut verified_weight = 0;
+
+        for (j, (signature, (public_key, weight))) in signatures
+            .iter_mut()
+            .zip(validators_pks_weights)
+            .enumerate()
+        {
+            match signature {
+                None => report.missing_signatures.push((i, j)),
+                Some(sig) => {
+                    if sig.sighash_type == sighash_type
+                        && secp
+                            .verify_schnorr(&sig.signature, &msg, public_key)
+                            .is_ok()
+                    {
+                        verified_weight += weight;
+                    } else {
+                        report.invalid_signatures.push((i, j));
+                        *signature = None;
+                    }
+                }
+            }
+        }
+
+        if verified_weight < threshold {
+            report.inputs_below_threshold.push(i);
+        }
+        report.verified_weights.push(verified_weight);
+    }
+
+    report
+}
+
+// Finalizes the witness of `tx` with the verified signatures only. Refuses to finalize if
+// the verified weight of any input is below the threshold, or if there isn't exactly one sighash
+// per input.
+pub fn finalize_verified_witness(
+    tx: &mut transaction::Transaction,
+    sighashes: &[TapSighash],
+    sighash_type: TapSighashType,
+    mut committee_signatures: Vec<Vec<Option<Signature>>>,
+    spend: MultisigSpend,
+    secp: &Secp256k1<All>,
+) -> Result<SignatureReport, SignatureReport> {
+    let mut report = verify_signatures(
+        sighashes,
+        sighash_type,
+        &mut committee_signatures,
+        spend.pks_weights,
+        spend.threshold,
+        secp,
+    );
+    // Inputs without a sighash, and sighashes without an input, are below the threshold
+    let inputs = tx.input.len();
+    for i in report.verified_weights.len()..inputs {
+        report.inputs_below_threshold.push(i);
+        report.verified_weights.push(0);
+    }
+    if report.verified_weights.len() > inputs {
+        report.inputs_below_threshold.retain(|&i| i < inputs);
+        report
+            .inputs_below_threshold
+            .extend(inputs..report.verified_weights.len());
+    }
+    if !report.is_complete() {
+        return Err(report);
+    }
+
+    tx.finalize_witness(
+        &committee_signatures,
+        spend.script,
+        spend.internal_key,
+        secp,
+    );
+    Ok(report)
+}
@@ -1,47 +1,75 @@
 use axelar_btc::{
-    collect_signatures, create_op_return, get_multisig_setup, get_private_key, handover_input_size,
-    init_wallet, test_and_submit, Utxo, SIG_SIZE,
+    collect_signatures,
+    config::{network_subdir, Config},
+    finalize_verified_witness, get_multisig_setup, get_private_key, init_wallet,
+    multisig_prover::{HandoverParams, MultisigProver},
+    state::State,
+    test_and_submit, MultisigSpend, Utxo,
 };
 use bitcoin::{
-    amount::Amount, bip32::Xpriv, key::Secp256k1, Address, Network, OutPoint, ScriptBuf,
+    amount::Amount, bip32::Xpriv, key::Secp256k1, Address, OutPoint, ScriptBuf, TapSighashType,
     Transaction, XOnlyPublicKey,
 };
 use bitcoin_rs::key::UnspendableKey;
 use bitcoin_rs::script::MultisigScript;
-use bitcoin_rs::transaction::WitnessControl;
 use bitcoincore_rpc::{Auth, Client};
-use multisig_prover::MultisigProver;
-use std::{env, path::PathBuf};
+use clap::Parser;
+use cli::{Cli, Command};
+use std::{path::Path, sync::Arc};
 use user::User;
 
-mod multisig_prover;
+mod cli;
+mod commands;
 mod user;
 
 const WALLET: &str = "wallets/default";
 const COOKIE: &str = ".cookie";
-const NETWORK: Network = Network::Regtest;
 
 fn main() {
+    let cli = Cli::parse();
+    let config = cli
+        .config()
+        .unwrap_or_else(|error| commands::fail(&error.to_string()));
+    match &cli.command {
+        Command::Demo { bitcoin_dir } => demo(&config, bitcoin_dir.as_deref()),
+        _ => {
+            let mut state = State::load(&config.state, config.network)
+                .unwrap_or_else(|error| commands::fail(&error.to_string()));
+            commands::run(&cli, &config, &mut state);
+            state.save(&config.state);
+        }
+    }
+}
+
+// Mines the coins it deposits, so it only runs on regtest
+fn demo(config: &Config, bitcoin_dir: Option<&Path>) {
+    config.require_regtest("The demo");
+    let network = config.network;
     let (mut validators, threshold) = get_multisig_setup();
 
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: cargo run <bitcoin_directory>");
-        std::process::exit(1);
-    }
-    let bitcoin_dir = args[1].to_owned() + "/regtest/";
+    let bitcoin_dir = bitcoin_dir
+        .or(config.rpc.datadir.as_deref())
+        .expect("The demo needs the bitcoin directory")
+        .join(network_subdir(network));
+    let bitcoin_dir = bitcoin_dir.to_str().unwrap().to_owned() + "/";
 
-    // Initialize RPC
+    // Initialize RPC. The demo creates its own wallet, so ignore the configured one.
     let rpc = Client::new(
-        "http://127.0.0.1:18443",
-        Auth::CookieFile(PathBuf::from(&(bitcoin_dir.to_owned() + COOKIE))),
+        &Config {
+            wallet: None,
+            ..config.clone()
+        }
+        .rpc_url(),
+        config
+            .rpc_auth()
+            .unwrap_or(Auth::CookieFile((bitcoin_dir.to_owned() + COOKIE).into())),
     )
     .unwrap();
-    let (address, coinbase_tx, coinbase_vout) = init_wallet(&bitcoin_dir, &rpc, NETWORK, WALLET);
+    let (address, coinbase_tx, coinbase_vout) = init_wallet(&bitcoin_dir, &rpc, network, WALLET);
 
     // Create validators' private keys
     for (i, validator) in validators.iter_mut().enumerate() {
-        validator.key = get_private_key(i, NETWORK);
+        validator.signer = Some(Arc::new(get_private_key(i, network).unwrap()));
     }
 
     // Store the public keys & weights of the validators
@@ -61,6 +89,13 @@ fn main() {
         &secp,
     );
 
+    let spend = MultisigSpend {
+        script: &script,
+        internal_key: &internal_key,
+        pks_weights: &validators_pks_weights,
+        threshold,
+    };
+
     // User: creates a deposit transaction
     let user_utxo = Utxo {
         outpoint: OutPoint {
@@ -72,12 +107,9 @@ fn main() {
     let peg_in = User::peg_in(user_utxo, &script_pubkey, &rpc);
 
     // Create key for recipient of withdrawal
-    let receiver_key = Xpriv::new_master(NETWORK, &[0]).unwrap();
+    let receiver_key = Xpriv::new_master(network, &[0]).unwrap();
     let receiver_pubkey = receiver_key.to_keypair(&secp).public_key();
-    let receiver_address = Address::p2pkh(
-        bitcoin::PublicKey::from(receiver_pubkey),
-        NETWORK,
-    );
+    let receiver_address = Address::p2pkh(bitcoin::PublicKey::from(receiver_pubkey), network);
 
     // Initialize MultisigProver
     let mut multisig_prover = MultisigProver {
@@ -100,22 +132,37 @@ fn main() {
     };
 
     // MultisigProver: Handover existing UTXOs to new multisig committee
-    let mut unsigned_handovers = multisig_prover.create_handover_tx(
-        2,
-        100000,
-        Amount::from_sat(1000),
-        Amount::from_sat(1),
-        &script,
-        &script_pubkey, // using the old committee again for simplicity
-    );
+    let mut unsigned_handovers = multisig_prover
+        .create_handover_tx(
+            HandoverParams {
+                max_output_no: 2,
+                max_tx_size: 100000,
+                miner_fee: Amount::from_sat(1000),
+                dust_limit: Amount::from_sat(1),
+            },
+            &script,
+            &script_pubkey, // using the old committee again for simplicity
+            TapSighashType::Default,
+        )
+        .expect("Invalid sighash type");
 
     let mut handover_txs: Vec<Transaction> = unsigned_handovers
         .iter_mut()
         .map(|(tx, sighashes)| {
             // Get signatures for the withdrawal from each member of the committee
-            let committee_signatures = collect_signatures(&sighashes, &validators, &secp);
+            let committee_signatures =
+                collect_signatures(&sighashes, TapSighashType::Default, &validators, &secp);
 
-            tx.finalize_witness(&committee_signatures, &script, &internal_key, &secp);
+            // MultisigProver: Verify signatures, drop invalid ones and finalize witness
+            finalize_verified_witness(
+                tx,
+                sighashes,
+                TapSighashType::Default,
+                committee_signatures,
+                spend,
+                &secp,
+            )
+            .unwrap_or_else(|report| panic!("Handover can't be finalized: {report:#?}"));
             tx.clone()
         })
         .collect();
@@ -139,21 +186,33 @@ fn main() {
         .collect();
 
     // MultisigProver: Creates an unsigned withdrawal transaction
-    let (mut peg_out, sighashes) = multisig_prover.create_peg_out_tx(
-        Amount::from_sat(5000),
-        vec![(
-            multisig_prover.available_utxos[0].txout.value / 2,
-            receiver_address.clone(),
-        )],
-        &script,
-        &script_pubkey,
-    );
+    let (mut peg_out, sighashes) = multisig_prover
+        .create_peg_out_tx(
+            Amount::from_sat(5000),
+            vec![(
+                multisig_prover.available_utxos[0].txout.value / 2,
+                receiver_address.clone(),
+            )],
+            &script,
+            &script_pubkey,
+            TapSighashType::Default,
+        )
+        .expect("Invalid payout");
 
     // Get signatures for the withdrawal from each member of the committee
-    let committee_signatures = collect_signatures(&sighashes, &validators, &secp);
+    let committee_signatures =
+        collect_signatures(&sighashes, TapSighashType::Default, &validators, &secp);
 
-    // MultisigProver: Collect signatures, fill in missing signatures, add control block and finalize witness
-    peg_out.finalize_witness(&committee_signatures, &script, &internal_key, &secp);
+    // MultisigProver: Verify signatures, fill in missing & invalid signatures, add control block and finalize witness
+    finalize_verified_witness(
+        &mut peg_out,
+        &sighashes,
+        TapSighashType::Default,
+        committee_signatures,
+        spend,
+        &secp,
+    )
+    .unwrap_or_else(|report| panic!("Peg-out can't be finalized: {report:#?}"));
 
     // let demo_outputs: Vec<Utxo> = vec![
     //     Utxo {
@@ -1,47 +1,150 @@
-use std::cmp;
+use std::{cmp, fmt};
 
+use crate::{
+    handover_input_size, max_committee_witness,
+    protocol_fee::{split_miner_fee, FeeSchedule, PayoutAccounting},
+    psbt::committee_psbt,
+    state::Committee,
+    taproot_sighashes, SighashError, Utxo,
+};
 use bitcoin::{
-    absolute::LockTime, script, transaction, Address, Amount, ScriptBuf, TapSighash, Weight,
-    Witness,
+    absolute::LockTime, key::Secp256k1, psbt::Psbt, script, secp256k1::All, transaction, Address,
+    Amount, ScriptBuf, TapSighash, TapSighashType, TxOut, Weight, Witness,
 };
-use bitcoin_rs::transaction::TaprootSighash;
-
-use crate::{handover_input_size, Utxo, SIG_SIZE};
 
 const PEG_IN_OUTPUT_SIZE: usize = 43; // As reported by `peg_in.output[0].size()`. TODO: double-check that this is always right
 const COMMITTEE_SIZE: usize = 75; // TODO: replace
 
-type Payouts = Vec<(Amount, Address)>;
+pub type Payouts = Vec<(Amount, Address)>;
+// Inputs, their prevouts, payout outputs and change output of a peg-out
+pub type PegOutParts = (
+    Vec<transaction::TxIn>,
+    Vec<transaction::TxOut>,
+    Vec<transaction::TxOut>,
+    Option<transaction::TxOut>,
+);
+
+// How `create_handover_tx` splits the UTXOs and what it pays the miners
+#[derive(Debug, Clone, Copy)]
+pub struct HandoverParams {
+    pub max_output_no: usize,
+    pub max_tx_size: usize,
+    pub miner_fee: Amount,
+    pub dust_limit: Amount,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PayoutError {
+    // Below the dust limit of the address type
+    Dust {
+        address: Address,
+        amount: Amount,
+        minimum: Amount,
+    },
+    // The available UTXOs can't cover the payouts and their fees
+    InsufficientFunds {
+        needed: Amount,
+        available: Amount,
+    },
+    // The validators can't sign the peg-out with the requested sighash type
+    Sighash(SighashError),
+}
+
+impl fmt::Display for PayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PayoutError::Dust {
+                address,
+                amount,
+                minimum,
+            } => write!(f, "{amount} to {address} is dust, the minimum is {minimum}"),
+            PayoutError::InsufficientFunds { needed, available } => write!(
+                f,
+                "The UTXOs hold {available}, but the payouts and their fees need {needed}"
+            ),
+            PayoutError::Sighash(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+// Every address type (P2PKH, P2SH, P2WPKH, P2WSH, P2TR and future witness versions) is standard,
+// but each has its own dust limit
+pub fn check_payout(amount: Amount, address: &Address) -> Result<(), PayoutError> {
+    let minimum = address.script_pubkey().minimal_non_dust();
+    if amount < minimum {
+        return Err(PayoutError::Dust {
+            address: address.clone(),
+            amount,
+            minimum,
+        });
+    }
+    Ok(())
+}
 
 pub struct MultisigProver {
     pub available_utxos: Vec<Utxo>,
 }
 
+// Pops UTXOs off `available` until they're worth at least `goal_value`, plus what `input_fee`
+// charges for each input. Returns the inputs, their prevouts, their total value and the final goal.
+fn select_utxos(
+    available: &mut Vec<Utxo>,
+    mut goal_value: Amount,
+    mut input_fee: impl FnMut() -> Amount,
+) -> Result<(Vec<transaction::TxIn>, Vec<TxOut>, Amount, Amount), PayoutError> {
+    let total = available.iter().map(|x| x.txout.value).sum::<Amount>();
+    let mut collected_input_value = Amount::ZERO;
+    let mut inputs = vec![];
+    let mut prevouts = vec![];
+    while collected_input_value < goal_value {
+        let utxo = available.pop().ok_or(PayoutError::InsufficientFunds {
+            needed: goal_value,
+            available: total,
+        })?;
+        collected_input_value += utxo.txout.value;
+        let txin = transaction::TxIn {
+            previous_output: utxo.outpoint,
+            script_sig: script::ScriptBuf::new(),
+            sequence: transaction::Sequence::MAX,
+            witness: Witness::default(),
+        };
+        goal_value += input_fee();
+        inputs.push(txin);
+        prevouts.push(utxo.txout);
+    }
+    Ok((inputs, prevouts, collected_input_value, goal_value))
+}
+
 impl MultisigProver {
     // Upon request for unwrapping BTC, the MultisigProver creates a peg_out transaction
     // releasing BTC from the multisig back to a recipient. This transaction will be passed
     // around the validators for signing.
     // The MultisigProver will use all the provided UTXOs for the peg_out transaction. Those
     // UTXOs might have more BTC than required for the withdrawal, so there is also a 'change'
-    // output sending the extra BTC back to the multisig.
+    // output sending the extra BTC back to the multisig, unless it would be dust.
     pub fn create_peg_out_tx(
         &mut self,
         miner_fee_per_vbyte: Amount,
         payouts: Payouts,
         script: &ScriptBuf,
         script_pubkey: &ScriptBuf,
-    ) -> (transaction::Transaction, Vec<TapSighash>) {
+        sighash_type: TapSighashType,
+    ) -> Result<(transaction::Transaction, Vec<TapSighash>), PayoutError> {
         // TODO: should take into account the maximum tx size as well and split the withdrawals to multiple
         // transctions, like the handover does.
+        for (amount, address) in &payouts {
+            check_payout(*amount, address)?;
+        }
 
-        let (inputs, prevouts, mut outputs, change_amount) =
-            self.consume_utxos(payouts, miner_fee_per_vbyte, Amount::from_sat(10));
-
-        let change_output = transaction::TxOut {
-            value: change_amount,
-            script_pubkey: script_pubkey.clone(),
-        };
-        outputs.push(change_output);
+        let available_utxos = self.available_utxos.clone();
+        let (inputs, prevouts, mut outputs, change) = self.consume_utxos(
+            payouts,
+            miner_fee_per_vbyte,
+            script,
+            script_pubkey,
+            sighash_type,
+        )?;
+        outputs.extend(change);
 
         let unsigned_peg_out_tx = transaction::Transaction {
             version: transaction::Version::TWO,
@@ -51,20 +154,143 @@ impl MultisigProver {
         };
 
         // Create sighash of peg out transaction to pass it around the validators for signing
-        let sighashes = unsigned_peg_out_tx.taproot_sighashes(prevouts.clone(), script);
+        // The UTXOs stay available if the validators can't sign it
+        let sighashes = taproot_sighashes(&unsigned_peg_out_tx, &prevouts, script, sighash_type)
+            .map_err(|error| {
+                self.available_utxos = available_utxos;
+                PayoutError::Sighash(error)
+            })?;
+
+        Ok((unsigned_peg_out_tx, sighashes))
+    }
+
+    // Peg-out where `payouts` are the withdrawn (gross) amounts. Every recipient pays the
+    // protocol fee of `schedule`, which stays with the committee as part of the change, and a
+    // share of the miner fee in proportion to its withdrawal.
+    pub fn create_fee_deducted_peg_out_tx(
+        &mut self,
+        miner_fee_per_vbyte: Amount,
+        payouts: Payouts,
+        schedule: &FeeSchedule,
+        script: &ScriptBuf,
+        script_pubkey: &ScriptBuf,
+        sighash_type: TapSighashType,
+    ) -> Result<
+        (
+            transaction::Transaction,
+            Vec<TapSighash>,
+            Vec<PayoutAccounting>,
+        ),
+        PayoutError,
+    > {
+        let protocol_fees = payouts
+            .iter()
+            .map(|(gross, _)| schedule.protocol_fee(*gross))
+            .collect::<Vec<_>>();
+        let mut outputs = vec![];
+        for ((gross, address), protocol_fee) in payouts.iter().zip(&protocol_fees) {
+            let value = gross.checked_sub(*protocol_fee).unwrap_or(Amount::ZERO);
+            check_payout(value, address)?;
+            outputs.push(transaction::TxOut {
+                value,
+                script_pubkey: address.script_pubkey(),
+            });
+        }
+
+        // The recipients pay the miner fee out of their outputs, so the inputs only need to cover
+        // the withdrawals minus the protocol fees. The UTXOs are only spent if the peg-out works
+        // out.
+        let goal_value = outputs.iter().map(|x| x.value).sum::<Amount>();
+        let mut available_utxos = self.available_utxos.clone();
+        let (inputs, prevouts, collected_input_value, _) =
+            select_utxos(&mut available_utxos, goal_value, || Amount::ZERO)?;
+        // Change too small to be relayed goes to the miners
+        let change = collected_input_value - goal_value;
+        if change >= script_pubkey.minimal_non_dust() {
+            outputs.push(transaction::TxOut {
+                value: change,
+                script_pubkey: script_pubkey.clone(),
+            });
+        }
+
+        let mut tx = transaction::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: inputs,
+            output: outputs,
+        };
+        // Sized as if every member signs every input
+        let mut signed_tx = tx.clone();
+        for input in &mut signed_tx.input {
+            input.witness = max_committee_witness(script, sighash_type);
+        }
+        let miner_fee = miner_fee_per_vbyte * signed_tx.weight().to_vbytes_ceil();
+        let grosses = payouts.iter().map(|(gross, _)| *gross).collect::<Vec<_>>();
+        let miner_fees = split_miner_fee(miner_fee, &grosses);
 
-        (unsigned_peg_out_tx, sighashes)
+        let mut accounting = vec![];
+        for (i, (gross, address)) in payouts.into_iter().enumerate() {
+            let output = &mut tx.output[i];
+            output.value = output
+                .value
+                .checked_sub(miner_fees[i])
+                .unwrap_or(Amount::ZERO);
+            check_payout(output.value, &address)?;
+            accounting.push(PayoutAccounting {
+                address,
+                gross,
+                miner_fee: miner_fees[i],
+                protocol_fee: protocol_fees[i],
+                net: output.value,
+            });
+        }
+
+        let sighashes = taproot_sighashes(&tx, &prevouts, script, sighash_type)
+            .map_err(PayoutError::Sighash)?;
+        self.available_utxos = available_utxos;
+        Ok((tx, sighashes, accounting))
+    }
+
+    // Same as `create_fee_deducted_peg_out_tx`, but as a PSBT for `committee` to sign
+    pub fn create_peg_out_psbt(
+        &mut self,
+        miner_fee_per_vbyte: Amount,
+        payouts: Payouts,
+        schedule: &FeeSchedule,
+        committee: &Committee,
+        sighash_type: TapSighashType,
+        secp: &Secp256k1<All>,
+    ) -> Result<(Psbt, Vec<PayoutAccounting>), PayoutError> {
+        let utxos = self.available_utxos.clone();
+        let (script, script_pubkey) = committee.scripts(secp);
+        let (tx, _, accounting) = self.create_fee_deducted_peg_out_tx(
+            miner_fee_per_vbyte,
+            payouts,
+            schedule,
+            &script,
+            &script_pubkey,
+            sighash_type,
+        )?;
+        let prevouts = prevouts(&tx, &utxos);
+        Ok((
+            committee_psbt(tx, &prevouts, committee, sighash_type, secp),
+            accounting,
+        ))
     }
 
     pub fn create_handover_tx(
         &self,
-        max_output_no: usize,
-        max_tx_size: usize,
-        miner_fee: Amount,
-        dust_limit: Amount,
+        params: HandoverParams,
         old_script: &ScriptBuf,
         new_script_pubkey: &ScriptBuf,
-    ) -> Vec<(transaction::Transaction, Vec<TapSighash>)> {
+        sighash_type: TapSighashType,
+    ) -> Result<Vec<(transaction::Transaction, Vec<TapSighash>)>, SighashError> {
+        let HandoverParams {
+            max_output_no,
+            max_tx_size,
+            miner_fee,
+            dust_limit,
+        } = params;
         // TODO: Maybe we should ceil the old_outputs.len() / max_output_no division to make
         // sure that we always get exactly max_output_no outputs. Consider the case of
         // old_outsputs.len() = 3, max_output_no = 2
@@ -76,7 +302,7 @@ impl MultisigProver {
         // to calculate the maximum number of validators that could be required in order to
         // achieve quorum, by summing the stakes of the smallest validators, and use that
         // to calculate the input size.
-        let input_size = handover_input_size(COMMITTEE_SIZE);
+        let input_size = handover_input_size(COMMITTEE_SIZE, sighash_type);
         let max_outputs_per_tx = max_tx_size / (fan_in * input_size + PEG_IN_OUTPUT_SIZE);
 
         let mut handover_txs = vec![];
@@ -134,65 +360,110 @@ impl MultisigProver {
         handover_txs
             .iter()
             .map(|(tx, prevouts)| {
-                (
-                    tx.clone(),
-                    tx.taproot_sighashes(prevouts.clone(), old_script),
-                )
+                let sighashes = taproot_sighashes(tx, prevouts, old_script, sighash_type)?;
+                Ok((tx.clone(), sighashes))
             })
             .collect()
     }
 
+    // Same as `create_handover_tx`, but as PSBTs for `old_committee` to sign
+    pub fn create_handover_psbts(
+        &self,
+        params: HandoverParams,
+        old_committee: &Committee,
+        new_script_pubkey: &ScriptBuf,
+        sighash_type: TapSighashType,
+        secp: &Secp256k1<All>,
+    ) -> Result<Vec<Psbt>, SighashError> {
+        let (old_script, _) = old_committee.scripts(secp);
+        Ok(self
+            .create_handover_tx(params, &old_script, new_script_pubkey, sighash_type)?
+            .into_iter()
+            .map(|(tx, _)| {
+                let prevouts = prevouts(&tx, &self.available_utxos);
+                committee_psbt(tx, &prevouts, old_committee, sighash_type, secp)
+            })
+            .collect())
+    }
+
+    // Returns the inputs spending the committee `script`, their prevouts, the payout outputs and
+    // the change output to `change_script_pubkey`, if the change is worth more than its dust
+    // limit. Otherwise the change goes to the miners. Spends no UTXOs if they aren't enough.
     pub fn consume_utxos(
         &mut self,
         payouts: Payouts, // First elements are net payments to the client after extracting our fee
         miner_fee_per_vbyte: Amount, // fee in sats per vbyte
-        dust_limit: Amount,
-    ) -> (
-        Vec<transaction::TxIn>,
-        Vec<transaction::TxOut>,
-        Vec<transaction::TxOut>,
-        Amount,
-    ) {
+        script: &ScriptBuf,
+        change_script_pubkey: &ScriptBuf,
+        sighash_type: TapSighashType,
+    ) -> Result<PegOutParts, PayoutError> {
         let input_value = payouts
             .iter()
             .fold(Amount::ZERO, |acc, (payout, _)| acc + *payout);
 
-        let outputs = payouts
+        let outputs: Vec<_> = payouts
             .iter()
             .map(|(net_payout, receiver)| transaction::TxOut {
                 value: *net_payout,
                 script_pubkey: receiver.script_pubkey(),
             })
             .collect();
+        // The transaction's fixed fields, the segwit marker & flag, and the payouts, whose sizes
+        // depend on their address types
+        let empty_tx = transaction::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![],
+        };
+        let mut weight = Weight::from_non_witness_data_size(empty_tx.base_size() as u64)
+            + Weight::from_wu(2)
+            + outputs.iter().map(|x| x.weight()).sum::<Weight>();
+        // Sized as if every member signs
+        let input_weight = transaction::TxIn {
+            witness: max_committee_witness(script, sighash_type),
+            ..Default::default()
+        }
+        .segwit_weight();
+        let mut change = transaction::TxOut {
+            value: Amount::ZERO,
+            script_pubkey: change_script_pubkey.clone(),
+        };
+        let change_fee = miner_fee_per_vbyte * change.weight().to_vbytes_ceil();
 
         // greedily add utxos until the required input_value and fees are reached
         // TODO: choose utxos more intelligently: reduce number of inputs/hit the exact input_value
-        let mut collected_input_value = Amount::ZERO;
-        let mut goal_value = input_value;
-        let mut inputs = vec![];
-        let mut prevouts = vec![];
-        while collected_input_value < goal_value {
-            let utxo = self.available_utxos.pop().expect(
-                // TODO: return Result if failing to peg_out is possible
-                &format!(
-                    "FATAL: all utxos are not enough to match input_value + fees = {goal_value}"
-                ),
-            );
-            collected_input_value += utxo.txout.value;
-            let txin = transaction::TxIn {
-                previous_output: utxo.outpoint,
-                script_sig: script::ScriptBuf::new(),
-                sequence: transaction::Sequence::MAX,
-                witness: Witness::default(),
-            };
-            goal_value += miner_fee_per_vbyte
-                * (txin.segwit_weight() + Weight::from_wu_usize(SIG_SIZE)).to_vbytes_ceil();
-            inputs.push(txin);
-            prevouts.push(utxo.txout);
-        }
+        let goal_value = input_value + miner_fee_per_vbyte * weight.to_vbytes_ceil();
+        let mut available_utxos = self.available_utxos.clone();
+        let (inputs, prevouts, collected_input_value, goal_value) =
+            select_utxos(&mut available_utxos, goal_value, || {
+                let fee = miner_fee_per_vbyte * weight.to_vbytes_ceil();
+                weight += input_weight;
+                miner_fee_per_vbyte * weight.to_vbytes_ceil() - fee
+            })?;
+        self.available_utxos = available_utxos;
 
-        let change = collected_input_value - goal_value;
+        // The change output pays for its own size
+        change.value = (collected_input_value - goal_value)
+            .checked_sub(change_fee)
+            .unwrap_or(Amount::ZERO);
+        let change = Some(change).filter(|x| x.value >= change_script_pubkey.minimal_non_dust());
 
-        (inputs, prevouts, outputs, change)
+        Ok((inputs, prevouts, outputs, change))
     }
 }
+
+// Outputs spent by `tx`, which all have to be among `utxos`
+fn prevouts(tx: &transaction::Transaction, utxos: &[Utxo]) -> Vec<TxOut> {
+    tx.input
+        .iter()
+        .map(|txin| {
+            utxos
+                .iter()
+                .find(|x| x.outpoint == txin.previous_output)
+                .expect("Spending an unknown UTXO")
+                .txout
+                .clone()
+        })
+        .collect()
+}
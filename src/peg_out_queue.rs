@@ -0,0 +1,177 @@
+// Withdrawal requests waiting for a peg-out. Requests are validated and deduplicated when they
+// are queued, and cut into batches once the batch is full, the oldest request has waited for the
+// batching window, or a deadline falls within the window. Batches take the most urgent requests
+// first (earliest deadline, then oldest), and every batch remembers the requests it paid.
+// Requests that turn out to be unpayable when their batch is built are parked with the reason,
+// so that they don't hold up the rest of the queue.
+//
+// Times are UNIX timestamps in seconds, passed by the caller.
+
+use std::fmt;
+
+use bitcoin::{address::NetworkUnchecked, Address, Amount, Network, Txid};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::BatchPolicy, multisig_prover::Payouts, protocol_fee::FeeSchedule};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PegOutRequest {
+    // Transaction of the source chain requesting the withdrawal, which identifies the request
+    pub id: String,
+    pub amount: Amount,
+    pub address: Address<NetworkUnchecked>,
+    // Time by which the peg-out should be broadcast
+    pub deadline: Option<u64>,
+    pub received_at: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PegOutBatch {
+    pub txid: Txid,
+    pub requests: Vec<PegOutRequest>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParkedRequest {
+    pub request: PegOutRequest,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueueError {
+    DuplicateRequest(String),
+    WrongNetwork {
+        address: String,
+        network: Network,
+    },
+    // The payout would be dust once the protocol fee is taken
+    DustAmount {
+        minimum: Amount,
+        protocol_fee: Amount,
+    },
+}
+
+impl fmt::Display for QueueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueueError::DuplicateRequest(id) => write!(f, "request {id} was already received"),
+            QueueError::WrongNetwork { address, network } => {
+                write!(f, "{address} isn't a {network} address")
+            }
+            QueueError::DustAmount {
+                minimum,
+                protocol_fee,
+            } => write!(
+                f,
+                "the amount must be at least {minimum} plus the {protocol_fee} protocol fee"
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PegOutQueue {
+    pub requests: Vec<PegOutRequest>,
+    // In the order they were cut
+    pub batches: Vec<PegOutBatch>,
+    #[serde(default)]
+    pub parked: Vec<ParkedRequest>,
+}
+
+impl PegOutQueue {
+    // The miner fee depends on the batch, so the payout can still end up as dust, but not because
+    // of the protocol fee alone
+    pub fn push(
+        &mut self,
+        request: PegOutRequest,
+        network: Network,
+        fees: &FeeSchedule,
+    ) -> Result<(), QueueError> {
+        if self.contains(&request.id) {
+            return Err(QueueError::DuplicateRequest(request.id));
+        }
+        let address = request
+            .address
+            .clone()
+            .require_network(network)
+            .map_err(|_| QueueError::WrongNetwork {
+                address: request.address.clone().assume_checked().to_string(),
+                network,
+            })?;
+        let minimum = address.script_pubkey().minimal_non_dust();
+        let protocol_fee = fees.protocol_fee(request.amount);
+        if request.amount < minimum + protocol_fee {
+            return Err(QueueError::DustAmount {
+                minimum,
+                protocol_fee,
+            });
+        }
+        self.requests.push(request);
+        Ok(())
+    }
+
+    // Whether the request was received, batched, parked or not
+    pub fn contains(&self, id: &str) -> bool {
+        self.requests.iter().any(|x| x.id == id)
+            || self.batch_of(id).is_some()
+            || self.parked_request(id).is_some()
+    }
+
+    // The peg-out paying the request
+    pub fn batch_of(&self, id: &str) -> Option<&PegOutBatch> {
+        self.batches
+            .iter()
+            .find(|x| x.requests.iter().any(|r| r.id == id))
+    }
+
+    pub fn parked_request(&self, id: &str) -> Option<&ParkedRequest> {
+        self.parked.iter().find(|x| x.request.id == id)
+    }
+
+    pub fn is_due(&self, policy: &BatchPolicy, now: u64) -> bool {
+        self.requests.len() >= policy.max_batch_size
+            || self.requests.iter().any(|x| {
+                now >= x.received_at + policy.batch_window
+                    || x.deadline.is_some_and(|d| now + policy.batch_window >= d)
+            })
+    }
+
+    // Removes the next batch from the queue, if one is due (or `force`d)
+    pub fn next_batch(
+        &mut self,
+        policy: &BatchPolicy,
+        now: u64,
+        force: bool,
+    ) -> Option<Vec<PegOutRequest>> {
+        if self.requests.is_empty() || !(force || self.is_due(policy, now)) {
+            return None;
+        }
+        self.requests
+            .sort_by_key(|x| (x.deadline.unwrap_or(u64::MAX), x.received_at));
+        let len = self.requests.len().min(policy.max_batch_size);
+        Some(self.requests.drain(..len).collect())
+    }
+
+    // Records the peg-out paying a batch from `next_batch`
+    pub fn record_batch(&mut self, txid: Txid, requests: Vec<PegOutRequest>) {
+        self.batches.push(PegOutBatch { txid, requests });
+    }
+
+    // Puts the requests of a batch from `next_batch` that couldn't be paid back in the queue
+    pub fn requeue(&mut self, requests: Vec<PegOutRequest>) {
+        self.requests.extend(requests);
+    }
+
+    // Takes a request out of the batches for good
+    pub fn park(&mut self, request: PegOutRequest, reason: String) {
+        self.parked.push(ParkedRequest { request, reason });
+    }
+}
+
+// Payouts of a batch, for the `MultisigProver`. The addresses were checked when queued.
+pub fn payouts(requests: &[PegOutRequest]) -> Payouts {
+    requests
+        .iter()
+        .map(|x| (x.amount, x.address.clone().assume_checked()))
+        .collect()
+}
@@ -0,0 +1,69 @@
+// Fees charged to the recipients of a peg-out. Every withdrawal pays the bridge's protocol fee,
+// which stays with the committee as part of the change, and a share of the miner fee in
+// proportion to its amount, so the committee's pool doesn't pay for the peg-outs.
+
+use std::fmt;
+
+use bitcoin::{Address, Amount};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeeSchedule {
+    // In sats, charged on every withdrawal
+    pub flat_fee: u64,
+    // In basis points of the withdrawn amount, rounded down
+    pub percentage_bps: u64,
+    // In sats, if the flat and percentage fees add up to less
+    pub minimum_fee: u64,
+}
+
+impl FeeSchedule {
+    pub fn protocol_fee(&self, gross: Amount) -> Amount {
+        let percentage = gross.to_sat() as u128 * self.percentage_bps as u128 / 10_000;
+        let fee = self.flat_fee + percentage as u64;
+        Amount::from_sat(fee.max(self.minimum_fee))
+    }
+}
+
+// How a withdrawal of `gross` ends up as a `net` payout
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayoutAccounting {
+    pub address: Address,
+    pub gross: Amount,
+    pub miner_fee: Amount,
+    pub protocol_fee: Amount,
+    pub net: Amount,
+}
+
+impl fmt::Display for PayoutAccounting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} gross, {} miner fee, {} protocol fee, {} net",
+            self.address,
+            self.gross.to_sat(),
+            self.miner_fee.to_sat(),
+            self.protocol_fee.to_sat(),
+            self.net.to_sat()
+        )
+    }
+}
+
+// Splits `miner_fee` in proportion to the amounts. The sats lost to rounding down are paid by the
+// first amounts, one each.
+pub fn split_miner_fee(miner_fee: Amount, amounts: &[Amount]) -> Vec<Amount> {
+    let total = amounts.iter().map(|x| x.to_sat() as u128).sum::<u128>();
+    if total == 0 {
+        return vec![Amount::ZERO; amounts.len()];
+    }
+    let mut shares: Vec<u64> = amounts
+        .iter()
+        .map(|x| (miner_fee.to_sat() as u128 * x.to_sat() as u128 / total) as u64)
+        .collect();
+    let remainder = miner_fee.to_sat() - shares.iter().sum::<u64>();
+    for share in shares.iter_mut().take(remainder as usize) {
+        *share += 1;
+    }
+    shares.into_iter().map(Amount::from_sat).collect()
+}
@@ -0,0 +1,304 @@
+// BIP174/BIP371 PSBTs for committee transactions, so that validators can sign with hardware
+// wallets or other standard tools instead of receiving bare sighashes. Every input spends the
+// committee's script, the single leaf of the taproot tree built by `MultisigScript`, or one of
+// the two leaves of a deposit address (see `set_deposit_leaves`).
+
+use std::{collections::BTreeMap, fmt};
+
+use bitcoin::{
+    key::Secp256k1,
+    psbt::{self, Psbt},
+    secp256k1::All,
+    sighash::InvalidSighashTypeError,
+    taproot::{ControlBlock, LeafVersion, Signature, TapLeafHash, TaprootBuilder},
+    transaction, ScriptBuf, TapSighash, TapSighashType, TxOut, Witness, XOnlyPublicKey,
+};
+
+use crate::{
+    deposit_address::DepositAddress, state::Committee, taproot_sighashes, validator::SignError,
+    verify_signatures, SighashError, SignatureReport,
+};
+
+// Why a PSBT can't be signed or finalized. PSBTs come from other parties, so nothing in them is
+// taken for granted.
+#[derive(Debug)]
+pub enum PsbtError {
+    // The input's sighash type isn't a taproot one
+    InvalidSighashType(usize, InvalidSighashTypeError),
+    MissingWitnessUtxo(usize),
+    // None of the input's leaves is the one the committee members sign
+    MissingCommitteeLeaf(usize),
+    Sighash(usize, SighashError),
+    // The validator didn't sign the input
+    Sign(usize, SignError),
+    BelowThreshold(SignatureReport),
+}
+
+impl fmt::Display for PsbtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PsbtError::InvalidSighashType(i, error) => write!(f, "Input #{i}: {error}"),
+            PsbtError::MissingWitnessUtxo(i) => write!(f, "Input #{i} has no witness UTXO"),
+            PsbtError::MissingCommitteeLeaf(i) => {
+                write!(f, "Input #{i} doesn't spend the committee leaf")
+            }
+            PsbtError::Sighash(i, error) => write!(f, "Input #{i}: {error}"),
+            PsbtError::Sign(i, error) => write!(f, "Input #{i} wasn't signed: {error}"),
+            PsbtError::BelowThreshold(report) => write!(
+                f,
+                "Inputs {:?} are below the threshold",
+                report.inputs_below_threshold
+            ),
+        }
+    }
+}
+
+// Unsigned PSBT of `tx` spending the committee's UTXOs `prevouts`, with the committee script,
+// its control block and every member's key origin, so each validator knows what to sign.
+pub fn committee_psbt(
+    tx: transaction::Transaction,
+    prevouts: &[TxOut],
+    committee: &Committee,
+    sighash_type: TapSighashType,
+    secp: &Secp256k1<All>,
+) -> Psbt {
+    assert_eq!(tx.input.len(), prevouts.len(), "Missing prevouts");
+
+    let (script, script_pubkey) = committee.scripts(secp);
+    let spend_info = TaprootBuilder::new()
+        .add_leaf(0, script.clone())
+        .unwrap()
+        .finalize(secp, committee.internal_key)
+        .unwrap();
+    assert_eq!(
+        ScriptBuf::new_p2tr_tweaked(spend_info.output_key()),
+        script_pubkey,
+        "The committee's taproot tree doesn't consist of the committee script only"
+    );
+    let control_block = spend_info
+        .control_block(&(script.clone(), LeafVersion::TapScript))
+        .unwrap();
+    let leaf_hash = TapLeafHash::from_script(&script, LeafVersion::TapScript);
+
+    let mut psbt = Psbt::from_unsigned_tx(tx).expect("The transaction is already signed");
+    for (input, prevout) in psbt.inputs.iter_mut().zip(prevouts) {
+        input.witness_utxo = Some(prevout.clone());
+        input.sighash_type = Some(sighash_type.into());
+        input.tap_internal_key = Some(committee.internal_key);
+        input.tap_merkle_root = spend_info.merkle_root();
+        input.tap_scripts = BTreeMap::from([(
+            control_block.clone(),
+            (script.clone(), LeafVersion::TapScript),
+        )]);
+        input.tap_key_origins = committee
+            .members
+            .iter()
+            .map(|x| (x.public_key, (vec![leaf_hash], x.key_source())))
+            .collect();
+    }
+
+    psbt
+}
+
+// Points the inputs spending deposit addresses at their own taproot tree. The committee leaf
+// and hence the sighashes are the same, only the merkle root and the control block differ.
+pub fn set_deposit_leaves(psbt: &mut Psbt, deposits: &[DepositAddress], secp: &Secp256k1<All>) {
+    for input in psbt.inputs.iter_mut() {
+        let Some(deposit) = input
+            .witness_utxo
+            .as_ref()
+            .and_then(|x| deposits.iter().find(|d| d.script_pubkey == x.script_pubkey))
+        else {
+            continue;
+        };
+        input.tap_merkle_root = deposit.spend_info(secp).merkle_root();
+        input.tap_scripts = BTreeMap::from([(
+            deposit.control_block(secp),
+            (deposit.committee_script.clone(), LeafVersion::TapScript),
+        )]);
+    }
+}
+
+// `SIGHASH_DEFAULT` if the input doesn't have one
+pub fn sighash_type(input: &psbt::Input) -> Result<TapSighashType, InvalidSighashTypeError> {
+    input.taproot_hash_ty()
+}
+
+// Outputs spent by the PSBT, which every taproot sighash commits to
+pub fn prevouts(psbt: &Psbt) -> Option<Vec<TxOut>> {
+    psbt.inputs.iter().map(|x| x.witness_utxo.clone()).collect()
+}
+
+// Same as `prevouts`, but tells which input has none
+pub(crate) fn checked_prevouts(psbt: &Psbt) -> Result<Vec<TxOut>, PsbtError> {
+    psbt.inputs
+        .iter()
+        .enumerate()
+        .map(|(i, x)| {
+            x.witness_utxo
+                .clone()
+                .ok_or(PsbtError::MissingWitnessUtxo(i))
+        })
+        .collect()
+}
+
+// The committee leaf spent by `input`, i.e. the leaf that its signers' key origins point at,
+// with its control block
+pub fn committee_leaf(input: &psbt::Input) -> Option<(ControlBlock, ScriptBuf, TapLeafHash)> {
+    input
+        .tap_scripts
+        .iter()
+        .map(|(control_block, (script, leaf_version))| {
+            let leaf_hash = TapLeafHash::from_script(script, *leaf_version);
+            (control_block.clone(), script.clone(), leaf_hash)
+        })
+        .find(|(_, _, leaf_hash)| {
+            input
+                .tap_key_origins
+                .values()
+                .any(|(leaf_hashes, _)| leaf_hashes.contains(leaf_hash))
+        })
+}
+
+// Script-path sighashes of every input of the PSBT for the leaf its committee members sign, with
+// the input's own sighash type. Inputs usually share the leaf and the sighash type, so the
+// sighashes of all the inputs are computed at once for each pair.
+pub fn committee_sighashes(psbt: &Psbt) -> Result<Vec<(TapSighash, TapSighashType)>, PsbtError> {
+    let prevouts = checked_prevouts(psbt)?;
+    let mut computed: Vec<((ScriptBuf, TapSighashType), Vec<TapSighash>)> = vec![];
+    let mut sighashes = vec![];
+    for (i, input) in psbt.inputs.iter().enumerate() {
+        let (_, script, _) = committee_leaf(input).ok_or(PsbtError::MissingCommitteeLeaf(i))?;
+        let sighash_type =
+            sighash_type(input).map_err(|error| PsbtError::InvalidSighashType(i, error))?;
+        let key = (script, sighash_type);
+        let sighash = match computed.iter().find(|(x, _)| *x == key) {
+            Some((_, all)) => all[i],
+            None => {
+                let all = taproot_sighashes(&psbt.unsigned_tx, &prevouts, &key.0, sighash_type)
+                    .map_err(|error| PsbtError::Sighash(i, error))?;
+                let sighash = all[i];
+                computed.push((key, all));
+                sighash
+            }
+        };
+        sighashes.push((sighash, sighash_type));
+    }
+    Ok(sighashes)
+}
+
+// Merges PSBTs of the same transaction signed by different validators
+pub fn combine_psbts(psbts: Vec<Psbt>) -> Result<Psbt, psbt::Error> {
+    let mut psbts = psbts.into_iter();
+    let mut combined = psbts.next().expect("No PSBTs to combine");
+    for psbt in psbts {
+        combined.combine(psbt)?;
+    }
+    Ok(combined)
+}
+
+// Signatures of the PSBT, per input and in committee order, as `finalize_witness` expects them
+pub fn committee_signatures(
+    psbt: &Psbt,
+    validators_pks_weights: &[(XOnlyPublicKey, i64)],
+) -> Vec<Vec<Option<Signature>>> {
+    psbt.inputs
+        .iter()
+        .map(|input| {
+            let leaf_hash = committee_leaf(input).map(|(_, _, x)| x);
+            validators_pks_weights
+                .iter()
+                .map(|(public_key, _)| {
+                    leaf_hash.and_then(|x| input.tap_script_sigs.get(&(*public_key, x)).copied())
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Adds the report of the single input `i` to `report`
+fn append_report(report: &mut SignatureReport, i: usize, input_report: SignatureReport) {
+    let reindex = |pairs: Vec<(usize, usize)>| pairs.into_iter().map(move |(_, j)| (i, j));
+    report
+        .invalid_signatures
+        .extend(reindex(input_report.invalid_signatures));
+    report
+        .missing_signatures
+        .extend(reindex(input_report.missing_signatures));
+    report
+        .inputs_below_threshold
+        .extend(input_report.inputs_below_threshold.into_iter().map(|_| i));
+    report
+        .verified_weights
+        .extend(input_report.verified_weights);
+}
+
+// Witness spending the committee `script` with `signatures`, in committee order. The script
+// checks the first member's signature first, so the signatures are pushed in reverse, and the
+// missing ones as empty signatures.
+fn committee_witness(
+    signatures: &[Option<Signature>],
+    script: &ScriptBuf,
+    control_block: &ControlBlock,
+) -> Witness {
+    let mut witness = Witness::new();
+    for signature in signatures.iter().rev() {
+        match signature {
+            Some(signature) => witness.push(signature.to_vec()),
+            None => witness.push([]),
+        }
+    }
+    witness.push(script.as_bytes());
+    witness.push(control_block.serialize());
+    witness
+}
+
+// Finalizes the witnesses with the verified signatures, like `finalize_verified_witness`, and
+// refuses to if any input is below the threshold. Every input is finalized with its own leaf,
+// control block and sighash type. As the BIP174 finalizer does, only the UTXOs and the final
+// witnesses are kept, so the transaction can be extracted with `extract_tx`.
+pub fn finalize_psbt(
+    psbt: &mut Psbt,
+    validators_pks_weights: &[(XOnlyPublicKey, i64)],
+    threshold: i64,
+    secp: &Secp256k1<All>,
+) -> Result<SignatureReport, PsbtError> {
+    let sighashes = committee_sighashes(psbt)?;
+    let signatures = committee_signatures(psbt, validators_pks_weights);
+    let mut report = SignatureReport::default();
+    let mut witnesses = vec![];
+
+    for (i, ((input, mut signatures), (sighash, sighash_type))) in psbt
+        .inputs
+        .iter()
+        .zip(signatures)
+        .zip(sighashes)
+        .enumerate()
+    {
+        let input_report = verify_signatures(
+            &[sighash],
+            sighash_type,
+            std::slice::from_mut(&mut signatures),
+            validators_pks_weights,
+            threshold,
+            secp,
+        );
+        append_report(&mut report, i, input_report);
+        let (control_block, script, _) =
+            committee_leaf(input).ok_or(PsbtError::MissingCommitteeLeaf(i))?;
+        witnesses.push(committee_witness(&signatures, &script, &control_block));
+    }
+    if !report.is_complete() {
+        return Err(PsbtError::BelowThreshold(report));
+    }
+
+    for (input, witness) in psbt.inputs.iter_mut().zip(witnesses) {
+        *input = psbt::Input {
+            witness_utxo: input.witness_utxo.take(),
+            final_script_witness: Some(witness),
+            ..Default::default()
+        };
+    }
+
+    Ok(report)
+}
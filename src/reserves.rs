@@ -0,0 +1,315 @@
+// Proof of reserves: how much BTC the committee holds, per script, checked against bitcoind. The
+// report is signed by the committee members over its hash, so auditors can check it offline
+// against the wrapped BTC outstanding on Axelar.
+
+use std::fmt;
+
+use bitcoin::{
+    hashes::{sha256, Hash, HashEngine},
+    key::Secp256k1,
+    secp256k1::{schnorr, All, Message},
+    Amount, Network, OutPoint, ScriptBuf, Txid, XOnlyPublicKey,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    signer::SignerError,
+    state::{Committee, State, TxKind},
+    validator::Validator,
+};
+
+// Domain separation from sighashes, which the committee members sign with the same keys
+const REPORT_TAG: &str = "axelar-btc/reserves";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UtxoStatus {
+    Confirmed { confirmations: u32 },
+    // In bitcoind's mempool
+    Unconfirmed,
+    // Output of a committee transaction that isn't broadcast yet
+    Unbroadcast,
+    // Spent, or unknown to bitcoind
+    Missing,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReserveUtxo {
+    pub outpoint: OutPoint,
+    pub value: Amount,
+    pub status: UtxoStatus,
+}
+
+// The committee's UTXOs locked by one script, i.e. its multisig or a deposit address
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScriptReserves {
+    pub script_pubkey: ScriptBuf,
+    pub utxos: Vec<ReserveUtxo>,
+    pub confirmed: Amount,
+    // In the mempool or not broadcast yet
+    pub unconfirmed: Amount,
+    pub missing: Amount,
+    // Part of `unconfirmed` deposited by peg-ins
+    pub pending_peg_ins: Amount,
+    // Paid out of the script's UTXOs by peg-outs that aren't broadcast yet, i.e. still being
+    // signed or finalized. They are already spent from `utxos`.
+    pub unbroadcast_peg_outs: Amount,
+}
+
+impl ScriptReserves {
+    fn new(script_pubkey: ScriptBuf) -> ScriptReserves {
+        ScriptReserves {
+            script_pubkey,
+            utxos: vec![],
+            confirmed: Amount::ZERO,
+            unconfirmed: Amount::ZERO,
+            missing: Amount::ZERO,
+            pending_peg_ins: Amount::ZERO,
+            unbroadcast_peg_outs: Amount::ZERO,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReservesReport {
+    pub network: Network,
+    // Unix time of the report
+    pub time: u64,
+    // Height of bitcoind's chain when the UTXOs were checked
+    pub height: u64,
+    // The committee signing the report
+    pub committee: Committee,
+    // The committee's script first
+    pub scripts: Vec<ScriptReserves>,
+    pub confirmed: Amount,
+    pub unconfirmed: Amount,
+    // Wrapped BTC outstanding on Axelar, as given by the operator
+    pub wrapped_supply: Option<Amount>,
+}
+
+impl ReservesReport {
+    // Checks every committee UTXO with `lookup`, except the outputs of transactions that aren't
+    // broadcast yet
+    pub fn new(
+        state: &State,
+        committee: &Committee,
+        mut lookup: impl FnMut(&OutPoint) -> UtxoStatus,
+        time: u64,
+        height: u64,
+        wrapped_supply: Option<Amount>,
+        secp: &Secp256k1<All>,
+    ) -> ReservesReport {
+        let unbroadcast: Vec<(Txid, TxKind)> = state
+            .pending
+            .iter()
+            .map(|x| (x.tx.compute_txid(), x.kind))
+            .chain(
+                state
+                    .finalized
+                    .iter()
+                    .map(|x| (x.tx.compute_txid(), x.kind)),
+            )
+            .collect();
+
+        let (_, committee_script_pubkey) = committee.scripts(secp);
+        let mut scripts = vec![ScriptReserves::new(committee_script_pubkey)];
+        for utxo in &state.utxos {
+            let index = match scripts
+                .iter()
+                .position(|x| x.script_pubkey == utxo.txout.script_pubkey)
+            {
+                Some(index) => index,
+                None => {
+                    scripts.push(ScriptReserves::new(utxo.txout.script_pubkey.clone()));
+                    scripts.len() - 1
+                }
+            };
+            let reserves = &mut scripts[index];
+
+            let kind = unbroadcast
+                .iter()
+                .find(|(txid, _)| *txid == utxo.outpoint.txid)
+                .map(|(_, kind)| *kind);
+            let status = match kind {
+                Some(_) => UtxoStatus::Unbroadcast,
+                None => lookup(&utxo.outpoint),
+            };
+            let value = utxo.txout.value;
+            match status {
+                UtxoStatus::Confirmed { .. } => reserves.confirmed += value,
+                UtxoStatus::Unconfirmed | UtxoStatus::Unbroadcast => reserves.unconfirmed += value,
+                UtxoStatus::Missing => reserves.missing += value,
+            }
+            if kind == Some(TxKind::PegIn) {
+                reserves.pending_peg_ins += value;
+            }
+            reserves.utxos.push(ReserveUtxo {
+                outpoint: utxo.outpoint,
+                value,
+                status,
+            });
+        }
+
+        // Everything a peg-out pays to other scripts than the one it spends leaves the reserves
+        let peg_outs = state
+            .pending
+            .iter()
+            .filter(|x| x.kind == TxKind::PegOut)
+            .map(|x| (&x.tx, &x.prevouts))
+            .chain(
+                state
+                    .finalized
+                    .iter()
+                    .filter(|x| x.kind == TxKind::PegOut)
+                    .map(|x| (&x.tx, &x.prevouts)),
+            );
+        for (tx, prevouts) in peg_outs {
+            let Some(spent) = prevouts.first() else {
+                continue;
+            };
+            let Some(reserves) = scripts
+                .iter_mut()
+                .find(|x| x.script_pubkey == spent.script_pubkey)
+            else {
+                continue;
+            };
+            reserves.unbroadcast_peg_outs += tx
+                .output
+                .iter()
+                .filter(|x| x.script_pubkey != spent.script_pubkey)
+                .map(|x| x.value)
+                .sum::<Amount>();
+        }
+
+        ReservesReport {
+            network: state.network,
+            time,
+            height,
+            committee: committee.clone(),
+            confirmed: scripts.iter().map(|x| x.confirmed).sum(),
+            unconfirmed: scripts.iter().map(|x| x.unconfirmed).sum(),
+            scripts,
+            wrapped_supply,
+        }
+    }
+
+    // Tagged hash of the report's JSON, which the committee signs
+    pub fn hash(&self) -> sha256::Hash {
+        let json = serde_json::to_vec(self).expect("Could not serialize report");
+        let tag_hash = sha256::Hash::hash(REPORT_TAG.as_bytes());
+        let mut engine = sha256::Hash::engine();
+        engine.input(tag_hash.as_ref());
+        engine.input(tag_hash.as_ref());
+        engine.input(&json);
+        sha256::Hash::from_engine(engine)
+    }
+
+    fn message(&self) -> Message {
+        Message::from_digest(self.hash().to_byte_array())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReportSignature {
+    pub public_key: XOnlyPublicKey,
+    pub signature: schnorr::Signature,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SignedReport {
+    pub report: ReservesReport,
+    pub signatures: Vec<ReportSignature>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ReportError {
+    UnknownSigner(XOnlyPublicKey),
+    DuplicateSigner(XOnlyPublicKey),
+    InvalidSignature(XOnlyPublicKey),
+    BelowThreshold { weight: i64, threshold: i64 },
+    // The signer of a committee member failed
+    Signer(SignerError),
+}
+
+impl fmt::Display for ReportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReportError::UnknownSigner(key) => write!(f, "{key} isn't a committee member"),
+            ReportError::DuplicateSigner(key) => write!(f, "{key} signed more than once"),
+            ReportError::InvalidSignature(key) => write!(f, "Invalid signature by {key}"),
+            ReportError::BelowThreshold { weight, threshold } => write!(
+                f,
+                "The signatures weigh {weight}, below the threshold of {threshold}"
+            ),
+            ReportError::Signer(error) => write!(f, "Could not sign: {error}"),
+        }
+    }
+}
+
+impl SignedReport {
+    pub fn new(report: ReservesReport) -> SignedReport {
+        SignedReport {
+            report,
+            signatures: vec![],
+        }
+    }
+
+    // Adds the validator's signature, replacing a previous one
+    pub fn sign(
+        &mut self,
+        validator: &Validator,
+        secp: &Secp256k1<All>,
+    ) -> Result<(), ReportError> {
+        let public_key = validator.public_key(secp);
+        if !self
+            .report
+            .committee
+            .members
+            .iter()
+            .any(|x| x.public_key == public_key)
+        {
+            return Err(ReportError::UnknownSigner(public_key));
+        }
+        let signature = validator
+            .sign_digest(&self.report.message(), secp)
+            .map_err(ReportError::Signer)?;
+        self.signatures.retain(|x| x.public_key != public_key);
+        self.signatures.push(ReportSignature {
+            public_key,
+            signature,
+        });
+        Ok(())
+    }
+
+    // Checks the signatures against the report's committee, without bitcoind. Whether the
+    // committee is the one holding the bridge's BTC is up to the verifier, e.g. by comparing its
+    // address. Returns the weight of the signatures.
+    pub fn verify(&self, secp: &Secp256k1<All>) -> Result<i64, ReportError> {
+        let committee = &self.report.committee;
+        let message = self.report.message();
+        let mut weight = 0;
+        for (i, signature) in self.signatures.iter().enumerate() {
+            let member = committee
+                .members
+                .iter()
+                .find(|x| x.public_key == signature.public_key)
+                .ok_or(ReportError::UnknownSigner(signature.public_key))?;
+            if self.signatures[..i]
+                .iter()
+                .any(|x| x.public_key == signature.public_key)
+            {
+                return Err(ReportError::DuplicateSigner(signature.public_key));
+            }
+            secp.verify_schnorr(&signature.signature, &message, &signature.public_key)
+                .map_err(|_| ReportError::InvalidSignature(signature.public_key))?;
+            weight += member.weight;
+        }
+
+        if weight < committee.threshold {
+            return Err(ReportError::BelowThreshold {
+                weight,
+                threshold: committee.threshold,
+            });
+        }
+        Ok(weight)
+    }
+}
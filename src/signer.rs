@@ -0,0 +1,300 @@
+// Where validators keep their keys. `Xpriv` signs in memory, which is fine for the demo;
+// production validators use `RemoteSigner` to reach an HSM or signing service over a small
+// JSON-RPC protocol (over HTTP POST):
+//
+//   getpublickey {"key_id": <string>}                      -> <x-only public key, hex>
+//   signschnorr  {"key_id": <string>, "message": <hex>}    -> <BIP340 signature, hex>
+//
+// `MockSignerServer` implements the protocol with in-memory keys, for tests.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    str::FromStr,
+    sync::OnceLock,
+    thread,
+};
+
+use bitcoin::{
+    bip32::{DerivationPath, KeySource, Xpriv},
+    hashes::{sha256, Hash, HashEngine},
+    hex::{DisplayHex, FromHex},
+    key::Secp256k1,
+    secp256k1::{schnorr, All, Message},
+    XOnlyPublicKey,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignerError {
+    // The signer couldn't be reached
+    Transport(String),
+    // The signer refused or failed to sign
+    Remote { code: i64, message: String },
+    InvalidResponse(String),
+    // The returned signature doesn't verify against the signer's public key
+    InvalidSignature,
+}
+
+impl fmt::Display for SignerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SignerError::Transport(error) => write!(f, "signer unreachable: {error}"),
+            SignerError::Remote { code, message } => write!(f, "signer error {code}: {message}"),
+            SignerError::InvalidResponse(error) => write!(f, "invalid signer response: {error}"),
+            SignerError::InvalidSignature => write!(f, "signer returned an invalid signature"),
+        }
+    }
+}
+
+pub trait Signer: fmt::Debug + Send + Sync {
+    fn public_key(&self, secp: &Secp256k1<All>) -> Result<XOnlyPublicKey, SignerError>;
+
+    // BIP340 signature of `msg`
+    fn sign_schnorr(
+        &self,
+        msg: &Message,
+        secp: &Secp256k1<All>,
+    ) -> Result<schnorr::Signature, SignerError>;
+
+    // BIP32 origin of the public key, if known, for PSBTs
+    fn key_origin(&self, _secp: &Secp256k1<All>) -> Option<KeySource> {
+        None
+    }
+}
+
+impl Signer for Xpriv {
+    fn public_key(&self, secp: &Secp256k1<All>) -> Result<XOnlyPublicKey, SignerError> {
+        Ok(self.to_keypair(secp).x_only_public_key().0)
+    }
+
+    fn sign_schnorr(
+        &self,
+        msg: &Message,
+        secp: &Secp256k1<All>,
+    ) -> Result<schnorr::Signature, SignerError> {
+        Ok(secp.sign_schnorr(msg, &self.to_keypair(secp)))
+    }
+
+    fn key_origin(&self, secp: &Secp256k1<All>) -> Option<KeySource> {
+        Some((self.fingerprint(secp), DerivationPath::master()))
+    }
+}
+
+// In-memory key that derives the BIP340 auxiliary randomness from `seed` and the message instead
+// of drawing it, so the same sighash always gets the same signature and whole transactions can
+// be compared against stored vectors. The nonce stays secret as long as the key does, but the
+// signatures are only as unpredictable as the seed.
+#[derive(Debug, Clone)]
+pub struct SeededSigner {
+    pub key: Xpriv,
+    pub seed: [u8; 32],
+}
+
+impl SeededSigner {
+    pub fn new(key: Xpriv, seed: [u8; 32]) -> SeededSigner {
+        SeededSigner { key, seed }
+    }
+
+    pub fn aux_rand(&self, msg: &Message) -> [u8; 32] {
+        let mut engine = sha256::Hash::engine();
+        engine.input(&self.seed);
+        engine.input(msg.as_ref());
+        sha256::Hash::from_engine(engine).to_byte_array()
+    }
+}
+
+impl Signer for SeededSigner {
+    fn public_key(&self, secp: &Secp256k1<All>) -> Result<XOnlyPublicKey, SignerError> {
+        self.key.public_key(secp)
+    }
+
+    fn sign_schnorr(
+        &self,
+        msg: &Message,
+        secp: &Secp256k1<All>,
+    ) -> Result<schnorr::Signature, SignerError> {
+        Ok(secp.sign_schnorr_with_aux_rand(msg, &self.key.to_keypair(secp), &self.aux_rand(msg)))
+    }
+
+    fn key_origin(&self, secp: &Secp256k1<All>) -> Option<KeySource> {
+        self.key.key_origin(secp)
+    }
+}
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    result: Option<Value>,
+    error: Option<RpcError>,
+}
+
+#[derive(Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Debug)]
+pub struct RemoteSigner {
+    pub url: String,
+    pub key_id: String,
+    client: reqwest::blocking::Client,
+    public_key: OnceLock<XOnlyPublicKey>,
+}
+
+impl RemoteSigner {
+    pub fn new(url: &str, key_id: &str) -> RemoteSigner {
+        RemoteSigner {
+            url: url.to_string(),
+            key_id: key_id.to_string(),
+            client: reqwest::blocking::Client::new(),
+            public_key: OnceLock::new(),
+        }
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<String, SignerError> {
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+        let response: RpcResponse = self
+            .client
+            .post(&self.url)
+            .json(&request)
+            .send()
+            .map_err(|error| SignerError::Transport(error.to_string()))?
+            .json()
+            .map_err(|error| SignerError::InvalidResponse(error.to_string()))?;
+
+        match (response.result, response.error) {
+            (_, Some(error)) => Err(SignerError::Remote {
+                code: error.code,
+                message: error.message,
+            }),
+            (Some(Value::String(result)), None) => Ok(result),
+            _ => Err(SignerError::InvalidResponse(
+                "expected a hex string".to_string(),
+            )),
+        }
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn public_key(&self, _secp: &Secp256k1<All>) -> Result<XOnlyPublicKey, SignerError> {
+        if let Some(public_key) = self.public_key.get() {
+            return Ok(*public_key);
+        }
+        let result = self.call("getpublickey", json!({"key_id": self.key_id}))?;
+        let public_key = XOnlyPublicKey::from_str(&result)
+            .map_err(|error| SignerError::InvalidResponse(error.to_string()))?;
+        Ok(*self.public_key.get_or_init(|| public_key))
+    }
+
+    // The signature is checked before it's returned, so that a faulty signer is caught here
+    // rather than when the witness is finalized.
+    fn sign_schnorr(
+        &self,
+        msg: &Message,
+        secp: &Secp256k1<All>,
+    ) -> Result<schnorr::Signature, SignerError> {
+        let public_key = self.public_key(secp)?;
+        let result = self.call(
+            "signschnorr",
+            json!({"key_id": self.key_id, "message": msg.as_ref().to_lower_hex_string()}),
+        )?;
+        let signature = schnorr::Signature::from_str(&result)
+            .map_err(|error| SignerError::InvalidResponse(error.to_string()))?;
+        secp.verify_schnorr(&signature, msg, &public_key)
+            .map_err(|_| SignerError::InvalidSignature)?;
+        Ok(signature)
+    }
+}
+
+// Serves the remote signer protocol on localhost with the given keys, from a background thread
+// that lives as long as the process.
+pub struct MockSignerServer {
+    pub url: String,
+}
+
+impl MockSignerServer {
+    pub fn start(keys: HashMap<String, Xpriv>) -> MockSignerServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            let secp = Secp256k1::new();
+            for stream in listener.incoming().flatten() {
+                // A broken connection only affects its own request
+                let _ = serve(stream, &keys, &secp);
+            }
+        });
+
+        MockSignerServer { url }
+    }
+}
+
+fn serve(
+    stream: TcpStream,
+    keys: &HashMap<String, Xpriv>,
+    secp: &Secp256k1<All>,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let response = match serde_json::from_slice::<Value>(&body) {
+        Ok(request) => handle_request(&request, keys, secp),
+        Err(error) => json!({"error": {"code": -32700, "message": error.to_string()}}),
+    }
+    .to_string();
+
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+        response.len()
+    )
+}
+
+fn handle_request(request: &Value, keys: &HashMap<String, Xpriv>, secp: &Secp256k1<All>) -> Value {
+    let id = request["id"].clone();
+    let error = |code: i64, message: &str| json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}});
+
+    let Some(key) = request["params"]["key_id"]
+        .as_str()
+        .and_then(|x| keys.get(x))
+    else {
+        return error(-32602, "unknown key_id");
+    };
+    let result = match request["method"].as_str() {
+        Some("getpublickey") => key.public_key(secp).unwrap().to_string(),
+        Some("signschnorr") => {
+            let Some(msg) = request["params"]["message"]
+                .as_str()
+                .and_then(|x| <[u8; 32]>::from_hex(x).ok())
+            else {
+                return error(-32602, "message must be 32 bytes in hex");
+            };
+            key.sign_schnorr(&Message::from_digest(msg), secp)
+                .unwrap()
+                .to_string()
+        }
+        _ => return error(-32601, "method not found"),
+    };
+
+    json!({"jsonrpc": "2.0", "id": id, "result": result})
+}
@@ -0,0 +1,384 @@
+// State kept between invocations of the CLI: the active committee, the UTXOs it controls and
+// the transactions on their way through signing, finalization and broadcasting.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use bitcoin::{
+    bip32::{DerivationPath, Fingerprint, KeySource},
+    consensus::serde::{Hex, With},
+    key::Secp256k1,
+    psbt::Psbt,
+    secp256k1::All,
+    taproot::{LeafVersion, Signature, TapLeafHash},
+    transaction, Network, OutPoint, ScriptBuf, TapSighash, TapSighashType, TxOut, XOnlyPublicKey,
+};
+use bitcoin_rs::key::UnspendableKey;
+use bitcoin_rs::script::MultisigScript;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    deposit_address::{find_deposits, DepositAddress},
+    evidence::EvidenceLog,
+    ledger::Ledger,
+    peg_out_queue::PegOutQueue,
+    psbt, taproot_sighashes,
+    validator::Validator,
+    SighashError, Utxo,
+};
+
+#[derive(Debug)]
+pub enum LoadError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, serde_json::Error),
+    // The state file was written for another network
+    Network { path: PathBuf, network: Network },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Read(path, error) => write!(f, "Could not read {}: {error}", path.display()),
+            LoadError::Parse(path, error) => {
+                write!(f, "Could not parse {}: {error}", path.display())
+            }
+            LoadError::Network { path, network } => {
+                write!(f, "{} belongs to {network}", path.display())
+            }
+        }
+    }
+}
+
+// Parses the JSON file at `path`
+fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T, LoadError> {
+    let json = fs::read_to_string(path).map_err(|error| LoadError::Read(path.into(), error))?;
+    serde_json::from_str(&json).map_err(|error| LoadError::Parse(path.into(), error))
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CommitteeMember {
+    pub operator_address: String,
+    pub weight: i64,
+    pub public_key: XOnlyPublicKey,
+    // BIP32 origin of `public_key`, so that wallets recognize their key in PSBTs
+    #[serde(default)]
+    pub key_origin: Option<KeySource>,
+}
+
+impl CommitteeMember {
+    // Unknown origins are reported with a zero fingerprint
+    pub fn key_source(&self) -> KeySource {
+        self.key_origin
+            .clone()
+            .unwrap_or((Fingerprint::default(), DerivationPath::master()))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Committee {
+    pub members: Vec<CommitteeMember>,
+    pub threshold: i64,
+    pub internal_key: XOnlyPublicKey,
+}
+
+impl Committee {
+    // The validators' signers must be set
+    pub fn from_validators(
+        validators: &[Validator],
+        threshold: i64,
+        secp: &Secp256k1<All>,
+    ) -> Committee {
+        Committee {
+            members: validators
+                .iter()
+                .map(|x| CommitteeMember {
+                    operator_address: x.operator_address.clone(),
+                    weight: x.weight,
+                    public_key: x.public_key(secp),
+                    key_origin: x.key_origin(secp),
+                })
+                .collect(),
+            threshold,
+            internal_key: XOnlyPublicKey::create_unspendable_key(),
+        }
+    }
+
+    pub fn pks_weights(&self) -> Vec<(XOnlyPublicKey, i64)> {
+        self.members
+            .iter()
+            .map(|x| (x.public_key, x.weight))
+            .collect()
+    }
+
+    // (script, script_pubkey) of the committee's multisig
+    pub fn scripts(&self, secp: &Secp256k1<All>) -> (ScriptBuf, ScriptBuf) {
+        ScriptBuf::create_threshold_multisig_with_weights(
+            &self.pks_weights(),
+            &self.internal_key,
+            self.threshold,
+            secp,
+        )
+    }
+
+    pub fn member_index(&self, operator_address: &str) -> Option<usize> {
+        self.members
+            .iter()
+            .position(|x| x.operator_address == operator_address)
+    }
+
+    pub fn load(path: &Path) -> Result<Committee, LoadError> {
+        read_json(path)
+    }
+
+    pub fn save(&self, path: &Path) {
+        let json = serde_json::to_string_pretty(self).expect("Could not serialize committee");
+        fs::write(path, json)
+            .unwrap_or_else(|error| panic!("Could not write {}: {error}", path.display()));
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TxKind {
+    PegIn,
+    Handover,
+    PegOut,
+    // A depositor taking back an unprocessed deposit
+    Refund,
+}
+
+// A committee transaction waiting for signatures
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PendingTx {
+    pub kind: TxKind,
+    #[serde(with = "With::<Hex>")]
+    pub tx: transaction::Transaction,
+    pub prevouts: Vec<TxOut>,
+    pub sighash_type: TapSighashType,
+    // The committee whose UTXOs are spent, i.e. the one that has to sign
+    pub committee: Committee,
+    // One vector per input, in committee order. Missing signatures are None.
+    pub signatures: Vec<Vec<Option<Signature>>>,
+    // Deposit addresses spent by the transaction
+    #[serde(default)]
+    pub deposits: Vec<DepositAddress>,
+}
+
+impl PendingTx {
+    pub fn new(
+        kind: TxKind,
+        tx: transaction::Transaction,
+        prevouts: Vec<TxOut>,
+        sighash_type: TapSighashType,
+        committee: Committee,
+    ) -> PendingTx {
+        let signatures = vec![vec![None; committee.members.len()]; tx.input.len()];
+        PendingTx {
+            kind,
+            tx,
+            prevouts,
+            sighash_type,
+            committee,
+            signatures,
+            deposits: vec![],
+        }
+    }
+
+    // Reads a committee PSBT, e.g. from `MultisigProver`
+    pub fn from_psbt(kind: TxKind, psbt: &Psbt, committee: Committee) -> PendingTx {
+        let mut pending = PendingTx::new(
+            kind,
+            psbt.unsigned_tx.clone(),
+            psbt::prevouts(psbt).expect("Missing witness UTXOs"),
+            psbt.inputs
+                .first()
+                .map_or(Ok(TapSighashType::Default), psbt::sighash_type)
+                .expect("Not a taproot sighash type"),
+            committee,
+        );
+        pending.import_psbt(psbt);
+        pending
+    }
+
+    pub fn sighashes(&self, secp: &Secp256k1<All>) -> Result<Vec<TapSighash>, SighashError> {
+        let (script, _) = self.committee.scripts(secp);
+        taproot_sighashes(&self.tx, &self.prevouts, &script, self.sighash_type)
+    }
+
+    // PSBT with the signatures collected so far
+    pub fn psbt(&self, secp: &Secp256k1<All>) -> Psbt {
+        let mut psbt = psbt::committee_psbt(
+            self.tx.clone(),
+            &self.prevouts,
+            &self.committee,
+            self.sighash_type,
+            secp,
+        );
+        psbt::set_deposit_leaves(&mut psbt, &self.deposits, secp);
+        let (script, _) = self.committee.scripts(secp);
+        let leaf_hash = TapLeafHash::from_script(&script, LeafVersion::TapScript);
+        for (input, signatures) in psbt.inputs.iter_mut().zip(&self.signatures) {
+            for (member, signature) in self.committee.members.iter().zip(signatures) {
+                if let Some(signature) = signature {
+                    input
+                        .tap_script_sigs
+                        .insert((member.public_key, leaf_hash), *signature);
+                }
+            }
+        }
+        psbt
+    }
+
+    // Copies the committee's signatures from a PSBT of the same transaction. Returns the number
+    // of signatures imported.
+    pub fn import_psbt(&mut self, psbt: &Psbt) -> usize {
+        assert_eq!(
+            psbt.unsigned_tx.compute_txid(),
+            self.tx.compute_txid(),
+            "The PSBT is for a different transaction"
+        );
+
+        let mut imported = 0;
+        let signatures = psbt::committee_signatures(psbt, &self.committee.pks_weights());
+        for (input_signatures, psbt_signatures) in self.signatures.iter_mut().zip(signatures) {
+            for (signature, psbt_signature) in input_signatures.iter_mut().zip(psbt_signatures) {
+                if psbt_signature.is_some() && *signature != psbt_signature {
+                    *signature = psbt_signature;
+                    imported += 1;
+                }
+            }
+        }
+        imported
+    }
+}
+
+// A signed transaction ready to be broadcast
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FinalizedTx {
+    pub kind: TxKind,
+    #[serde(with = "With::<Hex>")]
+    pub tx: transaction::Transaction,
+    // Empty if the spent outputs aren't known (e.g. wallet-funded peg-ins)
+    pub prevouts: Vec<TxOut>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct State {
+    pub network: Network,
+    pub committee: Option<Committee>,
+    // UTXOs of the committee, including outputs of transactions that aren't confirmed yet
+    pub utxos: Vec<Utxo>,
+    // Deposit addresses handed out to users, of this or previous committees
+    #[serde(default)]
+    pub deposit_addresses: Vec<DepositAddress>,
+    // Withdrawal requests, and the peg-outs paying them
+    #[serde(default)]
+    pub peg_out_queue: PegOutQueue,
+    pub pending: Vec<PendingTx>,
+    // In the order they have to be broadcast
+    pub finalized: Vec<FinalizedTx>,
+    // Flows of the transactions created since the state has a ledger
+    #[serde(default)]
+    pub ledger: Ledger,
+    // Signatures imported so far, to catch validators signing conflicting transactions
+    #[serde(default)]
+    pub evidence: EvidenceLog,
+}
+
+impl State {
+    pub fn new(network: Network) -> State {
+        State {
+            network,
+            committee: None,
+            utxos: vec![],
+            deposit_addresses: vec![],
+            peg_out_queue: PegOutQueue::default(),
+            pending: vec![],
+            finalized: vec![],
+            ledger: Ledger::default(),
+            evidence: EvidenceLog::default(),
+        }
+    }
+
+    // A new state if there is no file at `path`
+    pub fn load(path: &Path, network: Network) -> Result<State, LoadError> {
+        if !path.exists() {
+            return Ok(State::new(network));
+        }
+        let state: State = read_json(path)?;
+        if state.network != network {
+            return Err(LoadError::Network {
+                path: path.into(),
+                network: state.network,
+            });
+        }
+        Ok(state)
+    }
+
+    pub fn save(&self, path: &Path) {
+        let json = serde_json::to_string_pretty(self).expect("Could not serialize state");
+        fs::write(path, json)
+            .unwrap_or_else(|error| panic!("Could not write {}: {error}", path.display()));
+    }
+
+    // Adds the outputs of `tx` paying to `script_pubkey` to the committee's UTXOs
+    pub fn add_utxos(&mut self, tx: &transaction::Transaction, script_pubkey: &ScriptBuf) {
+        let txid = tx.compute_txid();
+        self.utxos.extend(
+            tx.output
+                .iter()
+                .enumerate()
+                .filter(|(_, txout)| txout.script_pubkey == *script_pubkey)
+                .map(|(vout, txout)| Utxo {
+                    outpoint: OutPoint {
+                        txid,
+                        vout: vout as u32,
+                    },
+                    txout: txout.clone(),
+                }),
+        );
+    }
+
+    // Adds the outputs of `tx` paying to deposit addresses to the committee's UTXOs. Returns
+    // them with their deposit address.
+    pub fn add_deposits(&mut self, tx: &transaction::Transaction) -> Vec<(Utxo, DepositAddress)> {
+        let txid = tx.compute_txid();
+        let deposits: Vec<_> = find_deposits(tx, &self.deposit_addresses)
+            .into_iter()
+            .map(|(vout, deposit)| {
+                let utxo = Utxo {
+                    outpoint: OutPoint { txid, vout },
+                    txout: tx.output[vout as usize].clone(),
+                };
+                (utxo, deposit.clone())
+            })
+            .filter(|(utxo, _)| !self.utxos.iter().any(|x| x.outpoint == utxo.outpoint))
+            .collect();
+        self.utxos
+            .extend(deposits.iter().map(|(utxo, _)| utxo.clone()));
+        deposits
+    }
+
+    // Deposit addresses among the outputs spent by a committee transaction
+    pub fn spent_deposits(&self, prevouts: &[TxOut]) -> Vec<DepositAddress> {
+        self.deposit_addresses
+            .iter()
+            .filter(|x| prevouts.iter().any(|p| p.script_pubkey == x.script_pubkey))
+            .cloned()
+            .collect()
+    }
+
+    // Spent outputs of `tx`, if they are all committee UTXOs
+    pub fn prevouts(&self, tx: &transaction::Transaction) -> Option<Vec<TxOut>> {
+        tx.input
+            .iter()
+            .map(|txin| {
+                self.utxos
+                    .iter()
+                    .find(|x| x.outpoint == txin.previous_output)
+                    .map(|x| x.txout.clone())
+            })
+            .collect()
+    }
+}
@@ -1,7 +1,10 @@
 use bitcoin::{absolute::LockTime, script, transaction, Amount, ScriptBuf, Witness};
 use bitcoincore_rpc::{Client, RpcApi};
 
-use crate::{create_op_return, Utxo};
+use axelar_btc::{create_op_return, Utxo};
+
+// Destination of the demo deposits
+pub const DEMO_DESTINATION: &str = "ethereum:0x0000000000000000000000000000000000000000:foobar";
 
 pub struct User;
 
@@ -35,7 +38,7 @@ impl User {
         // GMP data: destination chain, address and payload
         let op_return_out = transaction::TxOut {
             value: Amount::ZERO,
-            script_pubkey: create_op_return(),
+            script_pubkey: create_op_return(DEMO_DESTINATION),
         };
         txouts.push(op_return_out);
 
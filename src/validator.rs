@@ -1,12 +1,39 @@
 use bitcoin::{
-    bip32::Xpriv,
+    bip32::{KeySource, Xpriv},
     key::Secp256k1,
-    secp256k1::{All, Message},
+    psbt::Psbt,
+    secp256k1::{schnorr, All, Message},
+    sighash::{Prevouts, SighashCache},
     taproot::Signature,
     TapSighash, TapSighashType, XOnlyPublicKey,
 };
 use bitcoin_hashes::Hash;
 use serde::Deserialize;
+use std::{fmt, sync::Arc};
+
+use crate::{
+    check_sighash_type,
+    psbt::{self, PsbtError},
+    signer::{SeededSigner, Signer, SignerError},
+    SighashError,
+};
+
+// Why a validator didn't sign
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignError {
+    // The sighash type doesn't commit to the outputs, see `is_signable_sighash_type`
+    Unsignable(TapSighashType),
+    Signer(SignerError),
+}
+
+impl fmt::Display for SignError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SignError::Unsignable(sighash_type) => write!(f, "{sighash_type} isn't signed"),
+            SignError::Signer(error) => write!(f, "{error}"),
+        }
+    }
+}
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Validator {
@@ -14,23 +41,155 @@ pub struct Validator {
     // TODO: make sure this is the weight we should use
     #[serde(rename = "quadratic_voting_power")]
     pub weight: i64,
+    // In-memory key (`Xpriv`) or remote signer holding the validator's key
     #[serde(skip_deserializing)]
-    pub key: Option<Xpriv>,
+    pub signer: Option<Arc<dyn Signer>>,
 }
 
 impl Validator {
+    pub fn with_key(operator_address: &str, weight: i64, key: Xpriv) -> Validator {
+        Validator {
+            operator_address: operator_address.to_string(),
+            weight,
+            signer: Some(Arc::new(key)),
+        }
+    }
+
+    // Signs deterministically, see `SeededSigner`
+    pub fn with_seeded_key(
+        operator_address: &str,
+        weight: i64,
+        key: Xpriv,
+        seed: [u8; 32],
+    ) -> Validator {
+        Validator {
+            operator_address: operator_address.to_string(),
+            weight,
+            signer: Some(Arc::new(SeededSigner::new(key, seed))),
+        }
+    }
+
+    fn signer(&self) -> &dyn Signer {
+        self.signer
+            .as_deref()
+            .unwrap_or_else(|| panic!("Validator {} has no signer", self.operator_address))
+    }
+
     pub fn public_key(&self, secp: &Secp256k1<All>) -> XOnlyPublicKey {
-        self.key.unwrap().to_keypair(&secp).x_only_public_key().0
+        self.signer()
+            .public_key(secp)
+            .unwrap_or_else(|error| panic!("No public key for {}: {error}", self.operator_address))
+    }
+
+    pub fn key_origin(&self, secp: &Secp256k1<All>) -> Option<KeySource> {
+        self.signer.as_ref()?.key_origin(secp)
     }
 
     // The validators blindly trust the signature hash that they need to sign,
-    // and provide their Schnorr signatures on it.
-    pub fn sign_sighash(&self, sighash: &TapSighash, secp: &Secp256k1<All>) -> Signature {
+    // and provide their Schnorr signatures on it. The only thing they check is that
+    // the sighash type commits to the outputs, otherwise anyone holding the signature
+    // could redirect the committee's funds. Whether a `SINGLE` one commits to all the outputs
+    // depends on the transaction, which is up to whoever computes the sighashes.
+    pub fn sign_sighash(
+        &self,
+        sighash: &TapSighash,
+        sighash_type: TapSighashType,
+        secp: &Secp256k1<All>,
+    ) -> Result<Signature, SignError> {
+        if !is_signable_sighash_type(sighash_type) {
+            return Err(SignError::Unsignable(sighash_type));
+        }
+
         let msg = Message::from_digest_slice(&sighash.to_byte_array()).unwrap();
+        let signature = self
+            .signer()
+            .sign_schnorr(&msg, secp)
+            .map_err(SignError::Signer)?;
+        Ok(Signature {
+            signature,
+            sighash_type,
+        })
+    }
+
+    // Signs a message other than a sighash, e.g. a reserves report. It must be a tagged hash, so
+    // it can't be passed off as a sighash.
+    pub fn sign_digest(
+        &self,
+        msg: &Message,
+        secp: &Secp256k1<All>,
+    ) -> Result<schnorr::Signature, SignerError> {
+        self.signer().sign_schnorr(msg, secp)
+    }
+
+    // Adds the validator's signatures to every input that lists its key in `tap_key_origins`.
+    // Unlike with `sign_sighash`, the sighashes are computed from the PSBT itself, so `SINGLE`
+    // inputs are only signed if the transaction commits to all its outputs. The PSBT is left as
+    // is if any of the inputs to sign can't be, or the signer fails. Returns the number of
+    // signatures added.
+    pub fn sign_psbt(&self, psbt: &mut Psbt, secp: &Secp256k1<All>) -> Result<usize, PsbtError> {
+        let public_key = self.public_key(secp);
+        let mut to_sign = vec![];
+        for (i, input) in psbt.inputs.iter().enumerate() {
+            let Some((leaf_hashes, _)) = input.tap_key_origins.get(&public_key) else {
+                continue;
+            };
+            let sighash_type = psbt::sighash_type(input)
+                .map_err(|error| PsbtError::InvalidSighashType(i, error))?;
+            check_sighash_type(&psbt.unsigned_tx, sighash_type)
+                .map_err(|error| PsbtError::Sighash(i, error))?;
+            to_sign.extend(leaf_hashes.iter().map(|x| (i, *x, sighash_type)));
+        }
+        if to_sign.is_empty() {
+            return Ok(0);
+        }
+
+        let prevouts = psbt::checked_prevouts(psbt)?;
+        let mut sighash_cache = SighashCache::new(&psbt.unsigned_tx);
+        let mut sighashes = vec![];
+        for (i, leaf_hash, sighash_type) in to_sign {
+            let sighash = sighash_cache
+                .taproot_script_spend_signature_hash(
+                    i,
+                    &Prevouts::All(&prevouts),
+                    leaf_hash,
+                    sighash_type,
+                )
+                .map_err(|error| PsbtError::Sighash(i, SighashError::Taproot(error)))?;
+            sighashes.push((i, leaf_hash, sighash, sighash_type));
+        }
+
+        let mut signed = vec![];
+        for (i, leaf_hash, sighash, sighash_type) in sighashes {
+            let signature = self
+                .sign_sighash(&sighash, sighash_type, secp)
+                .map_err(|error| PsbtError::Sign(i, error))?;
+            signed.push((i, leaf_hash, signature));
+        }
+
+        let count = signed.len();
+        for (i, leaf_hash, signature) in signed {
+            psbt.inputs[i]
+                .tap_script_sigs
+                .insert((public_key, leaf_hash), signature);
+        }
+        Ok(count)
+    }
+}
 
-        Signature {
-            signature: secp.sign_schnorr(&msg, &self.key.unwrap().to_keypair(&secp)),
-            sighash_type: TapSighashType::Default,
+// `DEFAULT`/`ALL` commit to all inputs & outputs. `ANYONECANPAY` only commits to the signed
+// input, which lets others add inputs (e.g. to bump the fee) without touching the outputs.
+// `SINGLE|ANYONECANPAY` only commits to the signed input and the output with the same index, so
+// that inputs & outputs can be added in pairs. It's only signed for transactions with an output
+// per input (see `check_sighash_type`), otherwise some outputs would be up for grabs. `NONE`
+// commits to no outputs at all, and `SINGLE` alone has no use, so neither is ever signed.
+pub fn is_signable_sighash_type(sighash_type: TapSighashType) -> bool {
+    match sighash_type {
+        TapSighashType::Default
+        | TapSighashType::All
+        | TapSighashType::AllPlusAnyoneCanPay
+        | TapSighashType::SinglePlusAnyoneCanPay => true,
+        TapSighashType::Single | TapSighashType::None | TapSighashType::NonePlusAnyoneCanPay => {
+            false
         }
     }
 }
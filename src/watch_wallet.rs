@@ -0,0 +1,173 @@
+// bitcoind as the UTXO oracle of small deployments. The committee's scripts are imported as
+// `raw()` descriptors into a wallet without private keys, and its `listunspent` replaces the
+// UTXOs tracked by hand: deposits show up once they have enough confirmations, and UTXOs spent
+// elsewhere disappear. Transactions the CLI hasn't broadcast yet are unknown to bitcoind, so
+// their inputs stay spent and their outputs stay unspent.
+
+use std::fmt;
+
+use bitcoin::{OutPoint, ScriptBuf, Transaction, TxOut};
+use bitcoincore_rpc::{
+    json::{ImportDescriptors, Timestamp},
+    Client, RpcApi,
+};
+
+use crate::{descriptor::with_checksum, Utxo};
+
+const LABEL: &str = "axelar-btc";
+
+#[derive(Debug)]
+pub enum WatchError {
+    Rpc(bitcoincore_rpc::Error),
+    Import { descriptor: String, message: String },
+}
+
+impl fmt::Display for WatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WatchError::Rpc(error) => write!(f, "RPC error: {error}"),
+            WatchError::Import {
+                descriptor,
+                message,
+            } => write!(f, "Could not import {descriptor}: {message}"),
+        }
+    }
+}
+
+impl From<bitcoincore_rpc::Error> for WatchError {
+    fn from(error: bitcoincore_rpc::Error) -> WatchError {
+        WatchError::Rpc(error)
+    }
+}
+
+pub fn raw_descriptor(script_pubkey: &ScriptBuf) -> String {
+    with_checksum(format!("raw({})", script_pubkey.to_hex_string()))
+}
+
+// Creates the watch-only wallet, or loads it if it exists from a previous run. `rpc` can be
+// bound to any wallet.
+pub fn load_or_create(rpc: &Client, wallet: &str) -> Result<(), WatchError> {
+    if rpc.list_wallets()?.iter().any(|x| x == wallet) {
+        return Ok(());
+    }
+    if rpc.load_wallet(wallet).is_err() {
+        rpc.create_wallet(wallet, Some(true), Some(true), None, None)?;
+    }
+    Ok(())
+}
+
+// `rescan_from` is the Unix time of the scripts' oldest UTXO, or None for new scripts. `rpc` has
+// to be bound to the watch-only wallet.
+pub fn import_scripts(
+    rpc: &Client,
+    script_pubkeys: &[ScriptBuf],
+    rescan_from: Option<u64>,
+) -> Result<(), WatchError> {
+    for script_pubkey in script_pubkeys {
+        let descriptor = raw_descriptor(script_pubkey);
+        let results = rpc.import_descriptors(ImportDescriptors {
+            descriptor: descriptor.clone(),
+            timestamp: rescan_from.map_or(Timestamp::Now, Timestamp::Time),
+            active: None,
+            range: None,
+            next_index: None,
+            internal: None,
+            label: Some(LABEL.to_string()),
+        })?;
+        if let Some(result) = results.into_iter().find(|x| !x.success) {
+            return Err(WatchError::Import {
+                descriptor,
+                message: result.error.map_or(String::new(), |x| x.message),
+            });
+        }
+    }
+    Ok(())
+}
+
+// A UTXO of the watch-only wallet, unconfirmed ones included
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchedUtxo {
+    pub utxo: Utxo,
+    pub confirmations: u32,
+}
+
+// The wallet's UTXOs paying to one of the scripts
+pub fn list_unspent(
+    rpc: &Client,
+    script_pubkeys: &[ScriptBuf],
+) -> Result<Vec<WatchedUtxo>, WatchError> {
+    Ok(rpc
+        .list_unspent(Some(0), None, None, Some(true), None)?
+        .into_iter()
+        .filter(|x| script_pubkeys.contains(&x.script_pub_key))
+        .map(|x| WatchedUtxo {
+            utxo: Utxo {
+                outpoint: OutPoint {
+                    txid: x.txid,
+                    vout: x.vout,
+                },
+                txout: TxOut {
+                    value: x.amount,
+                    script_pubkey: x.script_pub_key,
+                },
+            },
+            confirmations: x.confirmations,
+        })
+        .collect())
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Reconciliation {
+    pub utxos: Vec<Utxo>,
+    // Unknown UTXOs with at least the minimum confirmations, e.g. deposits
+    pub discovered: Vec<Utxo>,
+    // Known UTXOs bitcoind reports as spent
+    pub vanished: Vec<Utxo>,
+}
+
+// The committee's UTXOs according to bitcoind, from the `known` ones (e.g. the state's).
+// Known UTXOs the wallet doesn't list, e.g. outputs of mempool transactions it missed while the
+// scripts weren't imported yet, are only dropped if `is_unspent` (e.g. `gettxout`) says so.
+pub fn reconcile(
+    known: &[Utxo],
+    unspent: &[WatchedUtxo],
+    unbroadcast: &[&Transaction],
+    min_confirmations: u32,
+    mut is_unspent: impl FnMut(&OutPoint) -> bool,
+) -> Reconciliation {
+    let is_unbroadcast = |utxo: &Utxo| {
+        unbroadcast
+            .iter()
+            .any(|tx| tx.compute_txid() == utxo.outpoint.txid)
+    };
+    let is_spent_by_unbroadcast = |utxo: &Utxo| {
+        unbroadcast.iter().any(|tx| {
+            tx.input
+                .iter()
+                .any(|txin| txin.previous_output == utxo.outpoint)
+        })
+    };
+
+    let mut reconciliation = Reconciliation::default();
+    for utxo in known {
+        if is_unbroadcast(utxo)
+            || unspent.iter().any(|x| x.utxo.outpoint == utxo.outpoint)
+            || is_unspent(&utxo.outpoint)
+        {
+            reconciliation.utxos.push(utxo.clone());
+        } else {
+            reconciliation.vanished.push(utxo.clone());
+        }
+    }
+    for watched in unspent {
+        let utxo = &watched.utxo;
+        if watched.confirmations >= min_confirmations
+            && !known.iter().any(|x| x.outpoint == utxo.outpoint)
+            && !is_spent_by_unbroadcast(utxo)
+        {
+            reconciliation.utxos.push(utxo.clone());
+            reconciliation.discovered.push(utxo.clone());
+        }
+    }
+    reconciliation
+}
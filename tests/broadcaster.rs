@@ -0,0 +1,356 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use axelar_btc::broadcaster::{
+    child_with_parent_packages, mempool_accept_reports, submit_package_reports,
+    BitcoindBroadcaster, Broadcaster, DryRunBroadcaster, ElectrumBroadcaster, MempoolAcceptResult,
+    SubmissionReport, SubmitPackageResult,
+};
+use bitcoin::{
+    absolute::LockTime, transaction::Version, Address, Amount, Network, OutPoint, ScriptBuf,
+    Transaction, TxIn, TxOut,
+};
+use bitcoincore_rpc::{Auth, Client};
+
+// Distinct transactions, one per `i`
+fn txs(count: u8) -> Vec<Transaction> {
+    (0..count)
+        .map(|i| Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(1000),
+                script_pubkey: ScriptBuf::new_op_return([i]),
+            }],
+        })
+        .collect()
+}
+
+// A chain of `count` transactions, each spending the previous one
+fn chain(count: u8) -> Vec<Transaction> {
+    let mut txs: Vec<Transaction> = vec![];
+    for mut tx in self::txs(count) {
+        if let Some(parent) = txs.last() {
+            tx.input.push(TxIn {
+                previous_output: OutPoint::new(parent.compute_txid(), 0),
+                ..Default::default()
+            });
+        }
+        txs.push(tx);
+    }
+    txs
+}
+
+// A bitcoind JSON-RPC server answering with `respond(method, params)`. Returns its URL and the
+// methods it was called with.
+fn fake_bitcoind(
+    respond: impl Fn(&str, &serde_json::Value) -> serde_json::Value + Send + 'static,
+) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let calls = Arc::new(Mutex::new(vec![]));
+    let recorded = calls.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            loop {
+                let mut content_length = None;
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                    if let Some(x) = line.to_lowercase().strip_prefix("content-length: ") {
+                        content_length = Some(x.trim().parse::<usize>().unwrap());
+                    }
+                    line.clear();
+                }
+                let Some(content_length) = content_length else {
+                    break;
+                };
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                let method = request["method"].as_str().unwrap().to_string();
+                let result = respond(&method, &request["params"]);
+                recorded.lock().unwrap().push(method);
+
+                let response = serde_json::json!({
+                    "result": result,
+                    "error": null,
+                    "id": request["id"],
+                })
+                .to_string();
+                write!(
+                    writer,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{response}",
+                    response.len()
+                )
+                .unwrap();
+            }
+        }
+    });
+    (url, calls)
+}
+
+// `submitpackage` response accepting the package, or rejecting its last transaction
+fn package_result(txs: &[Transaction], accepted: bool) -> serde_json::Value {
+    let mut results = serde_json::Map::new();
+    for (i, tx) in txs.iter().enumerate() {
+        let error = match accepted || i + 1 < txs.len() {
+            true => serde_json::Value::Null,
+            false => "min relay fee not met".into(),
+        };
+        results.insert(
+            tx.compute_wtxid().to_string(),
+            serde_json::json!({ "txid": tx.compute_txid(), "vsize": 100, "error": error }),
+        );
+    }
+    serde_json::json!({ "package_msg": "success", "tx-results": results })
+}
+
+#[test]
+fn mempool_accept_results_become_reports() {
+    let txs = txs(3);
+    // The third transaction is missing from the response
+    let response = serde_json::json!([
+        {
+            "txid": txs[0].compute_txid(),
+            "wtxid": txs[0].compute_wtxid(),
+            "allowed": true,
+            "vsize": 150,
+            "fees": { "base": 0.00001234 },
+        },
+        {
+            "txid": txs[1].compute_txid(),
+            "wtxid": txs[1].compute_wtxid(),
+            "allowed": false,
+            "reject-reason": "min relay fee not met",
+        },
+    ]);
+    let results: Vec<MempoolAcceptResult> = serde_json::from_value(response).unwrap();
+    let reports = mempool_accept_reports(&txs, &results);
+
+    assert_eq!(
+        reports[0],
+        SubmissionReport {
+            txid: txs[0].compute_txid(),
+            allowed: true,
+            reject_reason: None,
+            vsize: Some(150),
+            fees: Some(Amount::from_sat(1234)),
+            submitted: false,
+        }
+    );
+    assert!(!reports[1].allowed);
+    assert_eq!(
+        reports[1].reject_reason.as_deref(),
+        Some("min relay fee not met")
+    );
+    assert!(!reports[2].allowed);
+    assert_eq!(
+        reports[2].reject_reason.as_deref(),
+        Some("missing from response")
+    );
+    assert!(reports.iter().all(|x| !x.submitted));
+
+    // Packages failing as a whole only have a package error, and no `allowed`
+    let response = serde_json::json!([
+        {
+            "txid": txs[0].compute_txid(),
+            "wtxid": txs[0].compute_wtxid(),
+            "package-error": "package-not-child-with-unconfirmed-parents",
+        },
+    ]);
+    let results: Vec<MempoolAcceptResult> = serde_json::from_value(response).unwrap();
+    let reports = mempool_accept_reports(&txs[..1], &results);
+    assert!(!reports[0].allowed);
+    assert_eq!(
+        reports[0].reject_reason.as_deref(),
+        Some("package-not-child-with-unconfirmed-parents")
+    );
+}
+
+#[test]
+fn submit_package_results_become_reports() {
+    let txs = txs(3);
+    // The parent is accepted, the child rejected and the last one isn't validated
+    let response = serde_json::json!({
+        "package_msg": "transaction failed",
+        "tx-results": {
+            txs[0].compute_wtxid().to_string(): {
+                "txid": txs[0].compute_txid(),
+                "vsize": 120,
+                "fees": { "base": 0.0000025 },
+            },
+            txs[1].compute_wtxid().to_string(): {
+                "txid": txs[1].compute_txid(),
+                "error": "bad-txns-inputs-missingorspent",
+            },
+        },
+    });
+    let result: SubmitPackageResult = serde_json::from_value(response).unwrap();
+    let reports = submit_package_reports(&txs, &result);
+
+    assert_eq!(
+        reports[0],
+        SubmissionReport {
+            txid: txs[0].compute_txid(),
+            allowed: true,
+            reject_reason: None,
+            vsize: Some(120),
+            fees: Some(Amount::from_sat(250)),
+            submitted: true,
+        }
+    );
+    assert!(!reports[1].allowed && !reports[1].submitted);
+    assert_eq!(
+        reports[1].reject_reason.as_deref(),
+        Some("bad-txns-inputs-missingorspent")
+    );
+    assert!(!reports[2].allowed && !reports[2].submitted);
+    assert_eq!(
+        reports[2].reject_reason.as_deref(),
+        Some("transaction failed")
+    );
+
+    // Before Bitcoin Core 28 there is no package message
+    let response = serde_json::json!({ "tx-results": {} });
+    let result: SubmitPackageResult = serde_json::from_value(response).unwrap();
+    let reports = submit_package_reports(&txs[..1], &result);
+    assert_eq!(
+        reports[0].reject_reason.as_deref(),
+        Some("not fully validated")
+    );
+}
+
+#[test]
+fn dry_run_records_without_submitting() {
+    let txs = txs(2);
+    let mut broadcaster = DryRunBroadcaster::default();
+    let reports = broadcaster.broadcast(&txs);
+
+    assert_eq!(broadcaster.transactions, txs);
+    for (report, tx) in reports.iter().zip(&txs) {
+        assert_eq!(report.txid, tx.compute_txid());
+        assert!(report.allowed);
+        assert!(!report.submitted);
+        assert_eq!(report.reject_reason, None);
+        assert_eq!(report.vsize, Some(tx.vsize() as u64));
+    }
+}
+
+#[test]
+fn broadcasts_stop_at_the_first_rejection() {
+    let txs = txs(3);
+    // An Electrum server accepting the first transaction and rejecting the second
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let server = listener.local_addr().unwrap().to_string();
+    let requests = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let mut requests = 0;
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 0 {
+            let request: serde_json::Value = serde_json::from_str(&line).unwrap();
+            let response = match requests {
+                0 => serde_json::json!({ "id": request["id"], "result": "txid" }),
+                _ => serde_json::json!({
+                    "id": request["id"],
+                    "error": { "code": 1, "message": "bad-txns-inputs-missingorspent" },
+                }),
+            };
+            writeln!(writer, "{response}").unwrap();
+            requests += 1;
+            line.clear();
+        }
+        requests
+    });
+
+    let reports = ElectrumBroadcaster::new(&server).broadcast(&txs);
+    assert!(reports[0].submitted);
+    assert!(!reports[1].allowed && !reports[1].submitted);
+    assert!(reports[1]
+        .reject_reason
+        .as_ref()
+        .unwrap()
+        .contains("bad-txns-inputs-missingorspent"));
+    assert!(!reports[2].submitted);
+    assert_eq!(
+        reports[2].reject_reason.as_deref(),
+        Some("not submitted: an earlier transaction was rejected")
+    );
+    // The third transaction never reached the server
+    assert_eq!(requests.join().unwrap(), 2);
+}
+
+#[test]
+fn chains_are_cut_into_parent_child_pairs() {
+    let chain = chain(5);
+    assert_eq!(child_with_parent_packages(&chain), [0..2, 2..4, 4..5]);
+
+    // Unrelated transactions go on their own
+    let mut txs = self::txs(2);
+    txs.extend(self::chain(2));
+    assert_eq!(child_with_parent_packages(&txs), [0..1, 1..2, 2..4]);
+    assert!(child_with_parent_packages(&[]).is_empty());
+}
+
+#[test]
+fn packages_are_submitted_pair_by_pair() {
+    let txs = chain(3);
+    let secp = bitcoin::key::Secp256k1::new();
+    let key = bitcoin::key::Keypair::from_seckey_slice(&secp, &[1; 32]).unwrap();
+    let miner = Address::p2tr(&secp, key.x_only_public_key().0, None, Network::Regtest);
+
+    for accepted in [true, false] {
+        let chain = txs.clone();
+        let (url, calls) = fake_bitcoind(move |method, params| match method {
+            "submitpackage" => {
+                let package: Vec<Transaction> = params[0]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|x| bitcoin::consensus::encode::deserialize_hex(x.as_str().unwrap()))
+                    .collect::<Result<_, _>>()
+                    .unwrap();
+                assert!(chain.windows(package.len()).any(|x| x == package));
+                package_result(&package, accepted)
+            }
+            "generatetoaddress" => serde_json::json!([]),
+            _ => panic!("Unexpected call to {method}"),
+        });
+        let rpc = Client::new(&url, Auth::None).unwrap();
+        let mut broadcaster = BitcoindBroadcaster {
+            rpc: &rpc,
+            miner_address: Some(miner.clone()),
+            packages: true,
+        };
+        let reports = broadcaster.broadcast(&txs);
+
+        if accepted {
+            assert!(reports.iter().all(|x| x.submitted));
+            assert_eq!(
+                *calls.lock().unwrap(),
+                ["submitpackage", "submitpackage", "generatetoaddress"]
+            );
+        } else {
+            // The first package's child is rejected, so the rest isn't submitted, nor mined
+            assert!(reports[0].submitted);
+            assert_eq!(
+                reports[1].reject_reason.as_deref(),
+                Some("min relay fee not met")
+            );
+            assert_eq!(
+                reports[2].reject_reason.as_deref(),
+                Some("not submitted: an earlier transaction was rejected")
+            );
+            assert_eq!(*calls.lock().unwrap(), ["submitpackage"]);
+        }
+    }
+}
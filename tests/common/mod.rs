@@ -0,0 +1,47 @@
+// Fixtures shared by the integration tests. Each test crate only uses some of them.
+#![allow(dead_code)]
+
+pub mod regtest;
+
+use axelar_btc::{get_private_key, state::Committee, validator::Validator, Utxo};
+use bitcoin::{
+    hashes::{sha256d, Hash},
+    key::Secp256k1,
+    secp256k1::All,
+    Amount, Network, OutPoint, ScriptBuf, TxOut, Txid,
+};
+
+// Validator `i` with weight 1 and the demo key `i`
+pub fn validator(i: usize) -> Validator {
+    Validator::with_key(
+        &format!("validator-{i}"),
+        1,
+        get_private_key(i, Network::Regtest).unwrap(),
+    )
+}
+
+pub fn validators() -> Vec<Validator> {
+    (0..3).map(validator).collect()
+}
+
+// 2 of the 3 `validators`
+pub fn committee(secp: &Secp256k1<All>) -> Committee {
+    Committee::from_validators(&validators(), 2, secp)
+}
+
+pub fn txid(i: u32) -> Txid {
+    Txid::from_raw_hash(sha256d::Hash::hash(&i.to_le_bytes()))
+}
+
+pub fn utxo(i: u32, sats: u64, script_pubkey: &ScriptBuf) -> Utxo {
+    Utxo {
+        outpoint: OutPoint {
+            txid: txid(i),
+            vout: 0,
+        },
+        txout: TxOut {
+            value: Amount::from_sat(sats),
+            script_pubkey: script_pubkey.clone(),
+        },
+    }
+}
@@ -0,0 +1,215 @@
+// A throwaway bitcoind for integration tests: every `RegtestNode` runs its own daemon in a fresh
+// data directory on free ports, so tests don't depend on a manually started node and can run in
+// parallel. The daemon is stopped and its data directory removed when the node is dropped.
+//
+// The binary is `$BITCOIND`, or `bitcoind` from the `PATH`. The tests using it are ignored by
+// default, run them with `cargo test -- --ignored`.
+
+use std::{
+    env, fs,
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bitcoin::{transaction, Address, Amount, Network, OutPoint, Txid};
+use bitcoincore_rpc::{Auth, Client, RpcApi};
+
+use axelar_btc::Utxo;
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+// Attempts to start the daemon, in case another process grabs one of its ports in the meantime
+const START_ATTEMPTS: usize = 3;
+// Coinbase outputs can only be spent after 100 confirmations
+const COINBASE_MATURITY: u64 = 100;
+
+static NODE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+pub struct RegtestNode {
+    pub datadir: PathBuf,
+    pub rpc_port: u16,
+    // Client without a wallet, for node-wide calls
+    pub rpc: Client,
+    process: Child,
+}
+
+fn bitcoind_binary() -> PathBuf {
+    env::var_os("BITCOIND").map_or(PathBuf::from("bitcoind"), PathBuf::from)
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|x| x.local_addr())
+        .expect("Could not find a free port")
+        .port()
+}
+
+impl RegtestNode {
+    pub fn start() -> RegtestNode {
+        let datadir = env::temp_dir().join(format!(
+            "axelar-btc-regtest-{}-{}",
+            std::process::id(),
+            NODE_COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&datadir);
+        fs::create_dir_all(&datadir)
+            .unwrap_or_else(|error| panic!("Could not create {}: {error}", datadir.display()));
+
+        for attempt in 1..=START_ATTEMPTS {
+            match RegtestNode::spawn(&datadir) {
+                Ok(node) => return node,
+                Err(error) if attempt < START_ATTEMPTS => {
+                    eprintln!("bitcoind didn't start ({error}), retrying")
+                }
+                Err(error) => {
+                    let _ = fs::remove_dir_all(&datadir);
+                    panic!("bitcoind didn't start: {error}")
+                }
+            }
+        }
+        unreachable!()
+    }
+
+    fn spawn(datadir: &Path) -> Result<RegtestNode, String> {
+        let rpc_port = free_port();
+        let mut process = Command::new(bitcoind_binary())
+            .arg("-regtest")
+            .arg(format!("-datadir={}", datadir.display()))
+            .arg(format!("-rpcport={rpc_port}"))
+            .arg(format!("-port={}", free_port()))
+            .args([
+                "-server",
+                "-listen=0",
+                "-dnsseed=0",
+                "-fixedseeds=0",
+                "-rpcbind=127.0.0.1",
+                "-rpcallowip=127.0.0.1",
+                "-fallbackfee=0.0001",
+                "-txindex",
+                "-printtoconsole=0",
+            ])
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|error| format!("could not run {}: {error}", bitcoind_binary().display()))?;
+
+        let url = format!("http://127.0.0.1:{rpc_port}");
+        let cookie = datadir.join("regtest/.cookie");
+        let started = Instant::now();
+        loop {
+            if let Some(status) = process.try_wait().map_err(|error| error.to_string())? {
+                let mut stderr = String::new();
+                if let Some(mut pipe) = process.stderr.take() {
+                    let _ = std::io::Read::read_to_string(&mut pipe, &mut stderr);
+                }
+                return Err(format!("exited with {status}: {}", stderr.trim()));
+            }
+            if cookie.exists() {
+                let rpc = Client::new(&url, Auth::CookieFile(cookie.clone()))
+                    .map_err(|error| error.to_string())?;
+                // Fails with "warming up" until the node is ready
+                if rpc.get_blockchain_info().is_ok() {
+                    return Ok(RegtestNode {
+                        datadir: datadir.to_path_buf(),
+                        rpc_port,
+                        rpc,
+                        process,
+                    });
+                }
+            }
+            if started.elapsed() > STARTUP_TIMEOUT {
+                let _ = process.kill();
+                let _ = process.wait();
+                return Err("timed out waiting for RPC".to_string());
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://127.0.0.1:{}", self.rpc_port)
+    }
+
+    pub fn auth(&self) -> Auth {
+        Auth::CookieFile(self.datadir.join("regtest/.cookie"))
+    }
+
+    // Creates a wallet and returns a client bound to it
+    pub fn create_wallet(&self, name: &str) -> Client {
+        self.rpc
+            .create_wallet(name, None, None, None, None)
+            .unwrap_or_else(|error| panic!("Could not create wallet {name}: {error}"));
+        self.wallet(name)
+    }
+
+    // Client bound to a loaded wallet
+    pub fn wallet(&self, name: &str) -> Client {
+        Client::new(&format!("{}/wallet/{name}", self.url()), self.auth()).unwrap()
+    }
+
+    pub fn new_address(&self, wallet: &Client) -> Address {
+        wallet
+            .get_new_address(None, None)
+            .unwrap()
+            .require_network(Network::Regtest)
+            .unwrap()
+    }
+
+    pub fn mine(&self, blocks: u64, address: &Address) {
+        self.rpc.generate_to_address(blocks, address).unwrap();
+    }
+
+    // Mines a block paying to the wallet and matures it, so the wallet can spend 50 BTC
+    pub fn fund(&self, wallet: &Client) -> Address {
+        let address = self.new_address(wallet);
+        self.mine(COINBASE_MATURITY + 1, &address);
+        address
+    }
+
+    // Sends `amount` from the wallet to `address`, confirms it and returns the new UTXO
+    pub fn send_and_confirm(&self, wallet: &Client, address: &Address, amount: Amount) -> Utxo {
+        let txid = wallet
+            .send_to_address(address, amount, None, None, None, None, None, None)
+            .unwrap();
+        let miner = self.new_address(wallet);
+        self.mine(1, &miner);
+
+        let tx = self.transaction(&txid);
+        let vout = tx
+            .output
+            .iter()
+            .position(|x| x.script_pubkey == address.script_pubkey() && x.value == amount)
+            .expect("Payment not found");
+        Utxo {
+            outpoint: OutPoint {
+                txid,
+                vout: vout as u32,
+            },
+            txout: tx.output[vout].clone(),
+        }
+    }
+
+    pub fn transaction(&self, txid: &Txid) -> transaction::Transaction {
+        self.rpc.get_raw_transaction(txid, None).unwrap()
+    }
+}
+
+impl Drop for RegtestNode {
+    fn drop(&mut self) {
+        let _ = self.rpc.stop();
+        let stopped = Instant::now();
+        while !matches!(self.process.try_wait(), Ok(Some(_))) {
+            if stopped.elapsed() > SHUTDOWN_TIMEOUT {
+                let _ = self.process.kill();
+                let _ = self.process.wait();
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        let _ = fs::remove_dir_all(&self.datadir);
+    }
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use std::{env, fs, path::PathBuf, process};
+
+use axelar_btc::{
+    config::{Config, ConfigError},
+    state::{Committee, LoadError, State},
+};
+use bitcoin::{key::Secp256k1, Network};
+use bitcoincore_rpc::Auth;
+use common::committee;
+
+// File of this test process, so that tests running in parallel don't share it
+fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("axelar-btc-{}-{name}", process::id()));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn rpc_cookie_overrides_the_user_and_password() {
+    let mut config = Config::default();
+    config.rpc.user = Some("user".to_string());
+    config.rpc.pass = Some("pass".to_string());
+    assert_eq!(
+        config.rpc_auth(),
+        Some(Auth::UserPass("user".to_string(), "pass".to_string()))
+    );
+
+    let cookie = PathBuf::from("/bitcoin/regtest/.cookie");
+    config.set_rpc_cookie(cookie.clone());
+    assert_eq!(config.rpc_auth(), Some(Auth::CookieFile(cookie)));
+}
+
+#[test]
+fn bad_files_are_errors() {
+    let secp = Secp256k1::new();
+    let committee = committee(&secp);
+    let path = temp_file("committee.json", "");
+    committee.save(&path);
+    assert_eq!(Committee::load(&path).unwrap(), committee);
+
+    let state = State::new(Network::Regtest);
+    let path = temp_file("state.json", "");
+    state.save(&path);
+    assert!(State::load(&path, Network::Regtest).is_ok());
+    assert!(matches!(
+        State::load(&path, Network::Testnet),
+        Err(LoadError::Network {
+            network: Network::Regtest,
+            ..
+        })
+    ));
+
+    let path = temp_file("invalid.json", "{");
+    assert!(matches!(Committee::load(&path), Err(LoadError::Parse(..))));
+    assert!(matches!(
+        State::load(&path, Network::Regtest),
+        Err(LoadError::Parse(..))
+    ));
+
+    // A missing committee file is an error, a missing state file a new state
+    let missing = env::temp_dir().join(format!("axelar-btc-{}-missing", process::id()));
+    assert!(matches!(
+        Committee::load(&missing),
+        Err(LoadError::Read(..))
+    ));
+    let state = State::load(&missing, Network::Regtest).unwrap();
+    assert!(state.committee.is_none() && state.utxos.is_empty());
+}
+
+#[test]
+fn bad_config_is_an_error() {
+    let path = temp_file(
+        "config.toml",
+        "network = \"regtest\"\n[fees]\nfee_rate = 20\n",
+    );
+    let config = Config::load(Some(&path)).unwrap();
+    assert_eq!(
+        (config.network, config.fees.fee_rate),
+        (Network::Regtest, 20)
+    );
+
+    let path = temp_file("invalid.toml", "fee_rate = 20\n");
+    assert!(matches!(
+        Config::load(Some(&path)),
+        Err(ConfigError::Parse(..))
+    ));
+    let missing = env::temp_dir().join(format!("axelar-btc-{}-missing.toml", process::id()));
+    assert!(matches!(
+        Config::load(Some(&missing)),
+        Err(ConfigError::Read(..))
+    ));
+
+    // The only test of this file setting the environment
+    env::set_var("AXELAR_BTC_MAX_BATCH_SIZE", "many");
+    let result = Config::load(None);
+    env::remove_var("AXELAR_BTC_MAX_BATCH_SIZE");
+    assert!(matches!(
+        result,
+        Err(ConfigError::Env { name, value })
+            if name == "AXELAR_BTC_MAX_BATCH_SIZE" && value == "many"
+    ));
+}
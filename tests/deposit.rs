@@ -0,0 +1,152 @@
+mod common;
+
+use axelar_btc::{
+    deposit::{DepositBuilder, DepositError, DepositSigner, KeySigner},
+    get_private_key,
+    interpreter::verify_transaction,
+    Utxo,
+};
+use bitcoin::{
+    key::Secp256k1, Amount, CompressedPublicKey, FeeRate, Network, PrivateKey, ScriptBuf,
+};
+use common::utxo;
+
+const DESTINATION: &str = "ethereum:0x0000000000000000000000000000000000000000";
+
+fn user_key() -> PrivateKey {
+    get_private_key(100, Network::Regtest).unwrap().to_priv()
+}
+
+fn builder(amount: u64, utxos: Vec<Utxo>) -> DepositBuilder {
+    let secp = Secp256k1::new();
+    let committee = ScriptBuf::new_p2tr(
+        &secp,
+        get_private_key(0, Network::Regtest)
+            .unwrap()
+            .to_keypair(&secp)
+            .x_only_public_key()
+            .0,
+        None,
+    );
+    DepositBuilder {
+        amount: Amount::from_sat(amount),
+        script_pubkey: committee,
+        destination: DESTINATION.to_string(),
+        change_script_pubkey: p2wpkh(&user_key()),
+        fee_rate: FeeRate::from_sat_per_vb(10).unwrap(),
+        utxos,
+    }
+}
+
+fn p2wpkh(key: &PrivateKey) -> ScriptBuf {
+    let secp = Secp256k1::new();
+    ScriptBuf::new_p2wpkh(
+        &CompressedPublicKey::from_private_key(&secp, key)
+            .unwrap()
+            .wpubkey_hash(),
+    )
+}
+
+fn p2tr(key: &PrivateKey) -> ScriptBuf {
+    let secp = Secp256k1::new();
+    ScriptBuf::new_p2tr(&secp, key.inner.x_only_public_key(&secp).0, None)
+}
+
+#[test]
+fn fee_covers_the_signed_size() {
+    let secp = Secp256k1::new();
+    let key = user_key();
+    for script_pubkey in [p2wpkh(&key), p2tr(&key)] {
+        let deposit = builder(
+            50_000,
+            vec![
+                utxo(0, 30_000, &script_pubkey),
+                utxo(1, 40_000, &script_pubkey),
+            ],
+        )
+        .build()
+        .unwrap();
+        let tx = KeySigner { key }.sign_deposit(&deposit, &secp).unwrap();
+
+        assert_eq!(tx.output[0].value, Amount::from_sat(50_000));
+        assert_eq!(tx.output.len(), 3, "Expected change");
+        let spent = deposit.prevouts.iter().map(|x| x.value).sum::<Amount>();
+        let paid = tx.output.iter().map(|x| x.value).sum::<Amount>();
+        assert_eq!(spent - paid, deposit.fee);
+        // ECDSA signatures may be a byte shorter than predicted
+        let vsize = tx.vsize() as u64;
+        assert!(deposit.fee >= Amount::from_sat(10 * vsize));
+        assert!(deposit.fee <= Amount::from_sat(10 * (vsize + deposit.prevouts.len() as u64)));
+
+        if script_pubkey.is_p2tr() {
+            verify_transaction(&tx, &deposit.prevouts, &secp).unwrap();
+        }
+    }
+}
+
+#[test]
+fn largest_utxos_are_spent_first() {
+    let script_pubkey = p2tr(&user_key());
+    let deposit = builder(
+        20_000,
+        vec![
+            utxo(0, 10_000, &script_pubkey),
+            utxo(1, 100_000, &script_pubkey),
+            utxo(2, 30_000, &script_pubkey),
+        ],
+    )
+    .build()
+    .unwrap();
+
+    assert_eq!(deposit.tx.input.len(), 1);
+    assert_eq!(deposit.prevouts[0].value, Amount::from_sat(100_000));
+}
+
+#[test]
+fn dust_change_goes_to_the_miners() {
+    let script_pubkey = p2tr(&user_key());
+    let exact = builder(50_000, vec![utxo(0, 1_000_000, &script_pubkey)])
+        .build()
+        .unwrap();
+    let fee_without_change = exact.fee - Amount::from_sat(10 * 31); // The P2WPKH change output
+
+    let deposit = builder(
+        50_000,
+        vec![utxo(
+            0,
+            50_000 + fee_without_change.to_sat() + 100,
+            &script_pubkey,
+        )],
+    )
+    .build()
+    .unwrap();
+
+    assert_eq!(deposit.tx.output.len(), 2);
+    assert_eq!(deposit.fee, fee_without_change + Amount::from_sat(100));
+}
+
+#[test]
+fn deposits_are_checked() {
+    let script_pubkey = p2tr(&user_key());
+    let utxos = vec![utxo(0, 10_000, &script_pubkey)];
+
+    assert!(matches!(
+        builder(20_000, utxos.clone()).build(),
+        Err(DepositError::InsufficientFunds { .. })
+    ));
+    assert!(matches!(
+        builder(100, utxos.clone()).build(),
+        Err(DepositError::DustDeposit { .. })
+    ));
+    assert!(matches!(
+        builder(5_000, vec![utxo(0, 10_000, &ScriptBuf::new_op_return([1]))]).build(),
+        Err(DepositError::UnsupportedInput(_))
+    ));
+    assert!(matches!(
+        KeySigner {
+            key: get_private_key(101, Network::Regtest).unwrap().to_priv()
+        }
+        .sign_deposit(&builder(5_000, utxos).build().unwrap(), &Secp256k1::new()),
+        Err(DepositError::Signing(_))
+    ));
+}
@@ -0,0 +1,183 @@
+mod common;
+
+use axelar_btc::{
+    deposit::{DepositError, RefundBuilder},
+    deposit_address::{find_deposits, DepositAddress, RefundLeaf},
+    get_private_key,
+    interpreter::verify_transaction,
+    multisig_prover::{HandoverParams, MultisigProver},
+    psbt::{finalize_psbt, prevouts, set_deposit_leaves},
+    state::{Committee, State},
+};
+use bitcoin::{
+    absolute::LockTime, key::Secp256k1, secp256k1::All, transaction, Amount, FeeRate, Network,
+    PrivateKey, ScriptBuf, Sequence, TapSighashType, TxOut,
+};
+use common::{utxo, validators};
+
+const DESTINATION: &str = "ethereum:0x0000000000000000000000000000000000000001";
+
+fn deposit(committee: &Committee, destination: &str, secp: &Secp256k1<All>) -> DepositAddress {
+    DepositAddress::from_destination(committee, destination, secp).unwrap()
+}
+
+fn refund_key() -> PrivateKey {
+    get_private_key(100, Network::Regtest).unwrap().to_priv()
+}
+
+fn refundable(committee: &Committee, secp: &Secp256k1<All>) -> DepositAddress {
+    let refund = RefundLeaf {
+        key: refund_key().inner.x_only_public_key(secp).0,
+        delay: 144,
+    };
+    deposit(committee, DESTINATION, secp).with_refund(refund, secp)
+}
+
+#[test]
+fn addresses_are_bound_to_the_destination() {
+    let secp = Secp256k1::new();
+    let committee = Committee::from_validators(&validators(), 2, &secp);
+    let (_, committee_script_pubkey) = committee.scripts(&secp);
+
+    let addresses = [
+        deposit(&committee, DESTINATION, &secp),
+        deposit(&committee, &format!("{DESTINATION}:payload"), &secp),
+        deposit(
+            &committee,
+            "avalanche:0x0000000000000000000000000000000000000001",
+            &secp,
+        ),
+    ];
+    for (i, address) in addresses.iter().enumerate() {
+        assert!(address.script_pubkey.is_p2tr());
+        assert_ne!(address.script_pubkey, committee_script_pubkey);
+        for other in &addresses[i + 1..] {
+            assert_ne!(address.script_pubkey, other.script_pubkey);
+        }
+    }
+    // Addresses are derived deterministically from the committee and the destination
+    assert_eq!(addresses[0], deposit(&committee, DESTINATION, &secp));
+    assert!(DepositAddress::from_destination(&committee, "ethereum", &secp).is_none());
+}
+
+#[test]
+fn deposits_are_recognized() {
+    let secp = Secp256k1::new();
+    let committee = Committee::from_validators(&validators(), 2, &secp);
+    let address = deposit(&committee, DESTINATION, &secp);
+    let tx = transaction::Transaction {
+        version: transaction::Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![],
+        output: vec![
+            TxOut {
+                value: Amount::from_sat(1000),
+                script_pubkey: ScriptBuf::new_op_return([1]),
+            },
+            TxOut {
+                value: Amount::from_sat(50_000),
+                script_pubkey: address.script_pubkey.clone(),
+            },
+        ],
+    };
+    assert_eq!(
+        find_deposits(&tx, std::slice::from_ref(&address)),
+        vec![(1, &address)]
+    );
+
+    let mut state = State::new(Network::Regtest);
+    state.deposit_addresses.push(address.clone());
+    assert_eq!(state.add_deposits(&tx).len(), 1);
+    assert_eq!(state.utxos[0].outpoint.vout, 1);
+    // Registering the same transaction twice doesn't duplicate the UTXO
+    assert!(state.add_deposits(&tx).is_empty());
+    assert_eq!(state.utxos.len(), 1);
+}
+
+#[test]
+fn committee_spends_deposits() {
+    let secp = Secp256k1::new();
+    let validators = validators();
+    let committee = Committee::from_validators(&validators, 2, &secp);
+    let (_, committee_script_pubkey) = committee.scripts(&secp);
+    let address = deposit(&committee, DESTINATION, &secp);
+    let refundable = refundable(&committee, &secp);
+    assert_ne!(address.script_pubkey, refundable.script_pubkey);
+
+    let prover = MultisigProver {
+        available_utxos: vec![
+            utxo(0, 100_000, &committee_script_pubkey),
+            utxo(1, 100_000, &address.script_pubkey),
+            utxo(2, 100_000, &refundable.script_pubkey),
+        ],
+    };
+    let mut psbt = prover
+        .create_handover_psbts(
+            HandoverParams {
+                max_output_no: 1,
+                max_tx_size: 100_000,
+                miner_fee: Amount::from_sat(1000),
+                dust_limit: Amount::from_sat(330),
+            },
+            &committee,
+            &committee_script_pubkey,
+            TapSighashType::Default,
+            &secp,
+        )
+        .unwrap()
+        .remove(0);
+    set_deposit_leaves(&mut psbt, &[address, refundable], &secp);
+    for validator in &validators[..2] {
+        assert_eq!(validator.sign_psbt(&mut psbt, &secp).unwrap(), 3);
+    }
+    finalize_psbt(
+        &mut psbt,
+        &committee.pks_weights(),
+        committee.threshold,
+        &secp,
+    )
+    .unwrap();
+
+    let prevouts = prevouts(&psbt).unwrap();
+    let tx = psbt.extract_tx_unchecked_fee_rate();
+    verify_transaction(&tx, &prevouts, &secp).unwrap();
+}
+
+#[test]
+fn depositor_takes_back_refundable_deposits() {
+    let secp = Secp256k1::new();
+    let committee = Committee::from_validators(&validators(), 2, &secp);
+    let address = refundable(&committee, &secp);
+    let key = refund_key();
+    let builder = RefundBuilder {
+        deposit: address.clone(),
+        utxo: utxo(0, 100_000, &address.script_pubkey),
+        script_pubkey: ScriptBuf::new_p2tr(&secp, key.inner.x_only_public_key(&secp).0, None),
+        fee_rate: FeeRate::from_sat_per_vb(2).unwrap(),
+    };
+
+    let refund = builder.build(&secp).unwrap();
+    let tx = refund.sign(&key, &secp).unwrap();
+    assert_eq!(tx.input[0].sequence, Sequence::from_height(144));
+    assert_eq!(tx.output[0].value + refund.fee, Amount::from_sat(100_000));
+    assert!(refund.fee >= Amount::from_sat(2 * tx.vsize() as u64));
+    verify_transaction(&tx, std::slice::from_ref(&refund.prevout), &secp).unwrap();
+
+    // Only the refund key can sign
+    let other = get_private_key(101, Network::Regtest).unwrap().to_priv();
+    assert!(matches!(
+        refund.sign(&other, &secp),
+        Err(DepositError::Signing(_))
+    ));
+    // Deposits without a refund leaf can't be refunded
+    let plain = deposit(&committee, DESTINATION, &secp);
+    assert!(matches!(
+        RefundBuilder {
+            utxo: utxo(0, 100_000, &plain.script_pubkey),
+            deposit: plain,
+            ..builder
+        }
+        .build(&secp),
+        Err(DepositError::NotRefundable)
+    ));
+}
@@ -0,0 +1,93 @@
+use std::str::FromStr;
+
+use axelar_btc::{
+    descriptor::{checksum, CommitteeDescriptor, DescriptorError},
+    get_private_key,
+    state::Committee,
+    validator::Validator,
+};
+use bitcoin::{key::Secp256k1, Network, XOnlyPublicKey};
+
+fn committee() -> Committee {
+    let validators: Vec<Validator> = (0..3)
+        .map(|i| {
+            Validator::with_key(
+                &format!("validator-{i}"),
+                i as i64 + 1,
+                get_private_key(i, Network::Regtest).unwrap(),
+            )
+        })
+        .collect();
+    Committee::from_validators(&validators, 4, &Secp256k1::new())
+}
+
+#[test]
+fn checksums_follow_bip380() {
+    assert_eq!(checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+    assert_eq!(checksum("raw(deadbeef)\u{e9}"), None);
+}
+
+#[test]
+fn descriptors_round_trip() {
+    let secp = Secp256k1::new();
+    let committee = committee();
+    let descriptor = CommitteeDescriptor::new(&committee);
+    let string = descriptor.to_string();
+    assert!(string.starts_with(&format!("tr({},weighted_multi(4,", committee.internal_key)));
+
+    let parsed = CommitteeDescriptor::from_str(&string).unwrap();
+    assert_eq!(parsed, descriptor);
+    assert_eq!(parsed.scripts(&secp), committee.scripts(&secp));
+    assert_eq!(parsed.to_string(), string);
+
+    // Without the checksum
+    let (without_checksum, _) = string.split_once('#').unwrap();
+    assert_eq!(
+        CommitteeDescriptor::from_str(without_checksum).unwrap(),
+        descriptor
+    );
+
+    // The watch-only descriptor is the taproot output key of the same address
+    let (_, script_pubkey) = committee.scripts(&secp);
+    let watch_only = descriptor.watch_only(&secp);
+    let (rawtr, found) = watch_only.split_once('#').unwrap();
+    assert_eq!(checksum(rawtr).unwrap(), found);
+    assert_eq!(
+        rawtr,
+        format!(
+            "rawtr({})",
+            XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..]).unwrap()
+        )
+    );
+    assert_eq!(
+        descriptor.address(Network::Regtest, &secp).script_pubkey(),
+        script_pubkey
+    );
+}
+
+#[test]
+fn invalid_descriptors_are_refused() {
+    let descriptor = CommitteeDescriptor::new(&committee()).to_string();
+    let (body, _) = descriptor.split_once('#').unwrap();
+
+    assert!(matches!(
+        CommitteeDescriptor::from_str(&format!("{body}#qqqqqqqq")),
+        Err(DescriptorError::Checksum { .. })
+    ));
+    assert!(matches!(
+        CommitteeDescriptor::from_str(&body.replacen("weighted_multi(4", "weighted_multi(7", 1)),
+        Err(DescriptorError::Syntax(_))
+    ));
+    assert!(matches!(
+        CommitteeDescriptor::from_str(&body.replacen(":1", ":x", 1)),
+        Err(DescriptorError::Syntax(_))
+    ));
+    assert!(matches!(
+        CommitteeDescriptor::from_str(&body.replacen("tr(", "tr(zz", 1)),
+        Err(DescriptorError::Key(_))
+    ));
+    assert!(matches!(
+        CommitteeDescriptor::from_str("wsh(multi(1,02aa))"),
+        Err(DescriptorError::Syntax(_))
+    ));
+}
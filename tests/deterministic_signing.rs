@@ -0,0 +1,32 @@
+use axelar_btc::{get_private_key, validator::Validator};
+use bitcoin::{
+    hashes::Hash, key::Secp256k1, secp256k1::Message, Network, TapSighash, TapSighashType,
+};
+
+#[test]
+fn seeded_signatures_are_reproducible() {
+    let secp = Secp256k1::new();
+    let key = get_private_key(0, Network::Regtest).unwrap();
+    let sighash = TapSighash::from_byte_array([7; 32]);
+    let sign = |validator: &Validator| {
+        validator
+            .sign_sighash(&sighash, TapSighashType::Default, &secp)
+            .unwrap()
+    };
+
+    let seeded = Validator::with_seeded_key("validator-0", 1, key, [1; 32]);
+    let signature = sign(&seeded);
+    assert_eq!(
+        sign(&Validator::with_seeded_key("validator-0", 1, key, [1; 32])),
+        signature
+    );
+    assert_ne!(
+        sign(&Validator::with_seeded_key("validator-0", 1, key, [2; 32])),
+        signature
+    );
+    assert_ne!(sign(&Validator::with_key("validator-0", 1, key)), signature);
+
+    let msg = Message::from_digest(sighash.to_byte_array());
+    secp.verify_schnorr(&signature.signature, &msg, &seeded.public_key(&secp))
+        .unwrap();
+}
@@ -0,0 +1,186 @@
+mod common;
+
+use axelar_btc::{
+    collect_signatures,
+    evidence::{ConflictEvidence, EvidenceLog},
+    state::Committee,
+    taproot_sighashes,
+};
+use bitcoin::{
+    absolute::LockTime,
+    hashes::{sha256d, Hash},
+    key::Secp256k1,
+    secp256k1::All,
+    taproot::Signature,
+    transaction, Amount, OutPoint, ScriptBuf, Sequence, TapSighashType, TxIn, TxOut, Txid, Witness,
+};
+use common::validators;
+
+// Spends a committee UTXO of 100k sats, paying 50k sats to `receiver` and the rest but the fee
+// back to the committee
+fn tx(receiver: u8, fee: u64, script_pubkey: &ScriptBuf) -> transaction::Transaction {
+    transaction::Transaction {
+        version: transaction::Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint {
+                txid: Txid::from_raw_hash(sha256d::Hash::hash(b"committee utxo")),
+                vout: 0,
+            },
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        }],
+        output: vec![
+            TxOut {
+                value: Amount::from_sat(50_000),
+                script_pubkey: ScriptBuf::new_op_return([receiver]),
+            },
+            TxOut {
+                value: Amount::from_sat(50_000 - fee),
+                script_pubkey: script_pubkey.clone(),
+            },
+        ],
+    }
+}
+
+// Signatures of the validators in `signers` on `tx`
+fn sign(
+    tx: &transaction::Transaction,
+    prevouts: &[TxOut],
+    committee: &Committee,
+    sighash_type: TapSighashType,
+    signers: &[usize],
+    secp: &Secp256k1<All>,
+) -> Vec<Vec<Option<Signature>>> {
+    let (script, _) = committee.scripts(secp);
+    let sighashes = taproot_sighashes(tx, prevouts, &script, sighash_type).unwrap();
+    collect_signatures(&sighashes, sighash_type, &validators(), secp)
+        .into_iter()
+        .map(|signatures| {
+            signatures
+                .into_iter()
+                .enumerate()
+                .map(|(i, x)| x.filter(|_| signers.contains(&i)))
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn conflicting_payouts_are_evidence() {
+    let secp = Secp256k1::new();
+    let committee = Committee::from_validators(&validators(), 2, &secp);
+    let (_, script_pubkey) = committee.scripts(&secp);
+    let prevouts = vec![TxOut {
+        value: Amount::from_sat(100_000),
+        script_pubkey: script_pubkey.clone(),
+    }];
+    let mut log = EvidenceLog::default();
+    let mut record = |tx: &transaction::Transaction, sighash_type, signers: &[usize]| {
+        let signatures = sign(tx, &prevouts, &committee, sighash_type, signers, &secp);
+        log.record_signatures(tx, &prevouts, &committee, &signatures, &secp)
+    };
+
+    let peg_out = tx(1, 1000, &script_pubkey);
+    assert_eq!(record(&peg_out, TapSighashType::Default, &[0, 1, 2]), 0);
+    // Signed again, under another sighash type, and bumped: same recipients
+    assert_eq!(record(&peg_out, TapSighashType::Default, &[0, 1, 2]), 0);
+    assert_eq!(record(&peg_out, TapSighashType::All, &[0, 1, 2]), 0);
+    let bumped = tx(1, 3000, &script_pubkey);
+    assert_eq!(record(&bumped, TapSighashType::Default, &[0, 1]), 0);
+
+    // Validator 2 pays someone else with the same UTXO
+    let theft = tx(2, 1000, &script_pubkey);
+    assert_eq!(record(&theft, TapSighashType::Default, &[2]), 1);
+    assert_eq!(record(&theft, TapSighashType::Default, &[2]), 0);
+    // Validator 1 pays the same recipient less, and the committee more
+    let mut skimmed = peg_out.clone();
+    skimmed.output[0].value -= Amount::from_sat(10_000);
+    skimmed.output[1].value += Amount::from_sat(10_000);
+    assert_eq!(record(&skimmed, TapSighashType::Default, &[1]), 1);
+    assert_eq!(log.conflicts.len(), 2);
+    assert_eq!(log.conflicts[1].operator_address, "validator-1");
+    let conflict = &log.conflicts[0];
+    assert_eq!(conflict.operator_address, "validator-2");
+    assert_eq!(conflict.outpoint, peg_out.input[0].previous_output);
+    assert_eq!(conflict.first.tx, peg_out);
+    assert_eq!(conflict.second.tx, theft);
+    assert!(conflict.verify(&secp));
+
+    // The bundle verifies on its own
+    let bundle: Vec<ConflictEvidence> = serde_json::from_str(&log.export()).unwrap();
+    assert!(bundle[0].verify(&secp));
+    let log: EvidenceLog = serde_json::from_str(&serde_json::to_string(&log).unwrap()).unwrap();
+    assert_eq!(log.conflicts.len(), 2);
+}
+
+#[test]
+fn evidence_has_to_prove_a_conflict() {
+    let secp = Secp256k1::new();
+    let committee = Committee::from_validators(&validators(), 2, &secp);
+    let (_, script_pubkey) = committee.scripts(&secp);
+    let prevouts = vec![TxOut {
+        value: Amount::from_sat(100_000),
+        script_pubkey: script_pubkey.clone(),
+    }];
+    let mut log = EvidenceLog::default();
+    for receiver in [1, 2] {
+        let tx = tx(receiver, 1000, &script_pubkey);
+        let signatures = sign(
+            &tx,
+            &prevouts,
+            &committee,
+            TapSighashType::Default,
+            &[0],
+            &secp,
+        );
+        log.record_signatures(&tx, &prevouts, &committee, &signatures, &secp);
+    }
+    let conflict = &log.conflicts[0];
+    assert!(conflict.verify(&secp));
+
+    // A fee bump isn't a conflict
+    let mut bump = conflict.clone();
+    bump.second = bump.first.clone();
+    bump.second.tx.output[1].value -= Amount::from_sat(1000);
+    let (script, _) = committee.scripts(&secp);
+    let sighash =
+        taproot_sighashes(&bump.second.tx, &prevouts, &script, TapSighashType::Default).unwrap()[0];
+    bump.second.signature = validators()[0]
+        .sign_sighash(&sighash, TapSighashType::Default, &secp)
+        .unwrap();
+    assert!(!bump.verify(&secp));
+
+    // Made-up signed inputs are rejected rather than computed
+    let mut made_up = conflict.clone();
+    made_up.second.input_index = 1;
+    assert!(!made_up.verify(&secp));
+    let mut made_up = conflict.clone();
+    made_up.second.prevouts.clear();
+    assert!(!made_up.verify(&secp));
+    let mut made_up = conflict.clone();
+    made_up.second.signature.sighash_type = TapSighashType::None;
+    assert!(!made_up.verify(&secp));
+
+    // Nor is someone else's signature
+    let mut forged = conflict.clone();
+    forged.public_key = validators()[1].public_key(&secp);
+    assert!(!forged.verify(&secp));
+
+    // Signatures that don't verify aren't recorded
+    let tx = tx(3, 1000, &script_pubkey);
+    let mut signatures = sign(
+        &tx,
+        &prevouts,
+        &committee,
+        TapSighashType::Default,
+        &[1],
+        &secp,
+    );
+    signatures[0].swap(0, 1);
+    assert_eq!(
+        log.record_signatures(&tx, &prevouts, &committee, &signatures, &secp),
+        0
+    );
+}
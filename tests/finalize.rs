@@ -0,0 +1,177 @@
+mod common;
+
+use axelar_btc::{
+    collect_signatures, finalize_verified_witness,
+    interpreter::verify_transaction,
+    multisig_prover::{HandoverParams, MultisigProver},
+    state::Committee,
+    verify_signatures, MultisigSpend, Utxo,
+};
+use bitcoin::{key::Secp256k1, Amount, TapSighashType, TxOut};
+use common::{utxo, validators};
+
+#[test]
+fn invalid_signatures_are_dropped_and_reported() {
+    let secp = Secp256k1::new();
+    let validators = validators();
+    let committee = Committee::from_validators(&validators, 2, &secp);
+    let (script, script_pubkey) = committee.scripts(&secp);
+    let utxos: Vec<Utxo> = (0..2).map(|i| utxo(i, 100_000, &script_pubkey)).collect();
+    let prevouts: Vec<TxOut> = utxos.iter().map(|x| x.txout.clone()).collect();
+    let prover = MultisigProver {
+        available_utxos: utxos,
+    };
+    let (unsigned_tx, sighashes) = prover
+        .create_handover_tx(
+            HandoverParams {
+                max_output_no: 1,
+                max_tx_size: 100_000,
+                miner_fee: Amount::from_sat(1000),
+                dust_limit: Amount::from_sat(330),
+            },
+            &script,
+            &script_pubkey,
+            TapSighashType::Default,
+        )
+        .unwrap()
+        .remove(0);
+    assert_eq!(unsigned_tx.input.len(), 2);
+
+    let pks_weights = committee.pks_weights();
+    let spend = MultisigSpend {
+        script: &script,
+        internal_key: &committee.internal_key,
+        pks_weights: &pks_weights,
+        threshold: committee.threshold,
+    };
+    let mut signatures =
+        collect_signatures(&sighashes, TapSighashType::Default, &validators, &secp);
+    // Validator 0 doesn't sign the first input, and validator 2 signs the second one with the
+    // first one's sighash
+    signatures[0][0] = None;
+    signatures[1][2] = signatures[0][2];
+
+    let mut tx = unsigned_tx.clone();
+    let report = finalize_verified_witness(
+        &mut tx,
+        &sighashes,
+        TapSighashType::Default,
+        signatures.clone(),
+        spend,
+        &secp,
+    )
+    .unwrap();
+    assert_eq!(report.invalid_signatures, [(1, 2)]);
+    assert_eq!(report.missing_signatures, [(0, 0)]);
+    assert_eq!(report.misbehaving_validators(), [2]);
+    assert_eq!(report.verified_weights, [2, 2]);
+    // The invalid signature isn't in the witness, so the transaction still verifies
+    verify_transaction(&tx, &prevouts, &secp).unwrap();
+
+    // Without validator 1 the second input is below the threshold
+    signatures[1][1] = None;
+    let mut tx = unsigned_tx.clone();
+    let report = finalize_verified_witness(
+        &mut tx,
+        &sighashes,
+        TapSighashType::Default,
+        signatures,
+        spend,
+        &secp,
+    )
+    .unwrap_err();
+    assert_eq!(report.inputs_below_threshold, [1]);
+    assert_eq!(report.misbehaving_validators(), [2]);
+    assert_eq!(report.verified_weights, [2, 1]);
+    assert_eq!(tx, unsigned_tx);
+}
+
+#[test]
+fn mismatched_lengths_are_below_the_threshold() {
+    let secp = Secp256k1::new();
+    let validators = validators();
+    let committee = Committee::from_validators(&validators, 2, &secp);
+    let (script, script_pubkey) = committee.scripts(&secp);
+    let utxos: Vec<Utxo> = (0..2).map(|i| utxo(i, 100_000, &script_pubkey)).collect();
+    let prover = MultisigProver {
+        available_utxos: utxos,
+    };
+    let (unsigned_tx, sighashes) = prover
+        .create_handover_tx(
+            HandoverParams {
+                max_output_no: 1,
+                max_tx_size: 100_000,
+                miner_fee: Amount::from_sat(1000),
+                dust_limit: Amount::from_sat(330),
+            },
+            &script,
+            &script_pubkey,
+            TapSighashType::Default,
+        )
+        .unwrap()
+        .remove(0);
+    let pks_weights = committee.pks_weights();
+    let signatures = collect_signatures(&sighashes, TapSighashType::Default, &validators, &secp);
+
+    // A row without a slot for validator 2
+    let mut short = signatures.clone();
+    short[1].pop();
+    let report = verify_signatures(
+        &sighashes,
+        TapSighashType::Default,
+        &mut short,
+        &pks_weights,
+        committee.threshold,
+        &secp,
+    );
+    assert_eq!(report.inputs_below_threshold, [1]);
+    assert_eq!(report.verified_weights, [3, 0]);
+    assert!(short[1].iter().all(Option::is_none));
+
+    // No signatures for the second input
+    let mut missing = signatures[..1].to_vec();
+    let report = verify_signatures(
+        &sighashes,
+        TapSighashType::Default,
+        &mut missing,
+        &pks_weights,
+        committee.threshold,
+        &secp,
+    );
+    assert_eq!(report.inputs_below_threshold, [1]);
+    assert_eq!(report.verified_weights, [3, 0]);
+
+    let spend = MultisigSpend {
+        script: &script,
+        internal_key: &committee.internal_key,
+        pks_weights: &pks_weights,
+        threshold: committee.threshold,
+    };
+    // No sighash for the second input
+    let mut tx = unsigned_tx.clone();
+    let report = finalize_verified_witness(
+        &mut tx,
+        &sighashes[..1],
+        TapSighashType::Default,
+        signatures[..1].to_vec(),
+        spend,
+        &secp,
+    )
+    .unwrap_err();
+    assert_eq!(report.inputs_below_threshold, [1]);
+    assert_eq!(tx, unsigned_tx);
+
+    // A sighash without an input
+    let mut tx = unsigned_tx.clone();
+    tx.input.pop();
+    let report = finalize_verified_witness(
+        &mut tx,
+        &sighashes,
+        TapSighashType::Default,
+        signatures,
+        spend,
+        &secp,
+    )
+    .unwrap_err();
+    assert_eq!(report.inputs_below_threshold, [1]);
+}
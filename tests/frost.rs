@@ -0,0 +1,136 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use axelar_btc::{
+    frost::{
+        collect_frost_signatures, dkg_part1, dkg_part2, dkg_part3, finalize_key_spend,
+        key_spend_sighashes, run_dkg, FrostError, ShareAllocation,
+    },
+    interpreter::verify_transaction,
+};
+use bitcoin::{
+    absolute::LockTime,
+    hashes::Hash,
+    key::Secp256k1,
+    secp256k1::{Message, Scalar},
+    transaction, Amount, OutPoint, ScriptBuf, Sequence, TxIn, TxOut, Witness, XOnlyPublicKey,
+};
+use common::txid;
+
+// Shares 3, 2, 1 and 1, of which 5 have to sign
+fn allocation() -> ShareAllocation {
+    ShareAllocation {
+        shares: vec![3, 2, 1, 1],
+        threshold: 5,
+    }
+}
+
+// Spends two outputs of `script_pubkey` to an OP_RETURN
+fn spend(script_pubkey: &ScriptBuf) -> (transaction::Transaction, Vec<TxOut>) {
+    let input = (0..2u32)
+        .map(|i| TxIn {
+            previous_output: OutPoint {
+                txid: txid(i),
+                vout: 0,
+            },
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        })
+        .collect();
+    let tx = transaction::Transaction {
+        version: transaction::Version::TWO,
+        lock_time: LockTime::ZERO,
+        input,
+        output: vec![TxOut {
+            value: Amount::from_sat(19_000),
+            script_pubkey: ScriptBuf::new_op_return([1]),
+        }],
+    };
+    let prevouts = vec![
+        TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: script_pubkey.clone(),
+        };
+        2
+    ];
+    (tx, prevouts)
+}
+
+#[test]
+fn frost_signatures_are_bip340_key_spends() {
+    let secp = Secp256k1::new();
+    let (committee, key_shares) = run_dkg(&allocation(), &secp).unwrap();
+    let script_pubkey = committee.script_pubkey(&secp);
+    let (mut tx, prevouts) = spend(&script_pubkey);
+    let sighashes = key_spend_sighashes(&tx, &prevouts);
+
+    // 3 + 2 shares, and all of them
+    for signers in [&[0, 1][..], &[0, 1, 2, 3]] {
+        let signatures =
+            collect_frost_signatures(&sighashes, &committee, &key_shares, signers, &secp).unwrap();
+        let output_key = XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..]).unwrap();
+        for (signature, sighash) in signatures.iter().zip(&sighashes) {
+            let msg = Message::from_digest(sighash.to_byte_array());
+            secp.verify_schnorr(signature, &msg, &output_key).unwrap();
+        }
+
+        finalize_key_spend(&mut tx, &signatures);
+        verify_transaction(&tx, &prevouts, &secp).unwrap();
+    }
+}
+
+#[test]
+fn frost_needs_the_threshold() {
+    let secp = Secp256k1::new();
+    let (committee, key_shares) = run_dkg(&allocation(), &secp).unwrap();
+    let (tx, prevouts) = spend(&committee.script_pubkey(&secp));
+    let sighashes = key_spend_sighashes(&tx, &prevouts);
+
+    // 3 + 1 shares, one short of the threshold
+    let error =
+        collect_frost_signatures(&sighashes, &committee, &key_shares, &[0, 2], &secp).unwrap_err();
+    assert!(matches!(
+        error,
+        FrostError::NotEnoughSigners {
+            shares: 4,
+            threshold: 5
+        }
+    ));
+}
+
+#[test]
+fn dkg_rejects_short_commitments() {
+    let secp = Secp256k1::new();
+    let (secret_packages, mut round1_packages): (Vec<_>, Vec<_>) =
+        (1..=3).map(|i| dkg_part1(i, 2, &secp)).unzip();
+    let mut received_shares: BTreeMap<u32, Scalar> = BTreeMap::new();
+    for secret_package in &secret_packages {
+        let shares = dkg_part2(secret_package, &round1_packages, &secp).unwrap();
+        received_shares.insert(secret_package.identifier, shares[&1]);
+    }
+
+    // Participant 3 commits to a constant polynomial, i.e. a threshold of 1
+    round1_packages[2].commitments.truncate(1);
+    let invalid_commitments = |error| {
+        matches!(
+            error,
+            FrostError::InvalidCommitments {
+                identifier: 3,
+                commitments: 1,
+                threshold: 2
+            }
+        )
+    };
+    let error = dkg_part2(&secret_packages[0], &round1_packages, &secp).unwrap_err();
+    assert!(invalid_commitments(error));
+    let Err(error) = dkg_part3(1, 2, &round1_packages, &received_shares, &secp) else {
+        panic!("Participant 1 accepted participant 3's commitments");
+    };
+    assert!(invalid_commitments(error));
+
+    // No commitments at all
+    round1_packages[2].commitments.clear();
+    assert!(dkg_part2(&secret_packages[0], &round1_packages, &secp).is_err());
+}
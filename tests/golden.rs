@@ -0,0 +1,258 @@
+// Runs peg-in → handover → peg-out without bitcoind or axelarscan: a synthetic committee signs
+// deterministically (see `SeededSigner`), so every transaction can be compared against the hex
+// vectors in `tests/golden`. A missing vector fails the test; after an intended change, re-record
+// them all with `BLESS=1 cargo test --test golden` and review the diff.
+
+use std::{env, fs, path::PathBuf};
+
+use axelar_btc::{
+    collect_signatures, get_private_key,
+    interpreter::{verify_transaction, ScriptError},
+    multisig_prover::{HandoverParams, MultisigProver},
+    validator::Validator,
+    Utxo,
+};
+use bitcoin::{
+    consensus::encode::serialize_hex,
+    hashes::{sha256d, Hash},
+    key::Secp256k1,
+    secp256k1::All,
+    transaction, Address, Amount, CompressedPublicKey, Network, OutPoint, ScriptBuf,
+    TapSighashType, TxOut, Txid, XOnlyPublicKey,
+};
+use bitcoin_rs::{key::UnspendableKey, script::MultisigScript, transaction::WitnessControl};
+
+const THRESHOLD: i64 = 10;
+
+struct Committee {
+    validators: Vec<Validator>,
+    script: ScriptBuf,
+    script_pubkey: ScriptBuf,
+}
+
+// Validators `keys` with weights 1..=5, each signing with its own seed
+fn committee(keys: std::ops::Range<usize>, secp: &Secp256k1<All>) -> Committee {
+    let validators: Vec<Validator> = keys
+        .enumerate()
+        .map(|(i, key)| {
+            Validator::with_seeded_key(
+                &format!("validator-{key}"),
+                i as i64 + 1,
+                get_private_key(key, Network::Regtest).unwrap(),
+                [key as u8; 32],
+            )
+        })
+        .collect();
+    let pks_weights = validators
+        .iter()
+        .map(|x| (x.public_key(secp), x.weight))
+        .collect();
+    let (script, script_pubkey) = ScriptBuf::create_threshold_multisig_with_weights(
+        &pks_weights,
+        &XOnlyPublicKey::create_unspendable_key(),
+        THRESHOLD,
+        secp,
+    );
+    Committee {
+        validators,
+        script,
+        script_pubkey,
+    }
+}
+
+// Deposits of 1..=n * 0.001 BTC, with made-up txids
+fn peg_in_utxos(n: usize, script_pubkey: &ScriptBuf) -> Vec<Utxo> {
+    (1..=n)
+        .map(|i| Utxo {
+            outpoint: OutPoint {
+                txid: Txid::from_raw_hash(sha256d::Hash::hash(format!("peg-in {i}").as_bytes())),
+                vout: 0,
+            },
+            txout: TxOut {
+                value: Amount::from_sat(100_000 * i as u64),
+                script_pubkey: script_pubkey.clone(),
+            },
+        })
+        .collect()
+}
+
+fn outputs(tx: &transaction::Transaction) -> Vec<Utxo> {
+    tx.output
+        .iter()
+        .enumerate()
+        .map(|(vout, txout)| Utxo {
+            outpoint: OutPoint {
+                txid: tx.compute_txid(),
+                vout: vout as u32,
+            },
+            txout: txout.clone(),
+        })
+        .collect()
+}
+
+fn prevouts(tx: &transaction::Transaction, utxos: &[Utxo]) -> Vec<TxOut> {
+    tx.input
+        .iter()
+        .map(|txin| {
+            utxos
+                .iter()
+                .find(|x| x.outpoint == txin.previous_output)
+                .expect("Unknown prevout")
+                .txout
+                .clone()
+        })
+        .collect()
+}
+
+fn sign(
+    tx: &mut transaction::Transaction,
+    sighashes: &Vec<bitcoin::TapSighash>,
+    committee: &Committee,
+    secp: &Secp256k1<All>,
+) {
+    let signatures = collect_signatures(
+        sighashes,
+        TapSighashType::Default,
+        &committee.validators,
+        secp,
+    );
+    tx.finalize_witness(
+        &signatures,
+        &committee.script,
+        &XOnlyPublicKey::create_unspendable_key(),
+        secp,
+    );
+}
+
+fn check_golden(name: &str, hex: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.hex"));
+    if env::var_os("BLESS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, format!("{hex}\n")).unwrap();
+        eprintln!("Recorded {}", path.display());
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "{} is missing; record it with BLESS=1 if {name} is new",
+            path.display()
+        )
+    });
+    assert_eq!(
+        expected.trim(),
+        hex,
+        "{name} doesn't match {}; re-record it with BLESS=1 if the change is intended",
+        path.display()
+    );
+}
+
+#[test]
+fn peg_in_handover_peg_out() {
+    let secp = Secp256k1::new();
+    let old_committee = committee(0..5, &secp);
+    let new_committee = committee(5..10, &secp);
+    check_golden(
+        "old_committee_script",
+        &old_committee.script.to_hex_string(),
+    );
+    check_golden(
+        "new_committee_script",
+        &new_committee.script.to_hex_string(),
+    );
+
+    let mut prover = MultisigProver {
+        available_utxos: peg_in_utxos(4, &old_committee.script_pubkey),
+    };
+    let handovers = prover
+        .create_handover_tx(
+            HandoverParams {
+                max_output_no: 2,
+                max_tx_size: 100_000,
+                miner_fee: Amount::from_sat(1000),
+                dust_limit: Amount::from_sat(330),
+            },
+            &old_committee.script,
+            &new_committee.script_pubkey,
+            TapSighashType::Default,
+        )
+        .unwrap();
+
+    let mut new_utxos = vec![];
+    for (i, (mut tx, sighashes)) in handovers.into_iter().enumerate() {
+        sign(&mut tx, &sighashes, &old_committee, &secp);
+        verify_transaction(&tx, &prevouts(&tx, &prover.available_utxos), &secp)
+            .unwrap_or_else(|error| panic!("Handover {i} doesn't verify: {error:?}"));
+        check_golden(&format!("handover_{i}"), &serialize_hex(&tx));
+        new_utxos.extend(outputs(&tx));
+    }
+
+    let receiver = get_private_key(100, Network::Regtest).unwrap();
+    let receiver = Address::p2wpkh(
+        &CompressedPublicKey::from_private_key(&secp, &receiver.to_priv()).unwrap(),
+        Network::Regtest,
+    );
+    prover.available_utxos = new_utxos.clone();
+    let (mut peg_out, sighashes) = prover
+        .create_peg_out_tx(
+            Amount::from_sat(5),
+            vec![(Amount::from_sat(150_000), receiver)],
+            &new_committee.script,
+            &new_committee.script_pubkey,
+            TapSighashType::Default,
+        )
+        .unwrap();
+    sign(&mut peg_out, &sighashes, &new_committee, &secp);
+    verify_transaction(&peg_out, &prevouts(&peg_out, &new_utxos), &secp)
+        .unwrap_or_else(|error| panic!("Peg-out doesn't verify: {error:?}"));
+    check_golden("peg_out", &serialize_hex(&peg_out));
+}
+
+#[test]
+fn signatures_below_threshold_dont_verify() {
+    let secp = Secp256k1::new();
+    let committee = committee(0..5, &secp);
+    let utxos = peg_in_utxos(2, &committee.script_pubkey);
+    let prover = MultisigProver {
+        available_utxos: utxos.clone(),
+    };
+    let (mut tx, sighashes) = prover
+        .create_handover_tx(
+            HandoverParams {
+                max_output_no: 1,
+                max_tx_size: 100_000,
+                miner_fee: Amount::from_sat(1000),
+                dust_limit: Amount::from_sat(330),
+            },
+            &committee.script,
+            &committee.script_pubkey,
+            TapSighashType::Default,
+        )
+        .unwrap()
+        .remove(0);
+
+    // Without the validators of weight 1 and 5, 2 + 3 + 4 < 10
+    let signatures = collect_signatures(
+        &sighashes,
+        TapSighashType::Default,
+        &committee.validators,
+        &secp,
+    )
+    .into_iter()
+    .map(|mut x| {
+        x[0] = None;
+        x[4] = None;
+        x
+    })
+    .collect();
+    tx.finalize_witness(
+        &signatures,
+        &committee.script,
+        &XOnlyPublicKey::create_unspendable_key(),
+        &secp,
+    );
+
+    let error = verify_transaction(&tx, &prevouts(&tx, &utxos), &secp).unwrap_err();
+    assert_eq!(error.reason, ScriptError::EvalFalse);
+}
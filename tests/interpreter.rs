@@ -0,0 +1,292 @@
+mod common;
+
+use axelar_btc::{
+    collect_signatures, get_private_key,
+    interpreter::{verify_input, verify_transaction, ScriptError, VerificationError},
+    multisig_prover::{HandoverParams, MultisigProver},
+    state::Committee,
+    Utxo,
+};
+use bitcoin::{
+    absolute::LockTime,
+    hashes::Hash,
+    key::{Keypair, Secp256k1, TapTweak},
+    opcodes::all::*,
+    script::Builder,
+    secp256k1::{All, Message},
+    sighash::{Prevouts, SighashCache},
+    taproot::{LeafVersion, Signature, TaprootBuilder},
+    transaction::Version,
+    Amount, Network, OutPoint, ScriptBuf, Sequence, TapSighashType, Transaction, TxIn, TxOut,
+    Witness, XOnlyPublicKey,
+};
+use bitcoin_rs::transaction::WitnessControl;
+use common::{txid, utxo, validators};
+
+fn keypair(secp: &Secp256k1<All>) -> Keypair {
+    get_private_key(100, Network::Regtest)
+        .unwrap()
+        .to_keypair(secp)
+}
+
+fn tx(sequence: Sequence, lock_time: LockTime) -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time,
+        input: vec![TxIn {
+            previous_output: OutPoint {
+                txid: txid(0),
+                vout: 0,
+            },
+            sequence,
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(9000),
+            script_pubkey: ScriptBuf::new_op_return([1]),
+        }],
+    }
+}
+
+// Spends the single leaf `script` of a taproot output without arguments, and returns the
+// prevouts
+fn spend_leaf(tx: &mut Transaction, script: &ScriptBuf) -> Vec<TxOut> {
+    let secp = Secp256k1::new();
+    let internal_key = keypair(&secp).x_only_public_key().0;
+    let spend_info = TaprootBuilder::new()
+        .add_leaf(0, script.clone())
+        .unwrap()
+        .finalize(&secp, internal_key)
+        .unwrap();
+    let control_block = spend_info
+        .control_block(&(script.clone(), LeafVersion::TapScript))
+        .unwrap();
+
+    tx.input[0].witness = Witness::from_slice(&[script.to_bytes(), control_block.serialize()]);
+    vec![TxOut {
+        value: Amount::from_sat(10_000),
+        script_pubkey: ScriptBuf::new_p2tr_tweaked(spend_info.output_key()),
+    }]
+}
+
+fn verify_leaf(
+    sequence: Sequence,
+    lock_time: LockTime,
+    script: &ScriptBuf,
+) -> Result<(), VerificationError> {
+    let mut tx = tx(sequence, lock_time);
+    let prevouts = spend_leaf(&mut tx, script);
+    verify_transaction(&tx, &prevouts, &Secp256k1::new())
+}
+
+fn reason<T>(result: Result<T, VerificationError>) -> ScriptError {
+    match result {
+        Ok(_) => panic!("The spend verified"),
+        Err(error) => error.reason,
+    }
+}
+
+#[test]
+fn relative_timelocks() {
+    let script = Builder::new()
+        .push_int(144)
+        .push_opcode(OP_CSV)
+        .push_opcode(OP_DROP)
+        .push_opcode(OP_PUSHNUM_1)
+        .into_script();
+    let verify = |sequence| verify_leaf(sequence, LockTime::ZERO, &script);
+
+    verify(Sequence::from_height(144)).unwrap();
+    verify(Sequence::from_height(1000)).unwrap();
+    let error = verify(Sequence::from_height(143)).unwrap_err();
+    assert_eq!(error.reason, ScriptError::UnsatisfiedLocktime);
+    assert_eq!(error.opcode, Some((3, OP_CSV)));
+    // Disabled relative lock-times and time-based ones don't satisfy a height
+    assert_eq!(
+        reason(verify(Sequence::MAX)),
+        ScriptError::UnsatisfiedLocktime
+    );
+    assert_eq!(
+        reason(verify(Sequence::from_512_second_intervals(144))),
+        ScriptError::UnsatisfiedLocktime
+    );
+
+    // BIP68 only applies from version 2 on
+    let mut tx = tx(Sequence::from_height(144), LockTime::ZERO);
+    tx.version = Version::ONE;
+    let prevouts = spend_leaf(&mut tx, &script);
+    assert_eq!(
+        reason(verify_transaction(&tx, &prevouts, &Secp256k1::new())),
+        ScriptError::UnsatisfiedLocktime
+    );
+}
+
+#[test]
+fn absolute_timelocks() {
+    let script = Builder::new()
+        .push_int(800_000)
+        .push_opcode(OP_CLTV)
+        .push_opcode(OP_DROP)
+        .push_opcode(OP_PUSHNUM_1)
+        .into_script();
+    let verify = |sequence, height| {
+        let lock_time = LockTime::from_consensus(height);
+        verify_leaf(sequence, lock_time, &script)
+    };
+
+    verify(Sequence::ENABLE_LOCKTIME_NO_RBF, 800_000).unwrap();
+    verify(Sequence::ENABLE_RBF_NO_LOCKTIME, 800_001).unwrap();
+    let error = verify(Sequence::ENABLE_LOCKTIME_NO_RBF, 799_999).unwrap_err();
+    assert_eq!(error.reason, ScriptError::UnsatisfiedLocktime);
+    assert_eq!(error.opcode, Some((4, OP_CLTV)));
+    // The lock-time is disabled by a final sequence
+    assert_eq!(
+        reason(verify(Sequence::MAX, 800_000)),
+        ScriptError::UnsatisfiedLocktime
+    );
+    // Timestamps don't satisfy a height
+    assert_eq!(
+        reason(verify(Sequence::ENABLE_LOCKTIME_NO_RBF, 1_700_000_000)),
+        ScriptError::UnsatisfiedLocktime
+    );
+}
+
+#[test]
+fn unsupported_opcodes() {
+    let verify = |script: ScriptBuf| verify_leaf(Sequence::MAX, LockTime::ZERO, &script);
+
+    let error = verify(
+        Builder::new()
+            .push_opcode(OP_PUSHNUM_1)
+            .push_opcode(OP_CODESEPARATOR)
+            .into_script(),
+    )
+    .unwrap_err();
+    assert_eq!(error.reason, ScriptError::UnsupportedOpcode);
+    assert_eq!(error.opcode, Some((1, OP_CODESEPARATOR)));
+    assert_eq!(error.public_key, None);
+
+    assert_eq!(
+        reason(verify(Builder::new().push_opcode(OP_RETURN).into_script())),
+        ScriptError::OpReturn
+    );
+    // OP_CAT is an OP_SUCCESSx in tapscript, so anything goes
+    verify(
+        Builder::new()
+            .push_opcode(OP_RETURN)
+            .push_opcode(OP_CAT)
+            .into_script(),
+    )
+    .unwrap();
+
+    // Annexes aren't interpreted
+    let script = Builder::new().push_opcode(OP_PUSHNUM_1).into_script();
+    let mut tx = tx(Sequence::MAX, LockTime::ZERO);
+    let prevouts = spend_leaf(&mut tx, &script);
+    tx.input[0].witness.push([0x50, 0]);
+    assert_eq!(
+        reason(verify_transaction(&tx, &prevouts, &Secp256k1::new())),
+        ScriptError::UnsupportedAnnex
+    );
+}
+
+#[test]
+fn key_path_spends() {
+    let secp = Secp256k1::new();
+    let keypair = keypair(&secp);
+    let (internal_key, _) = keypair.x_only_public_key();
+    let script_pubkey = ScriptBuf::new_p2tr(&secp, internal_key, None);
+    let prevouts = vec![TxOut {
+        value: Amount::from_sat(10_000),
+        script_pubkey,
+    }];
+    let mut tx = tx(Sequence::MAX, LockTime::ZERO);
+
+    let sign = |tx: &Transaction, sighash_type| {
+        let sighash = SighashCache::new(tx)
+            .taproot_key_spend_signature_hash(0, &Prevouts::All(&prevouts), sighash_type)
+            .unwrap();
+        Signature {
+            signature: secp.sign_schnorr(
+                &Message::from_digest(sighash.to_byte_array()),
+                &keypair.tap_tweak(&secp, None).to_keypair(),
+            ),
+            sighash_type,
+        }
+    };
+
+    for sighash_type in [TapSighashType::Default, TapSighashType::AllPlusAnyoneCanPay] {
+        tx.input[0].witness = Witness::p2tr_key_spend(&sign(&tx, sighash_type));
+        verify_input(&tx, 0, &prevouts, &secp).unwrap();
+    }
+
+    // A signature of another transaction is attributed to the output key
+    let mut other = tx.clone();
+    other.output[0].value = Amount::from_sat(8000);
+    tx.input[0].witness = Witness::p2tr_key_spend(&sign(&other, TapSighashType::Default));
+    let error = verify_input(&tx, 0, &prevouts, &secp).unwrap_err();
+    assert_eq!(error.reason, ScriptError::InvalidSignature);
+    let output_key = XOnlyPublicKey::from_slice(&prevouts[0].script_pubkey.as_bytes()[2..]);
+    assert_eq!(error.public_key, Some(output_key.unwrap()));
+
+    tx.input[0].witness = Witness::new();
+    assert_eq!(
+        reason(verify_input(&tx, 0, &prevouts, &secp)),
+        ScriptError::EmptyWitness
+    );
+}
+
+#[test]
+fn invalid_signatures_are_attributed_to_their_validator() {
+    let secp = Secp256k1::new();
+    let validators = validators();
+    let committee = Committee::from_validators(&validators, 2, &secp);
+    let (script, script_pubkey) = committee.scripts(&secp);
+    let utxos: Vec<Utxo> = (0..2).map(|i| utxo(i, 100_000, &script_pubkey)).collect();
+    let prevouts: Vec<TxOut> = utxos.iter().map(|x| x.txout.clone()).collect();
+    let prover = MultisigProver {
+        available_utxos: utxos,
+    };
+    let (unsigned_tx, sighashes) = prover
+        .create_handover_tx(
+            HandoverParams {
+                max_output_no: 1,
+                max_tx_size: 100_000,
+                miner_fee: Amount::from_sat(1000),
+                dust_limit: Amount::from_sat(330),
+            },
+            &script,
+            &script_pubkey,
+            TapSighashType::Default,
+        )
+        .unwrap()
+        .remove(0);
+
+    // Validator 1 signs the second input with the first one's sighash
+    let mut signatures =
+        collect_signatures(&sighashes, TapSighashType::Default, &validators, &secp);
+    signatures[1][1] = signatures[0][1];
+    let mut tx = unsigned_tx.clone();
+    tx.finalize_witness(&signatures, &script, &committee.internal_key, &secp);
+
+    verify_input(&tx, 0, &prevouts, &secp).unwrap();
+    let error = verify_transaction(&tx, &prevouts, &secp).unwrap_err();
+    assert_eq!(error.input, 1);
+    assert_eq!(error.reason, ScriptError::InvalidSignature);
+    assert!(matches!(
+        error.opcode,
+        Some((_, OP_CHECKSIG | OP_CHECKSIGADD | OP_CHECKSIGVERIFY))
+    ));
+    assert_eq!(error.validator(&committee.pks_weights()), Some(1));
+
+    // Missing signatures are "no" votes, not failures of their validator
+    let mut signatures =
+        collect_signatures(&sighashes, TapSighashType::Default, &validators, &secp);
+    signatures[1][0] = None;
+    signatures[1][1] = None;
+    let mut tx = unsigned_tx;
+    tx.finalize_witness(&signatures, &script, &committee.internal_key, &secp);
+    let error = verify_transaction(&tx, &prevouts, &secp).unwrap_err();
+    assert_eq!(error.validator(&committee.pks_weights()), None);
+    assert_eq!((error.input, error.reason), (1, ScriptError::EvalFalse));
+}
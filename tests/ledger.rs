@@ -0,0 +1,198 @@
+mod common;
+
+use axelar_btc::{
+    get_private_key,
+    ledger::{Account, Entry, EntryKind, Ledger, LedgerError},
+    multisig_prover::{HandoverParams, MultisigProver},
+    protocol_fee::FeeSchedule,
+    state::Committee,
+    validator::Validator,
+    Utxo,
+};
+use bitcoin::{
+    key::Secp256k1, Address, Amount, CompressedPublicKey, Network, OutPoint, ScriptBuf,
+    SignedAmount, TapSighashType, Transaction, TxOut,
+};
+use common::{utxo, validator};
+
+fn committee(first: usize) -> Committee {
+    let validators: Vec<Validator> = (first..first + 3).map(validator).collect();
+    Committee::from_validators(&validators, 2, &Secp256k1::new())
+}
+
+fn receiver() -> Address {
+    let secp = Secp256k1::new();
+    let key = get_private_key(100, Network::Regtest).unwrap().to_priv();
+    Address::p2wpkh(
+        &CompressedPublicKey::from_private_key(&secp, &key).unwrap(),
+        Network::Regtest,
+    )
+}
+
+// Spends the inputs of `tx` from `utxos` and adds its outputs paying to `script_pubkey`, like
+// the commands do with the state
+fn apply(utxos: &mut Vec<Utxo>, tx: &Transaction, script_pubkey: &ScriptBuf) -> Vec<TxOut> {
+    let prevouts = tx
+        .input
+        .iter()
+        .map(|txin| {
+            let i = utxos
+                .iter()
+                .position(|x| x.outpoint == txin.previous_output)
+                .unwrap();
+            utxos.remove(i).txout
+        })
+        .collect();
+    let txid = tx.compute_txid();
+    for (vout, txout) in tx.output.iter().enumerate() {
+        if txout.script_pubkey == *script_pubkey {
+            utxos.push(Utxo {
+                outpoint: OutPoint {
+                    txid,
+                    vout: vout as u32,
+                },
+                txout: txout.clone(),
+            });
+        }
+    }
+    prevouts
+}
+
+fn balance(ledger: &Ledger, account: &Account) -> SignedAmount {
+    ledger
+        .balances()
+        .into_iter()
+        .find(|(x, _)| x == account)
+        .map_or(SignedAmount::ZERO, |(_, balance)| balance)
+}
+
+#[test]
+fn ledger_follows_the_committee_utxos() {
+    let secp = Secp256k1::new();
+    let old_committee = committee(0);
+    let (script, script_pubkey) = old_committee.scripts(&secp);
+    // Stands in for a deposit address
+    let (_, deposit_script_pubkey) = committee(3).scripts(&secp);
+
+    let mut ledger = Ledger::default();
+    let mut utxos = vec![
+        utxo(0, 100_000, &deposit_script_pubkey),
+        utxo(1, 100_000, &script_pubkey),
+    ];
+    for utxo in &utxos {
+        ledger.record_deposit(utxo, 1);
+    }
+    assert_eq!(ledger.check(&utxos), Ok(()));
+
+    // A peg-out spending both, so the deposit is swept into the committee's script
+    let mut prover = MultisigProver {
+        available_utxos: utxos.clone(),
+    };
+    let schedule = FeeSchedule {
+        flat_fee: 1000,
+        ..FeeSchedule::default()
+    };
+    let (tx, _, accounting) = prover
+        .create_fee_deducted_peg_out_tx(
+            Amount::from_sat(5),
+            vec![(Amount::from_sat(150_000), receiver())],
+            &schedule,
+            &script,
+            &script_pubkey,
+            TapSighashType::Default,
+        )
+        .unwrap();
+    let prevouts = apply(&mut utxos, &tx, &script_pubkey);
+    ledger.record_peg_out(&tx, &prevouts, &accounting, 2);
+    assert_eq!(ledger.check(&utxos), Ok(()));
+
+    let entries = ledger.entries_of(&tx.compute_txid());
+    let kinds: Vec<_> = entries.iter().map(|x| x.kind).collect();
+    assert_eq!(kinds.iter().filter(|x| **x == EntryKind::Sweep).count(), 1);
+    assert!(kinds.contains(&EntryKind::ProtocolFee));
+    assert!(kinds.contains(&EntryKind::Change));
+    assert_eq!(
+        balance(&ledger, &Account::Recipients),
+        accounting[0].net.to_signed().unwrap()
+    );
+    assert_eq!(
+        balance(&ledger, &Account::Miners),
+        accounting[0].miner_fee.to_signed().unwrap()
+    );
+
+    // A handover to the next committee
+    let new_committee = committee(6);
+    let (_, new_script_pubkey) = new_committee.scripts(&secp);
+    let prover = MultisigProver {
+        available_utxos: utxos.clone(),
+    };
+    for (tx, _) in prover
+        .create_handover_tx(
+            HandoverParams {
+                max_output_no: 2,
+                max_tx_size: 100_000,
+                miner_fee: Amount::from_sat(1000),
+                dust_limit: Amount::from_sat(330),
+            },
+            &script,
+            &new_script_pubkey,
+            TapSighashType::Default,
+        )
+        .unwrap()
+    {
+        let prevouts = apply(&mut utxos, &tx, &new_script_pubkey);
+        ledger.record_handover(&tx, &prevouts, 3);
+    }
+    assert_eq!(ledger.check(&utxos), Ok(()));
+    assert_eq!(
+        balance(&ledger, &Account::Committee(script_pubkey)),
+        SignedAmount::ZERO
+    );
+
+    // Double entry: the balances add up to zero
+    let total = ledger.balances().into_iter().map(|(_, x)| x).sum();
+    assert_eq!(SignedAmount::ZERO, total);
+    assert_eq!(
+        balance(&ledger, &Account::Depositors),
+        SignedAmount::from_sat(-200_000)
+    );
+
+    // A UTXO that disappears without a transaction is caught
+    let held = utxos.iter().map(|x| x.txout.value).sum::<Amount>();
+    let lost = utxos.pop().unwrap();
+    assert_eq!(
+        ledger.check(&utxos),
+        Err(LedgerError::Unbalanced {
+            script_pubkey: new_script_pubkey,
+            balance: held.to_signed().unwrap(),
+            utxos: held - lost.txout.value,
+        })
+    );
+    // Unless it's recorded as spent outside of the CLI
+    ledger.record_external_spend(&lost, 4);
+    assert_eq!(ledger.check(&utxos), Ok(()));
+    assert_eq!(
+        balance(&ledger, &Account::Unaccounted),
+        lost.txout.value.to_signed().unwrap()
+    );
+}
+
+#[test]
+fn ledger_exports() {
+    let secp = Secp256k1::new();
+    let (_, script_pubkey) = committee(0).scripts(&secp);
+    let mut ledger = Ledger::default();
+    let deposit = utxo(0, 100_000, &script_pubkey);
+    ledger.record_deposit(&deposit, 1_700_000_000);
+
+    let address = Address::from_script(&script_pubkey, Network::Regtest).unwrap();
+    assert_eq!(
+        ledger.to_csv(Network::Regtest),
+        format!(
+            "txid,vout,kind,from,to,amount,time\n{},0,Deposit,depositors,committee:{address},100000,1700000000\n",
+            deposit.outpoint.txid
+        )
+    );
+    let entries: Vec<Entry> = serde_json::from_str(&ledger.to_json()).unwrap();
+    assert_eq!(entries, ledger.entries);
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use axelar_btc::{
+    collect_signatures, get_private_key,
+    multisig_prover::{MultisigProver, PayoutError},
+    state::Committee,
+};
+use bitcoin::{
+    key::Secp256k1, script::Builder, Address, Amount, CompressedPublicKey, Network, PublicKey,
+    ScriptBuf, TapSighashType, WitnessProgram, WitnessVersion,
+};
+use bitcoin_rs::transaction::WitnessControl;
+use common::{utxo, validators};
+
+const FEE_RATE: u64 = 7;
+
+fn committee() -> Committee {
+    Committee::from_validators(&validators(), 2, &Secp256k1::new())
+}
+
+// One address of every type
+fn addresses() -> Vec<(&'static str, Address)> {
+    let secp = Secp256k1::new();
+    let key = get_private_key(100, Network::Regtest).unwrap().to_priv();
+    let public_key = CompressedPublicKey::from_private_key(&secp, &key).unwrap();
+    let redeem_script = Builder::new()
+        .push_key(&PublicKey::from(public_key.0))
+        .push_opcode(bitcoin::opcodes::all::OP_CHECKSIG)
+        .into_script();
+    let future = WitnessProgram::new(WitnessVersion::V2, &[1; 32]).unwrap();
+    vec![
+        ("p2pkh", Address::p2pkh(public_key, Network::Regtest)),
+        (
+            "p2sh",
+            Address::p2sh(&redeem_script, Network::Regtest).unwrap(),
+        ),
+        ("p2wpkh", Address::p2wpkh(&public_key, Network::Regtest)),
+        ("p2wsh", Address::p2wsh(&redeem_script, Network::Regtest)),
+        (
+            "p2tr",
+            Address::p2tr(&secp, public_key.0.into(), None, Network::Regtest),
+        ),
+        (
+            "witness v2",
+            Address::from_witness_program(future, Network::Regtest),
+        ),
+    ]
+}
+
+fn prover(script_pubkey: &ScriptBuf) -> MultisigProver {
+    prover_with(1_000_000, script_pubkey)
+}
+
+fn prover_with(sats: u64, script_pubkey: &ScriptBuf) -> MultisigProver {
+    MultisigProver {
+        available_utxos: vec![utxo(0, sats, script_pubkey)],
+    }
+}
+
+#[test]
+fn fees_include_the_payout_sizes() {
+    let secp = Secp256k1::new();
+    let committee = committee();
+    let (script, script_pubkey) = committee.scripts(&secp);
+
+    for (name, address) in addresses() {
+        let (mut tx, sighashes) = prover(&script_pubkey)
+            .create_peg_out_tx(
+                Amount::from_sat(FEE_RATE),
+                vec![(Amount::from_sat(100_000), address.clone())],
+                &script,
+                &script_pubkey,
+                TapSighashType::Default,
+            )
+            .unwrap_or_else(|error| panic!("{name}: {error}"));
+
+        assert_eq!(
+            tx.output[0].script_pubkey,
+            address.script_pubkey(),
+            "{name}"
+        );
+        // The fee pays for the transaction signed by every member, the most they can sign
+        let signatures =
+            collect_signatures(&sighashes, TapSighashType::Default, &validators(), &secp);
+        tx.finalize_witness(&signatures, &script, &committee.internal_key, &secp);
+        let paid = tx.output.iter().map(|x| x.value).sum::<Amount>();
+        assert_eq!(
+            Amount::from_sat(1_000_000) - paid,
+            Amount::from_sat(FEE_RATE * tx.vsize() as u64),
+            "{name}"
+        );
+    }
+}
+
+#[test]
+fn dust_limits_depend_on_the_address_type() {
+    let secp = Secp256k1::new();
+    let (script, script_pubkey) = committee().scripts(&secp);
+    let peg_out = |amount: u64, address: &Address| {
+        prover(&script_pubkey).create_peg_out_tx(
+            Amount::from_sat(FEE_RATE),
+            vec![(Amount::from_sat(amount), address.clone())],
+            &script,
+            &script_pubkey,
+            TapSighashType::Default,
+        )
+    };
+
+    let mut minimums = vec![];
+    for (name, address) in addresses() {
+        let minimum = address.script_pubkey().minimal_non_dust();
+        assert!(peg_out(minimum.to_sat(), &address).is_ok(), "{name}");
+        assert_eq!(
+            peg_out(minimum.to_sat() - 1, &address).unwrap_err(),
+            PayoutError::Dust {
+                address: address.clone(),
+                amount: minimum - Amount::from_sat(1),
+                minimum,
+            },
+            "{name}"
+        );
+        minimums.push(minimum.to_sat());
+    }
+    assert_eq!(minimums, [546, 540, 294, 330, 330, 330]);
+}
+
+#[test]
+fn dust_change_goes_to_the_miners() {
+    let secp = Secp256k1::new();
+    let (script, script_pubkey) = committee().scripts(&secp);
+    let (_, receiver) = addresses().remove(4);
+    let peg_out = |sats: u64| {
+        prover_with(sats, &script_pubkey)
+            .create_peg_out_tx(
+                Amount::from_sat(FEE_RATE),
+                vec![(Amount::from_sat(100_000), receiver.clone())],
+                &script,
+                &script_pubkey,
+                TapSighashType::Default,
+            )
+            .unwrap()
+            .0
+    };
+
+    // The fee of the peg-out with change, whose output is worth exactly the P2TR dust limit
+    let with_change = peg_out(1_000_000);
+    let change = &with_change.output[1];
+    assert_eq!(change.script_pubkey, script_pubkey);
+    let fee = Amount::from_sat(1_000_000 - 100_000) - change.value;
+    let minimum = script_pubkey.minimal_non_dust();
+    assert_eq!(minimum, Amount::from_sat(330));
+
+    let exact = peg_out(100_000 + fee.to_sat() + minimum.to_sat());
+    assert_eq!(exact.output.len(), 2);
+    assert_eq!(exact.output[1].value, minimum);
+
+    let dust = peg_out(100_000 + fee.to_sat() + minimum.to_sat() - 1);
+    assert_eq!(dust.output.len(), 1);
+    assert_eq!(dust.output[0].value, Amount::from_sat(100_000));
+}
@@ -0,0 +1,177 @@
+use axelar_btc::{
+    config::BatchPolicy,
+    get_private_key,
+    peg_out_queue::{payouts, PegOutQueue, PegOutRequest, QueueError},
+    protocol_fee::FeeSchedule,
+};
+use bitcoin::{
+    address::NetworkUnchecked, hashes::Hash, key::Secp256k1, Address, Amount, Network, Txid,
+};
+
+const POLICY: BatchPolicy = BatchPolicy {
+    max_batch_size: 3,
+    batch_window: 600,
+};
+
+const NO_FEES: FeeSchedule = FeeSchedule {
+    flat_fee: 0,
+    percentage_bps: 0,
+    minimum_fee: 0,
+};
+
+fn address(network: Network) -> Address<NetworkUnchecked> {
+    let secp = Secp256k1::new();
+    let key = get_private_key(100, network).unwrap().to_keypair(&secp);
+    Address::p2tr(&secp, key.x_only_public_key().0, None, network).into_unchecked()
+}
+
+fn request(id: &str, received_at: u64, deadline: Option<u64>) -> PegOutRequest {
+    PegOutRequest {
+        id: id.to_string(),
+        amount: Amount::from_sat(10_000),
+        address: address(Network::Regtest),
+        deadline,
+        received_at,
+    }
+}
+
+fn ids(requests: &[PegOutRequest]) -> Vec<&str> {
+    requests.iter().map(|x| x.id.as_str()).collect()
+}
+
+#[test]
+fn requests_are_validated() {
+    let mut queue = PegOutQueue::default();
+    queue
+        .push(request("a", 0, None), Network::Regtest, &NO_FEES)
+        .unwrap();
+
+    assert_eq!(
+        queue.push(request("a", 1, None), Network::Regtest, &NO_FEES),
+        Err(QueueError::DuplicateRequest("a".to_string()))
+    );
+    let mainnet = PegOutRequest {
+        address: address(Network::Bitcoin),
+        ..request("b", 0, None)
+    };
+    assert!(matches!(
+        queue.push(mainnet, Network::Regtest, &NO_FEES),
+        Err(QueueError::WrongNetwork { .. })
+    ));
+    let dust = PegOutRequest {
+        amount: Amount::from_sat(100),
+        ..request("c", 0, None)
+    };
+    assert!(matches!(
+        queue.push(dust, Network::Regtest, &NO_FEES),
+        Err(QueueError::DustAmount { .. })
+    ));
+    // Nor once the protocol fee is taken
+    let fees = FeeSchedule {
+        flat_fee: 9_800,
+        ..NO_FEES
+    };
+    assert_eq!(
+        queue.push(request("d", 0, None), Network::Regtest, &fees),
+        Err(QueueError::DustAmount {
+            minimum: Amount::from_sat(330),
+            protocol_fee: Amount::from_sat(9_800),
+        })
+    );
+    assert_eq!(ids(&queue.requests), ["a"]);
+
+    // Batched requests are still known
+    let batch = queue.next_batch(&POLICY, 0, true).unwrap();
+    queue.record_batch(Txid::all_zeros(), batch);
+    assert_eq!(
+        queue.push(request("a", 2, None), Network::Regtest, &NO_FEES),
+        Err(QueueError::DuplicateRequest("a".to_string()))
+    );
+}
+
+#[test]
+fn batches_are_cut_when_due() {
+    let mut queue = PegOutQueue::default();
+    queue
+        .push(request("a", 100, None), Network::Regtest, &NO_FEES)
+        .unwrap();
+    queue
+        .push(request("b", 200, None), Network::Regtest, &NO_FEES)
+        .unwrap();
+    assert!(queue.next_batch(&POLICY, 600, false).is_none());
+
+    // The oldest request waited for the whole window
+    let batch = queue.next_batch(&POLICY, 700, false).unwrap();
+    assert_eq!(ids(&batch), ["a", "b"]);
+    assert!(queue.requests.is_empty());
+
+    // A deadline within the window
+    queue
+        .push(request("c", 700, Some(1500)), Network::Regtest, &NO_FEES)
+        .unwrap();
+    assert!(queue.next_batch(&POLICY, 800, false).is_none());
+    assert_eq!(ids(&queue.next_batch(&POLICY, 900, false).unwrap()), ["c"]);
+
+    // A full batch
+    for id in ["d", "e", "f"] {
+        queue
+            .push(request(id, 2000, None), Network::Regtest, &NO_FEES)
+            .unwrap();
+    }
+    assert_eq!(queue.next_batch(&POLICY, 2000, false).unwrap().len(), 3);
+}
+
+#[test]
+fn urgent_requests_go_first() {
+    let mut queue = PegOutQueue::default();
+    queue
+        .push(request("a", 100, None), Network::Regtest, &NO_FEES)
+        .unwrap();
+    queue
+        .push(request("b", 200, Some(5000)), Network::Regtest, &NO_FEES)
+        .unwrap();
+    queue
+        .push(request("c", 50, None), Network::Regtest, &NO_FEES)
+        .unwrap();
+    queue
+        .push(request("d", 300, Some(4000)), Network::Regtest, &NO_FEES)
+        .unwrap();
+
+    let batch = queue.next_batch(&POLICY, 300, true).unwrap();
+    assert_eq!(ids(&batch), ["d", "b", "c"]);
+    assert_eq!(ids(&queue.requests), ["a"]);
+    assert_eq!(payouts(&batch).len(), 3);
+
+    let txid = Txid::all_zeros();
+    queue.record_batch(txid, batch);
+    assert_eq!(queue.batch_of("b").unwrap().txid, txid);
+    assert!(queue.batch_of("a").is_none());
+    assert!(queue.contains("a"));
+}
+
+#[test]
+fn unpayable_requests_are_parked() {
+    let mut queue = PegOutQueue::default();
+    for id in ["a", "b", "c"] {
+        queue
+            .push(request(id, 100, None), Network::Regtest, &NO_FEES)
+            .unwrap();
+    }
+    let mut batch = queue.next_batch(&POLICY, 100, true).unwrap();
+    let dust = batch.remove(1);
+    queue.park(dust, "dust".to_string());
+    queue.requeue(batch);
+
+    // The rest is batched again, the parked request never is
+    assert_eq!(
+        ids(&queue.next_batch(&POLICY, 100, true).unwrap()),
+        ["a", "c"]
+    );
+    assert!(queue.next_batch(&POLICY, 100, true).is_none());
+    assert_eq!(queue.parked_request("b").unwrap().reason, "dust");
+    assert!(queue.batch_of("b").is_none());
+    assert_eq!(
+        queue.push(request("b", 200, None), Network::Regtest, &NO_FEES),
+        Err(QueueError::DuplicateRequest("b".to_string()))
+    );
+}
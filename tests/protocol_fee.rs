@@ -0,0 +1,157 @@
+mod common;
+
+use axelar_btc::{
+    collect_signatures, get_private_key,
+    multisig_prover::{MultisigProver, PayoutError},
+    protocol_fee::{split_miner_fee, FeeSchedule},
+    state::Committee,
+    Utxo,
+};
+use bitcoin::{key::Secp256k1, Address, Amount, CompressedPublicKey, Network, TapSighashType};
+use bitcoin_rs::transaction::WitnessControl;
+use common::{utxo, validators};
+
+fn sats(amounts: &[u64]) -> Vec<Amount> {
+    amounts.iter().map(|x| Amount::from_sat(*x)).collect()
+}
+
+fn receiver(i: usize) -> Address {
+    let secp = Secp256k1::new();
+    let key = get_private_key(100 + i, Network::Regtest)
+        .unwrap()
+        .to_priv();
+    Address::p2wpkh(
+        &CompressedPublicKey::from_private_key(&secp, &key).unwrap(),
+        Network::Regtest,
+    )
+}
+
+#[test]
+fn protocol_fees_follow_the_schedule() {
+    let schedule = FeeSchedule {
+        flat_fee: 100,
+        percentage_bps: 25,
+        minimum_fee: 500,
+    };
+    assert_eq!(
+        schedule.protocol_fee(Amount::from_sat(1_000_000)),
+        Amount::from_sat(2600)
+    );
+    assert_eq!(
+        schedule.protocol_fee(Amount::from_sat(10_000)),
+        Amount::from_sat(500)
+    );
+    assert_eq!(
+        FeeSchedule::default().protocol_fee(Amount::from_sat(10_000)),
+        Amount::ZERO
+    );
+}
+
+#[test]
+fn miner_fee_is_split_in_proportion() {
+    assert_eq!(
+        split_miner_fee(Amount::from_sat(1000), &sats(&[100, 300])),
+        sats(&[250, 750])
+    );
+    // The rounding is paid by the first payouts
+    assert_eq!(
+        split_miner_fee(Amount::from_sat(1001), &sats(&[1, 1, 1])),
+        sats(&[334, 334, 333])
+    );
+}
+
+#[test]
+fn recipients_pay_the_fees() {
+    let secp = Secp256k1::new();
+    let validators = validators();
+    let committee = Committee::from_validators(&validators, 2, &secp);
+    let (script, script_pubkey) = committee.scripts(&secp);
+    let utxos: Vec<Utxo> = (0..3).map(|i| utxo(i, 100_000, &script_pubkey)).collect();
+    let schedule = FeeSchedule {
+        flat_fee: 0,
+        percentage_bps: 100,
+        minimum_fee: 1000,
+    };
+    let payouts = vec![
+        (Amount::from_sat(150_000), receiver(0)),
+        (Amount::from_sat(50_000), receiver(1)),
+    ];
+
+    let mut prover = MultisigProver {
+        available_utxos: utxos.clone(),
+    };
+    let (mut tx, sighashes, accounting) = prover
+        .create_fee_deducted_peg_out_tx(
+            Amount::from_sat(5),
+            payouts.clone(),
+            &schedule,
+            &script,
+            &script_pubkey,
+            TapSighashType::Default,
+        )
+        .unwrap();
+    assert_eq!(sighashes.len(), tx.input.len());
+
+    let protocol_fees = accounting
+        .iter()
+        .map(|x| x.protocol_fee)
+        .collect::<Vec<_>>();
+    assert_eq!(protocol_fees, sats(&[1500, 1000]));
+    let miner_fee = accounting.iter().map(|x| x.miner_fee).sum::<Amount>();
+    assert_eq!(
+        accounting[0].miner_fee,
+        Amount::from_sat((miner_fee.to_sat() * 3).div_ceil(4))
+    );
+    for (i, payout) in accounting.iter().enumerate() {
+        assert_eq!(payout.gross, payouts[i].0);
+        assert_eq!(
+            payout.net,
+            payout.gross - payout.miner_fee - payout.protocol_fee
+        );
+        assert_eq!(tx.output[i].value, payout.net);
+    }
+
+    // The committee keeps the protocol fees and doesn't pay for the miners
+    let spent = Amount::from_sat(100_000) * tx.input.len() as u64;
+    let change = tx.output.last().unwrap();
+    assert_eq!(change.script_pubkey, script_pubkey);
+    assert_eq!(
+        change.value,
+        spent - Amount::from_sat(200_000) + Amount::from_sat(2500)
+    );
+    let paid = tx.output.iter().map(|x| x.value).sum::<Amount>();
+    assert_eq!(spent - paid, miner_fee);
+
+    // The fee covers the signed transaction
+    let signatures = collect_signatures(&sighashes, TapSighashType::Default, &validators, &secp);
+    tx.finalize_witness(&signatures, &script, &committee.internal_key, &secp);
+    assert!(miner_fee >= Amount::from_sat(5 * tx.vsize() as u64));
+
+    // Withdrawals smaller than their fees are refused, 400 sats are left after the protocol fee
+    // but not after the miner fee. So are withdrawals the UTXOs can't cover. Neither spends UTXOs.
+    let mut prover = MultisigProver {
+        available_utxos: utxos.clone(),
+    };
+    let mut peg_out = |payouts| {
+        prover.create_fee_deducted_peg_out_tx(
+            Amount::from_sat(5),
+            payouts,
+            &schedule,
+            &script,
+            &script_pubkey,
+            TapSighashType::Default,
+        )
+    };
+    assert!(matches!(
+        peg_out(vec![(Amount::from_sat(1400), receiver(0))]),
+        Err(PayoutError::Dust { .. })
+    ));
+    assert!(matches!(
+        peg_out(vec![(Amount::from_sat(400_000), receiver(0))]),
+        Err(PayoutError::InsufficientFunds {
+            needed,
+            available
+        }) if needed == Amount::from_sat(396_000) && available == Amount::from_sat(300_000)
+    ));
+    assert_eq!(prover.available_utxos, utxos);
+}
@@ -0,0 +1,249 @@
+mod common;
+
+use axelar_btc::{
+    interpreter::verify_transaction,
+    multisig_prover::{HandoverParams, MultisigProver},
+    psbt::{combine_psbts, committee_psbt, finalize_psbt, prevouts, PsbtError},
+    state::Committee,
+    SighashError, Utxo,
+};
+use bitcoin::{
+    key::Secp256k1,
+    psbt::{Psbt, PsbtSighashType},
+    secp256k1::All,
+    Amount, TapSighashType,
+};
+use common::{utxo, validators};
+
+const PARAMS: HandoverParams = HandoverParams {
+    max_output_no: 1,
+    max_tx_size: 100_000,
+    miner_fee: Amount::from_sat(1000),
+    dust_limit: Amount::from_sat(330),
+};
+
+// Handover of the UTXOs to the committee itself, in a single PSBT
+fn handover(utxos: Vec<Utxo>, committee: &Committee, secp: &Secp256k1<All>) -> Psbt {
+    let (_, script_pubkey) = committee.scripts(secp);
+    let prover = MultisigProver {
+        available_utxos: utxos,
+    };
+    prover
+        .create_handover_psbts(
+            PARAMS,
+            committee,
+            &script_pubkey,
+            TapSighashType::Default,
+            secp,
+        )
+        .unwrap()
+        .remove(0)
+}
+
+#[test]
+fn psbts_signed_apart_are_combined_and_finalized() {
+    let secp = Secp256k1::new();
+    let validators = validators();
+    let committee = Committee::from_validators(&validators, 2, &secp);
+    let (_, script_pubkey) = committee.scripts(&secp);
+    let psbt = handover(
+        (0..2).map(|i| utxo(i, 100_000, &script_pubkey)).collect(),
+        &committee,
+        &secp,
+    );
+
+    // Validators 0 and 2 sign copies of their own
+    let signed: Vec<Psbt> = [&validators[0], &validators[2]]
+        .into_iter()
+        .map(|validator| {
+            let mut psbt = psbt.clone();
+            assert_eq!(validator.sign_psbt(&mut psbt, &secp).unwrap(), 2);
+            psbt
+        })
+        .collect();
+
+    // A single signature per input is below the threshold
+    let mut below = signed[0].clone();
+    let Err(PsbtError::BelowThreshold(report)) = finalize_psbt(
+        &mut below,
+        &committee.pks_weights(),
+        committee.threshold,
+        &secp,
+    ) else {
+        panic!("Finalized below the threshold");
+    };
+    assert_eq!(report.inputs_below_threshold, [0, 1]);
+    assert_eq!(report.missing_signatures, [(0, 1), (0, 2), (1, 1), (1, 2)]);
+    assert_eq!(below, signed[0]);
+
+    let mut combined = combine_psbts(signed).unwrap();
+    assert!(combined.inputs.iter().all(|x| x.tap_script_sigs.len() == 2));
+    let report = finalize_psbt(
+        &mut combined,
+        &committee.pks_weights(),
+        committee.threshold,
+        &secp,
+    )
+    .unwrap();
+    assert_eq!(report.verified_weights, [2, 2]);
+    assert!(report.invalid_signatures.is_empty());
+
+    let prevouts = prevouts(&combined).unwrap();
+    let tx = combined.extract_tx_unchecked_fee_rate();
+    verify_transaction(&tx, &prevouts, &secp).unwrap();
+
+    // PSBTs of different transactions can't be combined
+    let other = handover(vec![utxo(2, 100_000, &script_pubkey)], &committee, &secp);
+    assert!(combine_psbts(vec![psbt, other]).is_err());
+}
+
+#[test]
+fn inputs_are_finalized_with_their_own_leaf_and_sighash_type() {
+    let secp = Secp256k1::new();
+    let validators = validators();
+    // Same members, different scripts
+    let committee = Committee::from_validators(&validators, 2, &secp);
+    let other = Committee::from_validators(&validators, 3, &secp);
+    let (_, script_pubkey) = committee.scripts(&secp);
+    let (_, other_script_pubkey) = other.scripts(&secp);
+    let mut psbt = handover(
+        vec![
+            utxo(0, 100_000, &script_pubkey),
+            utxo(1, 100_000, &other_script_pubkey),
+        ],
+        &committee,
+        &secp,
+    );
+
+    // The second input spends the other committee's script with ANYONECANPAY
+    let other_psbt = committee_psbt(
+        psbt.unsigned_tx.clone(),
+        &prevouts(&psbt).unwrap(),
+        &other,
+        TapSighashType::AllPlusAnyoneCanPay,
+        &secp,
+    );
+    psbt.inputs[1] = other_psbt.inputs[1].clone();
+
+    for validator in &validators {
+        assert_eq!(validator.sign_psbt(&mut psbt, &secp).unwrap(), 2);
+    }
+    let report = finalize_psbt(
+        &mut psbt,
+        &committee.pks_weights(),
+        committee.threshold,
+        &secp,
+    )
+    .unwrap();
+    assert_eq!(report.verified_weights, [3, 3]);
+    assert!(report.invalid_signatures.is_empty());
+
+    let prevouts = prevouts(&psbt).unwrap();
+    let tx = psbt.extract_tx_unchecked_fee_rate();
+    verify_transaction(&tx, &prevouts, &secp).unwrap();
+    // The ANYONECANPAY signatures carry their sighash type
+    assert!(tx.input[1].witness.iter().any(|x| x.len() == 65));
+    assert!(tx.input[0].witness.iter().all(|x| x.len() != 65));
+}
+
+#[test]
+fn inputs_are_finalized_with_the_leaf_their_signers_sign() {
+    let secp = Secp256k1::new();
+    let validators = validators();
+    let committee = Committee::from_validators(&validators, 2, &secp);
+    // With the same internal key, the control blocks of two single-leaf trees can be equal
+    let other = Committee {
+        internal_key: validators[0].public_key(&secp),
+        ..Committee::from_validators(&validators, 3, &secp)
+    };
+    let (_, script_pubkey) = committee.scripts(&secp);
+    let mut psbt = handover(vec![utxo(0, 100_000, &script_pubkey)], &committee, &secp);
+    let (committee_control_block, _) = psbt.inputs[0].tap_scripts.first_key_value().unwrap();
+    let committee_control_block = committee_control_block.clone();
+    for validator in &validators {
+        assert_eq!(validator.sign_psbt(&mut psbt, &secp).unwrap(), 1);
+    }
+
+    // Another leaf that nobody signs, whichever way the leaves sort
+    let other_psbt = committee_psbt(
+        psbt.unsigned_tx.clone(),
+        &prevouts(&psbt).unwrap(),
+        &other,
+        TapSighashType::Default,
+        &secp,
+    );
+    let (control_block, leaf) = other_psbt.inputs[0].tap_scripts.first_key_value().unwrap();
+    psbt.inputs[0]
+        .tap_scripts
+        .insert(control_block.clone(), leaf.clone());
+
+    finalize_psbt(
+        &mut psbt,
+        &committee.pks_weights(),
+        committee.threshold,
+        &secp,
+    )
+    .unwrap();
+    let tx = psbt.extract_tx_unchecked_fee_rate();
+    assert_eq!(
+        tx.input[0].witness.last().unwrap(),
+        committee_control_block.serialize()
+    );
+    verify_transaction(&tx, &[utxo(0, 100_000, &script_pubkey).txout], &secp).unwrap();
+}
+
+#[test]
+fn malformed_psbts_are_errors() {
+    let secp = Secp256k1::new();
+    let validators = validators();
+    let committee = Committee::from_validators(&validators, 2, &secp);
+    let (_, script_pubkey) = committee.scripts(&secp);
+    let psbt = handover(
+        (0..2).map(|i| utxo(i, 100_000, &script_pubkey)).collect(),
+        &committee,
+        &secp,
+    );
+    let finalize =
+        |psbt: &mut Psbt| finalize_psbt(psbt, &committee.pks_weights(), committee.threshold, &secp);
+
+    // Not a taproot sighash type
+    let mut malformed = psbt.clone();
+    malformed.inputs[1].sighash_type = Some(PsbtSighashType::from_u32(0x100));
+    assert!(matches!(
+        validators[0].sign_psbt(&mut malformed, &secp),
+        Err(PsbtError::InvalidSighashType(1, _))
+    ));
+    assert!(matches!(
+        finalize(&mut malformed),
+        Err(PsbtError::InvalidSighashType(1, _))
+    ));
+
+    // No witness UTXO to compute the sighashes with, and nothing gets signed
+    let mut malformed = psbt.clone();
+    malformed.inputs[1].witness_utxo = None;
+    assert!(matches!(
+        validators[0].sign_psbt(&mut malformed, &secp),
+        Err(PsbtError::MissingWitnessUtxo(1))
+    ));
+    assert!(malformed.inputs[0].tap_script_sigs.is_empty());
+    assert!(matches!(
+        finalize(&mut malformed),
+        Err(PsbtError::MissingWitnessUtxo(1))
+    ));
+
+    // A sighash type that leaves outputs out
+    let mut malformed = psbt.clone();
+    malformed.inputs[0].sighash_type = Some(TapSighashType::NonePlusAnyoneCanPay.into());
+    assert!(matches!(
+        validators[0].sign_psbt(&mut malformed, &secp),
+        Err(PsbtError::Sighash(0, SighashError::Unsignable(_)))
+    ));
+
+    // No committee leaf
+    let mut malformed = psbt;
+    malformed.inputs[0].tap_scripts.clear();
+    assert!(matches!(
+        finalize(&mut malformed),
+        Err(PsbtError::MissingCommitteeLeaf(0))
+    ));
+}
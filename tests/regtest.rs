@@ -0,0 +1,148 @@
+// Runs against a bitcoind of its own (see `RegtestNode`), with `cargo test -- --ignored`
+
+mod common;
+
+use axelar_btc::{
+    broadcaster::{BitcoindBroadcaster, Broadcaster},
+    collect_signatures,
+    deposit::RefundBuilder,
+    deposit_address::{DepositAddress, RefundLeaf},
+    finalize_verified_witness, get_private_key, init_wallet,
+    multisig_prover::{HandoverParams, MultisigProver},
+    state::Committee,
+    MultisigSpend,
+};
+use bitcoin::{key::Secp256k1, Address, Amount, FeeRate, Network, TapSighashType};
+use bitcoincore_rpc::RpcApi;
+use common::{regtest::RegtestNode, validators};
+
+#[test]
+#[ignore = "needs bitcoind"]
+fn init_wallet_reuses_the_wallet() {
+    let node = RegtestNode::start();
+    let datadir = format!("{}/", node.datadir.display());
+
+    init_wallet(&datadir, &node.rpc, Network::Regtest, "wallets/default");
+    init_wallet(&datadir, &node.rpc, Network::Regtest, "wallets/default");
+
+    assert_eq!(node.rpc.list_wallets().unwrap().len(), 1);
+}
+
+#[test]
+#[ignore = "needs bitcoind"]
+fn handover_is_accepted() {
+    let node = RegtestNode::start();
+    let secp = Secp256k1::new();
+    let wallet = node.create_wallet("user");
+    let miner = node.fund(&wallet);
+
+    let validators = validators();
+    let committee = Committee::from_validators(&validators, 2, &secp);
+    let (script, script_pubkey) = committee.scripts(&secp);
+    let address = Address::from_script(&script_pubkey, Network::Regtest).unwrap();
+
+    let prover = MultisigProver {
+        available_utxos: vec![
+            node.send_and_confirm(&wallet, &address, Amount::from_sat(100_000)),
+            node.send_and_confirm(&wallet, &address, Amount::from_sat(200_000)),
+        ],
+    };
+    let txs: Vec<_> = prover
+        .create_handover_tx(
+            HandoverParams {
+                max_output_no: 2,
+                max_tx_size: 100_000,
+                miner_fee: Amount::from_sat(1000),
+                dust_limit: Amount::from_sat(330),
+            },
+            &script,
+            &script_pubkey,
+            TapSighashType::Default,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|(mut tx, sighashes)| {
+            let signatures =
+                collect_signatures(&sighashes, TapSighashType::Default, &validators, &secp);
+            finalize_verified_witness(
+                &mut tx,
+                &sighashes,
+                TapSighashType::Default,
+                signatures,
+                MultisigSpend {
+                    script: &script,
+                    internal_key: &committee.internal_key,
+                    pks_weights: &committee.pks_weights(),
+                    threshold: committee.threshold,
+                },
+                &secp,
+            )
+            .unwrap();
+            tx
+        })
+        .collect();
+
+    let reports = BitcoindBroadcaster::regtest(&node.rpc, miner).broadcast(&txs);
+    assert_eq!(reports.len(), txs.len());
+    for report in reports {
+        assert!(report.submitted, "{report:?}");
+        assert!(node
+            .rpc
+            .get_tx_out(&report.txid, 0, Some(false))
+            .unwrap()
+            .is_some());
+    }
+}
+
+#[test]
+#[ignore = "needs bitcoind"]
+fn refund_is_accepted_after_the_delay() {
+    let node = RegtestNode::start();
+    let secp = Secp256k1::new();
+    let wallet = node.create_wallet("user");
+    let miner = node.fund(&wallet);
+
+    let committee = Committee::from_validators(&validators(), 2, &secp);
+    let key = get_private_key(100, Network::Regtest).unwrap().to_priv();
+    let refund = RefundLeaf {
+        key: key.inner.x_only_public_key(&secp).0,
+        delay: 10,
+    };
+    let deposit = DepositAddress::from_destination(
+        &committee,
+        "ethereum:0x0000000000000000000000000000000000000000",
+        &secp,
+    )
+    .unwrap()
+    .with_refund(refund, &secp);
+    let utxo = node.send_and_confirm(
+        &wallet,
+        &deposit.to_address(Network::Regtest),
+        Amount::from_sat(100_000),
+    );
+
+    let tx = RefundBuilder {
+        deposit,
+        utxo,
+        script_pubkey: node.new_address(&wallet).script_pubkey(),
+        fee_rate: FeeRate::from_sat_per_vb(2).unwrap(),
+    }
+    .build(&secp)
+    .unwrap()
+    .sign(&key, &secp)
+    .unwrap();
+
+    // The deposit has 1 confirmation, the refund can be mined once it has 10
+    let error = node.rpc.send_raw_transaction(&tx).unwrap_err();
+    assert!(error.to_string().contains("non-BIP68-final"), "{error}");
+    node.mine(refund.delay as u64 - 2, &miner);
+    assert!(node.rpc.send_raw_transaction(&tx).is_err());
+    node.mine(1, &miner);
+    let txid = node.rpc.send_raw_transaction(&tx).unwrap();
+    node.mine(1, &miner);
+    assert!(node
+        .rpc
+        .get_tx_out(&txid, 0, Some(false))
+        .unwrap()
+        .is_some());
+}
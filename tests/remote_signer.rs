@@ -0,0 +1,67 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axelar_btc::{
+    get_private_key,
+    signer::{MockSignerServer, RemoteSigner, Signer, SignerError},
+    validator::{SignError, Validator},
+};
+use bitcoin::{
+    hashes::Hash, key::Secp256k1, secp256k1::Message, Network, TapSighash, TapSighashType,
+};
+
+#[test]
+fn remote_signer_signs_like_the_in_memory_key() {
+    let secp = Secp256k1::new();
+    let key = get_private_key(0, Network::Regtest).unwrap();
+    let server = MockSignerServer::start(HashMap::from([("validator-0".to_string(), key)]));
+    let signer = RemoteSigner::new(&server.url, "validator-0");
+
+    assert_eq!(
+        signer.public_key(&secp).unwrap(),
+        key.public_key(&secp).unwrap()
+    );
+
+    let validator = Validator {
+        operator_address: "validator-0".to_string(),
+        weight: 1,
+        signer: Some(Arc::new(signer)),
+    };
+    let sighash = TapSighash::from_byte_array([7; 32]);
+    let signature = validator
+        .sign_sighash(&sighash, TapSighashType::Default, &secp)
+        .expect("The remote signer didn't sign");
+    let msg = Message::from_digest(sighash.to_byte_array());
+    secp.verify_schnorr(&signature.signature, &msg, &validator.public_key(&secp))
+        .unwrap();
+}
+
+#[test]
+fn remote_signer_reports_unknown_keys() {
+    let secp = Secp256k1::new();
+    let server = MockSignerServer::start(HashMap::new());
+    let signer = RemoteSigner::new(&server.url, "nobody");
+
+    assert!(matches!(
+        signer.public_key(&secp),
+        Err(SignerError::Remote { code: -32602, .. })
+    ));
+}
+
+#[test]
+fn unreachable_signer_doesnt_sign() {
+    let secp = Secp256k1::new();
+    let validator = Validator {
+        operator_address: "validator-0".to_string(),
+        weight: 1,
+        signer: Some(Arc::new(RemoteSigner::new(
+            "http://127.0.0.1:1",
+            "validator-0",
+        ))),
+    };
+    let sighash = TapSighash::from_byte_array([7; 32]);
+
+    assert!(matches!(
+        validator.sign_sighash(&sighash, TapSighashType::Default, &secp),
+        Err(SignError::Signer(SignerError::Transport(_)))
+    ));
+}
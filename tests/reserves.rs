@@ -0,0 +1,153 @@
+mod common;
+
+use axelar_btc::{
+    get_private_key,
+    reserves::{ReportError, ReservesReport, SignedReport, UtxoStatus},
+    state::{Committee, FinalizedTx, State, TxKind},
+    validator::Validator,
+};
+use bitcoin::{
+    absolute::LockTime, key::Secp256k1, transaction::Version, Amount, Network, ScriptBuf,
+    Transaction, TxOut,
+};
+use common::{txid, utxo, validators};
+
+fn txout(sats: u64, script_pubkey: &ScriptBuf) -> TxOut {
+    TxOut {
+        value: Amount::from_sat(sats),
+        script_pubkey: script_pubkey.clone(),
+    }
+}
+
+fn tx(output: Vec<TxOut>) -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![],
+        output,
+    }
+}
+
+// Two confirmed UTXOs, a spent one, an unbroadcast peg-in of 30k and an unbroadcast peg-out
+// of 40k with 9k of change
+fn state(committee: &Committee) -> State {
+    let secp = Secp256k1::new();
+    let (_, script_pubkey) = committee.scripts(&secp);
+    let receiver = ScriptBuf::new_op_return([1]);
+
+    let mut state = State::new(Network::Regtest);
+    state.committee = Some(committee.clone());
+    state.utxos = (0..3).map(|i| utxo(i, 100_000, &script_pubkey)).collect();
+
+    let peg_in = tx(vec![txout(30_000, &script_pubkey)]);
+    state.add_utxos(&peg_in, &script_pubkey);
+    state.finalized.push(FinalizedTx {
+        kind: TxKind::PegIn,
+        tx: peg_in,
+        prevouts: vec![],
+    });
+    let peg_out = tx(vec![txout(40_000, &receiver), txout(9_000, &script_pubkey)]);
+    state.add_utxos(&peg_out, &script_pubkey);
+    state.finalized.push(FinalizedTx {
+        kind: TxKind::PegOut,
+        tx: peg_out,
+        prevouts: vec![txout(50_000, &script_pubkey)],
+    });
+    state
+}
+
+fn report(committee: &Committee) -> ReservesReport {
+    let spent = txid(2);
+    ReservesReport::new(
+        &state(committee),
+        committee,
+        |outpoint| {
+            if outpoint.txid == spent {
+                UtxoStatus::Missing
+            } else {
+                UtxoStatus::Confirmed { confirmations: 6 }
+            }
+        },
+        1_700_000_000,
+        800_000,
+        Some(Amount::from_sat(200_000)),
+        &Secp256k1::new(),
+    )
+}
+
+#[test]
+fn reserves_are_summed_per_script() {
+    let secp = Secp256k1::new();
+    let committee = Committee::from_validators(&validators(), 2, &secp);
+    let report = report(&committee);
+
+    assert_eq!(report.scripts.len(), 1);
+    let reserves = &report.scripts[0];
+    assert_eq!(reserves.script_pubkey, committee.scripts(&secp).1);
+    let statuses: Vec<_> = reserves.utxos.iter().map(|x| x.status).collect();
+    assert_eq!(
+        statuses,
+        [
+            UtxoStatus::Confirmed { confirmations: 6 },
+            UtxoStatus::Confirmed { confirmations: 6 },
+            UtxoStatus::Missing,
+            UtxoStatus::Unbroadcast,
+            UtxoStatus::Unbroadcast,
+        ]
+    );
+    assert_eq!(reserves.confirmed, Amount::from_sat(200_000));
+    assert_eq!(reserves.unconfirmed, Amount::from_sat(39_000));
+    assert_eq!(reserves.missing, Amount::from_sat(100_000));
+    assert_eq!(reserves.pending_peg_ins, Amount::from_sat(30_000));
+    assert_eq!(reserves.unbroadcast_peg_outs, Amount::from_sat(40_000));
+    assert_eq!(report.confirmed, Amount::from_sat(200_000));
+    assert_eq!(report.unconfirmed, Amount::from_sat(39_000));
+}
+
+#[test]
+fn committee_signatures_are_checked_offline() {
+    let secp = Secp256k1::new();
+    let validators = validators();
+    let committee = Committee::from_validators(&validators, 2, &secp);
+
+    let mut signed = SignedReport::new(report(&committee));
+    signed.sign(&validators[0], &secp).unwrap();
+    assert_eq!(
+        signed.verify(&secp),
+        Err(ReportError::BelowThreshold {
+            weight: 1,
+            threshold: 2
+        })
+    );
+    signed.sign(&validators[2], &secp).unwrap();
+    assert_eq!(signed.verify(&secp), Ok(2));
+
+    // The report verifies after a round trip through JSON
+    let json = serde_json::to_string_pretty(&signed).unwrap();
+    let parsed: SignedReport = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed.report.hash(), signed.report.hash());
+    assert_eq!(parsed.verify(&secp), Ok(2));
+
+    // Outsiders can't sign
+    let outsider =
+        Validator::with_key("outsider", 1, get_private_key(3, Network::Regtest).unwrap());
+    assert_eq!(
+        signed.sign(&outsider, &secp),
+        Err(ReportError::UnknownSigner(outsider.public_key(&secp)))
+    );
+
+    let mut duplicated = signed.clone();
+    duplicated.signatures.push(signed.signatures[0].clone());
+    let public_key = signed.signatures[0].public_key;
+    assert_eq!(
+        duplicated.verify(&secp),
+        Err(ReportError::DuplicateSigner(public_key))
+    );
+
+    let mut tampered = signed.clone();
+    tampered.report.confirmed += Amount::from_sat(1);
+    assert_eq!(
+        tampered.verify(&secp),
+        Err(ReportError::InvalidSignature(public_key))
+    );
+}
@@ -0,0 +1,209 @@
+mod common;
+
+use axelar_btc::{
+    collect_signatures,
+    interpreter::{verify_input, verify_transaction},
+    multisig_prover::{HandoverParams, MultisigProver, PayoutError},
+    state::Committee,
+    taproot_sighash, taproot_sighashes,
+    validator::{is_signable_sighash_type, SignError},
+    SighashError, Utxo,
+};
+use bitcoin::{
+    absolute::LockTime, hashes::Hash, key::Secp256k1, transaction::Version, Address, Amount,
+    Network, ScriptBuf, Sequence, TapSighash, TapSighashType, Transaction, TxIn, TxOut, Witness,
+};
+use bitcoin_rs::transaction::WitnessControl;
+use common::{utxo, validators};
+
+#[test]
+fn non_default_sighash_types_verify() {
+    let secp = Secp256k1::new();
+    let validators = validators();
+    let committee = Committee::from_validators(&validators, 2, &secp);
+    let (script, script_pubkey) = committee.scripts(&secp);
+    let utxos: Vec<Utxo> = (0..2).map(|i| utxo(i, 100_000, &script_pubkey)).collect();
+    let prevouts: Vec<TxOut> = utxos.iter().map(|x| x.txout.clone()).collect();
+
+    for sighash_type in [TapSighashType::All, TapSighashType::AllPlusAnyoneCanPay] {
+        let prover = MultisigProver {
+            available_utxos: utxos.clone(),
+        };
+        let (mut tx, sighashes) = prover
+            .create_handover_tx(
+                HandoverParams {
+                    max_output_no: 1,
+                    max_tx_size: 100_000,
+                    miner_fee: Amount::from_sat(1000),
+                    dust_limit: Amount::from_sat(330),
+                },
+                &script,
+                &script_pubkey,
+                sighash_type,
+            )
+            .unwrap()
+            .remove(0);
+        let signatures = collect_signatures(&sighashes, sighash_type, &validators, &secp);
+        tx.finalize_witness(&signatures, &script, &committee.internal_key, &secp);
+        verify_transaction(&tx, &prevouts, &secp)
+            .unwrap_or_else(|error| panic!("{sighash_type} doesn't verify: {error:?}"));
+
+        // 64 bytes and the sighash type
+        let signature = tx.input[0].witness.nth(0).unwrap();
+        assert_eq!(signature.len(), 65);
+        assert_eq!(signature[64], sighash_type as u8);
+
+        // Another input, e.g. to bump the fee, only keeps the signatures valid with ANYONECANPAY
+        let mut bumped = tx.clone();
+        let extra = utxo(2, 100_000, &script_pubkey);
+        bumped.input.push(TxIn {
+            previous_output: extra.outpoint,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        });
+        let mut bumped_prevouts = prevouts.clone();
+        bumped_prevouts.push(extra.txout);
+        assert_eq!(
+            verify_input(&bumped, 0, &bumped_prevouts, &secp).is_ok(),
+            sighash_type == TapSighashType::AllPlusAnyoneCanPay
+        );
+    }
+}
+
+#[test]
+fn single_anyonecanpay_commits_to_its_own_output() {
+    let secp = Secp256k1::new();
+    let validators = validators();
+    let committee = Committee::from_validators(&validators, 2, &secp);
+    let (script, script_pubkey) = committee.scripts(&secp);
+    let utxos: Vec<Utxo> = (0..2).map(|i| utxo(i, 100_000, &script_pubkey)).collect();
+    let prevouts: Vec<TxOut> = utxos.iter().map(|x| x.txout.clone()).collect();
+    let sighash_type = TapSighashType::SinglePlusAnyoneCanPay;
+
+    // One output per input, so every output is committed to
+    let prover = MultisigProver {
+        available_utxos: utxos.clone(),
+    };
+    let (mut tx, sighashes) = prover
+        .create_handover_tx(
+            HandoverParams {
+                max_output_no: 2,
+                max_tx_size: 100_000,
+                miner_fee: Amount::from_sat(1000),
+                dust_limit: Amount::from_sat(330),
+            },
+            &script,
+            &script_pubkey,
+            sighash_type,
+        )
+        .unwrap()
+        .remove(0);
+    assert_eq!((tx.input.len(), tx.output.len()), (2, 2));
+    let signatures = collect_signatures(&sighashes, sighash_type, &validators, &secp);
+    tx.finalize_witness(&signatures, &script, &committee.internal_key, &secp);
+    verify_transaction(&tx, &prevouts, &secp).unwrap();
+
+    // Others can add inputs & outputs in pairs, but not touch the committed outputs
+    let mut extended = tx.clone();
+    let extra = utxo(2, 100_000, &script_pubkey);
+    extended.input.push(TxIn {
+        previous_output: extra.outpoint,
+        ..Default::default()
+    });
+    extended.output.push(TxOut {
+        value: Amount::from_sat(50_000),
+        script_pubkey: ScriptBuf::new_op_return([1]),
+    });
+    extended.output[1].value = Amount::from_sat(1000);
+    let mut extended_prevouts = prevouts.clone();
+    extended_prevouts.push(extra.txout);
+    verify_input(&extended, 0, &extended_prevouts, &secp).unwrap();
+    assert!(verify_input(&extended, 1, &extended_prevouts, &secp).is_err());
+
+    // Fanning two UTXOs into one output would leave the second input's signature uncommitted
+    assert_eq!(
+        prover
+            .create_handover_tx(
+                HandoverParams {
+                    max_output_no: 1,
+                    max_tx_size: 100_000,
+                    miner_fee: Amount::from_sat(1000),
+                    dust_limit: Amount::from_sat(330),
+                },
+                &script,
+                &script_pubkey,
+                sighash_type,
+            )
+            .unwrap_err(),
+        SighashError::UncommittedOutputs {
+            inputs: 2,
+            outputs: 1
+        }
+    );
+    // And so would a payout with change out of a single UTXO, which stays available
+    let mut prover = MultisigProver {
+        available_utxos: utxos,
+    };
+    let receiver = Address::p2tr(&secp, committee.internal_key, None, Network::Regtest);
+    assert!(matches!(
+        prover.create_peg_out_tx(
+            Amount::from_sat(1),
+            vec![(Amount::from_sat(10_000), receiver)],
+            &script,
+            &script_pubkey,
+            sighash_type,
+        ),
+        Err(PayoutError::Sighash(
+            SighashError::UncommittedOutputs { .. }
+        ))
+    ));
+    assert_eq!(prover.available_utxos.len(), 2);
+}
+
+#[test]
+fn sighash_types_leaving_outputs_out_are_refused() {
+    let secp = Secp256k1::new();
+    let validator = &validators()[0];
+    let sighash = TapSighash::from_byte_array([7; 32]);
+    for sighash_type in [
+        TapSighashType::Single,
+        TapSighashType::None,
+        TapSighashType::NonePlusAnyoneCanPay,
+    ] {
+        assert!(!is_signable_sighash_type(sighash_type));
+        assert_eq!(
+            validator.sign_sighash(&sighash, sighash_type, &secp),
+            Err(SignError::Unsignable(sighash_type))
+        );
+    }
+    for sighash_type in [
+        TapSighashType::Default,
+        TapSighashType::All,
+        TapSighashType::AllPlusAnyoneCanPay,
+        TapSighashType::SinglePlusAnyoneCanPay,
+    ] {
+        assert!(validator
+            .sign_sighash(&sighash, sighash_type, &secp)
+            .is_ok());
+    }
+
+    // Sighashes aren't even computed for them
+    let committee = Committee::from_validators(&validators(), 2, &secp);
+    let (script, script_pubkey) = committee.scripts(&secp);
+    let tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn::default()],
+        output: vec![],
+    };
+    let prevouts = [utxo(0, 100_000, &script_pubkey).txout];
+    assert_eq!(
+        taproot_sighashes(&tx, &prevouts, &script, TapSighashType::None),
+        Err(SighashError::Unsignable(TapSighashType::None))
+    );
+    assert!(matches!(
+        taproot_sighash(&tx, 1, &prevouts, &script, TapSighashType::Default),
+        Err(SighashError::Taproot(_))
+    ));
+}
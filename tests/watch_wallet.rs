@@ -0,0 +1,103 @@
+mod common;
+
+use axelar_btc::{
+    descriptor::checksum,
+    watch_wallet::{self, reconcile, WatchedUtxo},
+    Utxo,
+};
+use bitcoin::{
+    absolute::LockTime, key::Secp256k1, transaction::Version, Address, Amount, Network, OutPoint,
+    ScriptBuf, Sequence, Transaction, TxIn, Witness,
+};
+use common::{committee, regtest::RegtestNode, utxo};
+
+fn committee_script_pubkey() -> ScriptBuf {
+    let secp = Secp256k1::new();
+    committee(&secp).scripts(&secp).1
+}
+
+fn watched(utxo: &Utxo, confirmations: u32) -> WatchedUtxo {
+    WatchedUtxo {
+        utxo: utxo.clone(),
+        confirmations,
+    }
+}
+
+#[test]
+fn raw_descriptors_have_checksums() {
+    let script_pubkey = committee_script_pubkey();
+    let descriptor = watch_wallet::raw_descriptor(&script_pubkey);
+    let (raw, found) = descriptor.split_once('#').unwrap();
+    assert_eq!(raw, format!("raw({})", script_pubkey.to_hex_string()));
+    assert_eq!(checksum(raw).unwrap(), found);
+}
+
+#[test]
+fn bitcoind_has_the_last_word_on_utxos() {
+    let script_pubkey = committee_script_pubkey();
+    let utxos: Vec<Utxo> = (0..6).map(|i| utxo(i, 100_000, &script_pubkey)).collect();
+    // Spends UTXO 4 and pays UTXO 5's script, but isn't broadcast yet
+    let peg_out = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: utxos[4].outpoint,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![utxos[5].txout.clone()],
+    };
+    let change = Utxo {
+        outpoint: OutPoint {
+            txid: peg_out.compute_txid(),
+            vout: 0,
+        },
+        txout: peg_out.output[0].clone(),
+    };
+
+    // Known: 0 (listed), 1 (spent elsewhere), 2 (in the mempool, not listed yet) and the change
+    // of the peg-out. Listed: 0, 3 (a confirmed deposit), 4 (spent by the peg-out) and 5 (a
+    // deposit without enough confirmations).
+    let known = vec![
+        utxos[0].clone(),
+        utxos[1].clone(),
+        utxos[2].clone(),
+        change.clone(),
+    ];
+    let unspent = vec![
+        watched(&utxos[0], 3),
+        watched(&utxos[3], 1),
+        watched(&utxos[4], 10),
+        watched(&utxos[5], 0),
+    ];
+    let reconciliation = reconcile(&known, &unspent, &[&peg_out], 1, |outpoint| {
+        *outpoint == utxos[2].outpoint
+    });
+
+    assert_eq!(
+        reconciliation.utxos,
+        [utxos[0].clone(), utxos[2].clone(), change, utxos[3].clone()]
+    );
+    assert_eq!(reconciliation.discovered, [utxos[3].clone()]);
+    assert_eq!(reconciliation.vanished, [utxos[1].clone()]);
+}
+
+#[test]
+#[ignore = "needs bitcoind"]
+fn watch_only_wallet_lists_committee_utxos() {
+    let node = RegtestNode::start();
+    let user = node.create_wallet("user");
+    node.fund(&user);
+
+    let script_pubkey = committee_script_pubkey();
+    watch_wallet::load_or_create(&node.rpc, "watch").unwrap();
+    watch_wallet::load_or_create(&node.rpc, "watch").unwrap();
+    let watch = node.wallet("watch");
+    watch_wallet::import_scripts(&watch, std::slice::from_ref(&script_pubkey), None).unwrap();
+
+    let address = Address::from_script(&script_pubkey, Network::Regtest).unwrap();
+    let deposit = node.send_and_confirm(&user, &address, Amount::from_sat(100_000));
+    let unspent = watch_wallet::list_unspent(&watch, &[script_pubkey]).unwrap();
+    assert_eq!(unspent, [watched(&deposit, 1)]);
+}